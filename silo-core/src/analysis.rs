@@ -0,0 +1,12 @@
+mod comparison;
+mod contingency;
+mod cooccurrence;
+pub mod correction;
+pub mod probability;
+
+pub use comparison::{
+    mann_whitney, variance, welch_t_test, AttributeComparison, AttributeSummary, AttributeTest,
+    ComparisonOptions, GroupComparison, Sample, TraitComparison,
+};
+pub use contingency::{AssociationTest, ContingencyTable, TestResult};
+pub use cooccurrence::{Cooccurrence, TraitPair};
@@ -0,0 +1,454 @@
+use super::correction::{benjamini_hochberg, bonferroni};
+use super::probability::{normal_two_sided, student_t_two_sided};
+use super::{AssociationTest, ContingencyTable};
+use crate::models::{Subject, SubjectTrait, SubjectTraitLink};
+use crate::stats::{mean, quantile, trait_holders};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Specifies the test comparing an attribute between two groups.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AttributeTest {
+    /// Welch's unequal variances t-test.
+    TTest,
+    /// The Mann-Whitney U test, with a tie-corrected normal approximation.
+    MannWhitney,
+}
+
+impl std::str::FromStr for AttributeTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "t_test" => Ok(AttributeTest::TTest),
+            "mann_whitney" => Ok(AttributeTest::MannWhitney),
+            test => Err(format!("unknown attribute test {}", test)),
+        }
+    }
+}
+
+/// Options for comparing two groups.
+#[derive(Debug, Clone)]
+pub struct ComparisonOptions {
+    /// The test of association between each trait and group membership.
+    pub trait_test: AssociationTest,
+    /// The test comparing each attribute between the groups.
+    pub attribute_test: AttributeTest,
+}
+
+impl Default for ComparisonOptions {
+    fn default() -> Self {
+        Self {
+            trait_test: AssociationTest::Auto,
+            attribute_test: AttributeTest::TTest,
+        }
+    }
+}
+
+/// The subjects of a group and their trait links.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample<'a> {
+    /// The ID of the group.
+    pub group_id: i32,
+    /// The subjects of the group.
+    pub subjects: &'a [Subject],
+    /// The trait links of the subjects.
+    pub links: &'a [SubjectTraitLink],
+}
+
+impl<'a> Sample<'a> {
+    /// Returns the IDs of the subjects with each trait, keyed by trait ID.
+    fn holders(&self) -> HashMap<i32, HashSet<i32>> {
+        trait_holders(self.subjects, self.links)
+    }
+}
+
+/// The comparison of a single trait between a case and a control group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TraitComparison {
+    /// The ID of the trait.
+    pub trait_id: i32,
+    /// The name of the trait.
+    pub trait_name: String,
+    /// The number of case subjects with the trait.
+    pub case_count: usize,
+    /// The number of control subjects with the trait.
+    pub control_count: usize,
+    /// The proportion of case subjects with the trait.
+    pub case_prevalence: Option<f64>,
+    /// The proportion of control subjects with the trait.
+    pub control_prevalence: Option<f64>,
+    /// The case prevalence minus the control prevalence.
+    pub difference: Option<f64>,
+    /// The odds ratio of the trait in the case group relative to the control group.
+    pub odds_ratio: f64,
+    /// The lower bound of the 95% confidence interval of the odds ratio.
+    pub odds_ratio_lower: f64,
+    /// The upper bound of the 95% confidence interval of the odds ratio.
+    pub odds_ratio_upper: f64,
+    /// The test of association that was run.
+    pub test: AssociationTest,
+    /// The test statistic, if the test has one.
+    pub statistic: Option<f64>,
+    /// The p-value of the test, if it is defined.
+    pub p_value: Option<f64>,
+    /// The p-value adjusted with the Bonferroni correction.
+    pub p_bonferroni: Option<f64>,
+    /// The p-value adjusted with the Benjamini-Hochberg procedure.
+    pub p_benjamini_hochberg: Option<f64>,
+}
+
+/// Summarises an attribute within a single group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeSummary {
+    /// The number of values.
+    pub count: usize,
+    /// The mean of the values.
+    pub mean: Option<f64>,
+    /// The sample standard deviation of the values.
+    pub standard_deviation: Option<f64>,
+    /// The median of the values.
+    pub median: Option<f64>,
+}
+
+impl AttributeSummary {
+    /// Creates and returns an AttributeSummary of sorted values.
+    fn new(sorted: &[f64]) -> Self {
+        Self {
+            count: sorted.len(),
+            mean: mean(sorted),
+            standard_deviation: variance(sorted).map(f64::sqrt),
+            median: quantile(sorted, 0.5),
+        }
+    }
+}
+
+/// The comparison of a single attribute between a case and a control group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeComparison {
+    /// The name of the attribute.
+    pub name: String,
+    /// The attribute in the case group.
+    pub case: AttributeSummary,
+    /// The attribute in the control group.
+    pub control: AttributeSummary,
+    /// The case mean minus the control mean.
+    pub difference: Option<f64>,
+    /// The test that was run.
+    pub test: AttributeTest,
+    /// The test statistic: t for the t-test, and U of the case group for the
+    /// Mann-Whitney test.
+    pub statistic: Option<f64>,
+    /// The two-sided p-value of the test, if it is defined.
+    pub p_value: Option<f64>,
+    /// The p-value adjusted with the Bonferroni correction.
+    pub p_bonferroni: Option<f64>,
+    /// The p-value adjusted with the Benjamini-Hochberg procedure.
+    pub p_benjamini_hochberg: Option<f64>,
+}
+
+/// The comparison of a case group and a control group, trait by trait and
+/// attribute by attribute. P-values are corrected across every test of the
+/// comparison.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupComparison {
+    /// The ID of the case group.
+    pub case_group_id: i32,
+    /// The ID of the control group.
+    pub control_group_id: i32,
+    /// The number of subjects in the case group.
+    pub case_subject_count: usize,
+    /// The number of subjects in the control group.
+    pub control_subject_count: usize,
+    /// The comparison of each trait.
+    pub traits: Vec<TraitComparison>,
+    /// The comparison of each attribute.
+    pub attributes: Vec<AttributeComparison>,
+}
+
+impl GroupComparison {
+    /// Compares a case group to a control group over the given traits and the
+    /// attributes of their subjects.
+    pub fn new(
+        case: Sample,
+        control: Sample,
+        traits: &[SubjectTrait],
+        options: &ComparisonOptions,
+    ) -> Self {
+        let (case_holders, control_holders) = (case.holders(), control.holders());
+        let (n_case, n_control) = (case.subjects.len(), control.subjects.len());
+        let proportion = |count: usize, n: usize| match n {
+            0 => None,
+            n => Some(count as f64 / n as f64),
+        };
+
+        let mut trait_comparisons: Vec<TraitComparison> = traits
+            .iter()
+            .map(|t| {
+                let case_count = case_holders.get(&t.id).map_or(0, |h| h.len());
+                let control_count = control_holders.get(&t.id).map_or(0, |h| h.len());
+
+                let table = ContingencyTable::new(
+                    case_count,
+                    n_case - case_count,
+                    control_count,
+                    n_control - control_count,
+                );
+                let (odds_ratio_lower, odds_ratio_upper) = table.odds_ratio_interval();
+                let result = table.test(options.trait_test);
+
+                let case_prevalence = proportion(case_count, n_case);
+                let control_prevalence = proportion(control_count, n_control);
+
+                TraitComparison {
+                    trait_id: t.id,
+                    trait_name: t.trait_name.clone(),
+                    case_count,
+                    control_count,
+                    case_prevalence,
+                    control_prevalence,
+                    difference: case_prevalence.and_then(|c| control_prevalence.map(|d| c - d)),
+                    odds_ratio: table.odds_ratio(),
+                    odds_ratio_lower,
+                    odds_ratio_upper,
+                    test: result.test,
+                    statistic: result.statistic,
+                    p_value: result.p_value,
+                    p_bonferroni: None,
+                    p_benjamini_hochberg: None,
+                }
+            })
+            .collect();
+
+        let mut attribute_comparisons = vec![
+            compare_attribute(
+                "age",
+                &attribute_values(case.subjects, |s| s.age),
+                &attribute_values(control.subjects, |s| s.age),
+                options.attribute_test,
+            ),
+            compare_attribute(
+                "length_of_stay",
+                &attribute_values(case.subjects, |s| s.length_of_stay),
+                &attribute_values(control.subjects, |s| s.length_of_stay),
+                options.attribute_test,
+            ),
+        ];
+
+        // Correct every p-value of the comparison as a single family.
+        let p_values: Vec<Option<f64>> = trait_comparisons
+            .iter()
+            .map(|t| t.p_value)
+            .chain(attribute_comparisons.iter().map(|a| a.p_value))
+            .collect();
+        let mut corrected = bonferroni(&p_values)
+            .into_iter()
+            .zip(benjamini_hochberg(&p_values));
+
+        for t in trait_comparisons.iter_mut() {
+            let (p_bonferroni, p_benjamini_hochberg) = corrected.next().unwrap();
+            t.p_bonferroni = p_bonferroni;
+            t.p_benjamini_hochberg = p_benjamini_hochberg;
+        }
+        for a in attribute_comparisons.iter_mut() {
+            let (p_bonferroni, p_benjamini_hochberg) = corrected.next().unwrap();
+            a.p_bonferroni = p_bonferroni;
+            a.p_benjamini_hochberg = p_benjamini_hochberg;
+        }
+
+        Self {
+            case_group_id: case.group_id,
+            control_group_id: control.group_id,
+            case_subject_count: n_case,
+            control_subject_count: n_control,
+            traits: trait_comparisons,
+            attributes: attribute_comparisons,
+        }
+    }
+}
+
+/// Returns the sorted values of an attribute of subjects.
+fn attribute_values(subjects: &[Subject], attribute: fn(&Subject) -> i16) -> Vec<f64> {
+    let mut values: Vec<f64> = subjects.iter().map(|s| attribute(s).into()).collect();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    values
+}
+
+/// Compares the sorted values of an attribute in a case and a control group.
+fn compare_attribute(
+    name: &str,
+    case: &[f64],
+    control: &[f64],
+    test: AttributeTest,
+) -> AttributeComparison {
+    let (statistic, p_value) = match test {
+        AttributeTest::TTest => welch_t_test(case, control),
+        AttributeTest::MannWhitney => mann_whitney(case, control),
+    }
+    .map_or((None, None), |(statistic, p)| (Some(statistic), Some(p)));
+
+    let (case, control) = (AttributeSummary::new(case), AttributeSummary::new(control));
+
+    AttributeComparison {
+        name: name.into(),
+        difference: case.mean.and_then(|c| control.mean.map(|d| c - d)),
+        case,
+        control,
+        test,
+        statistic,
+        p_value,
+        p_bonferroni: None,
+        p_benjamini_hochberg: None,
+    }
+}
+
+/// Returns the sample variance of values.
+pub fn variance(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+
+    let m = mean(values)?;
+    Some(values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64)
+}
+
+/// Runs Welch's t-test on two samples, returning t and the two-sided p-value.
+/// Undefined when either sample has fewer than two values or both have no
+/// variance.
+pub fn welch_t_test(first: &[f64], second: &[f64]) -> Option<(f64, f64)> {
+    let (n1, n2) = (first.len() as f64, second.len() as f64);
+    let (v1, v2) = (variance(first)? / n1, variance(second)? / n2);
+    if v1 + v2 <= 0.0 {
+        return None;
+    }
+
+    let t = (mean(first)? - mean(second)?) / (v1 + v2).sqrt();
+    let df = (v1 + v2).powi(2) / (v1.powi(2) / (n1 - 1.0) + v2.powi(2) / (n2 - 1.0));
+
+    Some((t, student_t_two_sided(t, df)))
+}
+
+/// Runs the Mann-Whitney U test on two samples, returning U of the first sample
+/// and the two-sided p-value of the tie-corrected normal approximation with
+/// continuity correction. Undefined when either sample is empty or every value
+/// is tied.
+pub fn mann_whitney(first: &[f64], second: &[f64]) -> Option<(f64, f64)> {
+    let (n1, n2) = (first.len(), second.len());
+    if n1 == 0 || n2 == 0 {
+        return None;
+    }
+
+    let mut pooled: Vec<(f64, bool)> = first
+        .iter()
+        .map(|v| (*v, true))
+        .chain(second.iter().map(|v| (*v, false)))
+        .collect();
+    pooled.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    // Rank the pooled values, giving ties their average rank.
+    let n = pooled.len();
+    let mut rank_sum = 0.0;
+    let mut ties = 0.0;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && pooled[j + 1].0 == pooled[i].0 {
+            j += 1;
+        }
+
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let tied = (j - i + 1) as f64;
+        rank_sum += rank * pooled[i..=j].iter().filter(|(_, first)| *first).count() as f64;
+        ties += tied.powi(3) - tied;
+        i = j + 1;
+    }
+
+    let (n1, n2, n) = (n1 as f64, n2 as f64, n as f64);
+    let u = rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return None;
+    }
+
+    let z = ((u - n1 * n2 / 2.0).abs() - 0.5).max(0.0) / variance.sqrt();
+    Some((u, normal_two_sided(z)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_tests() {
+        let first = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let second = [4.0, 5.0, 6.0, 7.0, 8.0];
+
+        let (t, p) = welch_t_test(&first, &second).unwrap();
+        assert!((t + 3.0).abs() < 1e-9);
+        assert!((p - 0.017_071).abs() < 1e-5);
+
+        let (u, p) = mann_whitney(&first, &second).unwrap();
+        assert_eq!(u, 2.0);
+        assert!((p - 0.035_579).abs() < 1e-6);
+    }
+
+    #[test]
+    fn compare_groups() {
+        let subject = |id, group_id, age| Subject {
+            id,
+            group_id,
+            age,
+            length_of_stay: 3,
+            admitted_at: None,
+        };
+        let case_subjects = vec![subject(1, 1, 60), subject(2, 1, 70)];
+        let control_subjects = vec![subject(3, 2, 30), subject(4, 2, 40)];
+        let link = |subject_id| SubjectTraitLink {
+            id: 0,
+            subject_id,
+            subject_trait_id: 10,
+            onset_at: None,
+            resolved_at: None,
+            value: None,
+            qualifier: None,
+        };
+        let links = vec![link(1), link(2), link(3)];
+        let traits = vec![SubjectTrait {
+            id: 10,
+            parent_id: 0,
+            trait_name: "asthma".into(),
+        }];
+
+        let comparison = GroupComparison::new(
+            Sample {
+                group_id: 1,
+                subjects: &case_subjects,
+                links: &links,
+            },
+            Sample {
+                group_id: 2,
+                subjects: &control_subjects,
+                links: &links,
+            },
+            &traits,
+            &ComparisonOptions::default(),
+        );
+
+        let asthma = &comparison.traits[0];
+        assert_eq!((asthma.case_count, asthma.control_count), (2, 1));
+        assert_eq!(asthma.difference, Some(0.5));
+        assert_eq!(asthma.test, AssociationTest::Fisher);
+
+        let age = &comparison.attributes[0];
+        assert_eq!(age.difference, Some(30.0));
+        // length_of_stay has no variance, so only two p-values are corrected.
+        assert_eq!(comparison.attributes[1].p_value, None);
+        assert_eq!(age.p_bonferroni, age.p_value.map(|p| (p * 2.0).min(1.0)));
+    }
+}
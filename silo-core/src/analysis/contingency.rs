@@ -0,0 +1,231 @@
+use super::probability::{chi_square_sf, ln_choose};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The z-score of a two-sided 95% confidence interval.
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// Specifies the test of association between the two variables of a table.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AssociationTest {
+    /// Uses Fisher's exact test when any expected count is below 5, and Pearson's
+    /// chi-square test otherwise.
+    Auto,
+    /// Pearson's chi-square test without continuity correction.
+    ChiSquare,
+    /// Fisher's exact test, two-sided.
+    Fisher,
+}
+
+impl std::str::FromStr for AssociationTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(AssociationTest::Auto),
+            "chi_square" => Ok(AssociationTest::ChiSquare),
+            "fisher" => Ok(AssociationTest::Fisher),
+            test => Err(format!("unknown association test {}", test)),
+        }
+    }
+}
+
+/// The outcome of a test of association.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResult {
+    /// The test that was run. Never `Auto`.
+    pub test: AssociationTest,
+    /// The test statistic, if the test has one.
+    pub statistic: Option<f64>,
+    /// The p-value of the test, if it is defined for the table.
+    pub p_value: Option<f64>,
+}
+
+/// A 2x2 contingency table of two binary variables, laid out as:
+///
+/// |            | second | not second |
+/// |------------|--------|------------|
+/// | first      | a      | b          |
+/// | not first  | c      | d          |
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct ContingencyTable {
+    /// The count with both variables present.
+    pub a: usize,
+    /// The count with only the first variable present.
+    pub b: usize,
+    /// The count with only the second variable present.
+    pub c: usize,
+    /// The count with neither variable present.
+    pub d: usize,
+}
+
+impl ContingencyTable {
+    /// Creates and returns a ContingencyTable from its four cells.
+    pub fn new(a: usize, b: usize, c: usize, d: usize) -> Self {
+        Self { a, b, c, d }
+    }
+
+    /// Returns the total count of the table.
+    pub fn total(&self) -> usize {
+        self.a + self.b + self.c + self.d
+    }
+
+    /// Returns the expected counts of the cells under independence, in the order
+    /// a, b, c, d.
+    pub fn expected(&self) -> [f64; 4] {
+        let n = self.total() as f64;
+        let (first, not_first) = ((self.a + self.b) as f64, (self.c + self.d) as f64);
+        let (second, not_second) = ((self.a + self.c) as f64, (self.b + self.d) as f64);
+
+        [
+            first * second / n,
+            first * not_second / n,
+            not_first * second / n,
+            not_first * not_second / n,
+        ]
+    }
+
+    /// Returns the cells as floats, adding 0.5 to every cell when any is zero
+    /// (the Haldane-Anscombe correction).
+    fn corrected(&self) -> [f64; 4] {
+        let cells = [self.a, self.b, self.c, self.d];
+        let correction = match cells.contains(&0) {
+            true => 0.5,
+            false => 0.0,
+        };
+
+        [
+            cells[0] as f64 + correction,
+            cells[1] as f64 + correction,
+            cells[2] as f64 + correction,
+            cells[3] as f64 + correction,
+        ]
+    }
+
+    /// Returns the odds ratio of the table. Tables with an empty cell are
+    /// corrected so the ratio is always finite.
+    pub fn odds_ratio(&self) -> f64 {
+        let [a, b, c, d] = self.corrected();
+        (a * d) / (b * c)
+    }
+
+    /// Returns Woolf's 95% confidence interval of the odds ratio as
+    /// `(lower, upper)`.
+    pub fn odds_ratio_interval(&self) -> (f64, f64) {
+        let [a, b, c, d] = self.corrected();
+        let ln_ratio = self.odds_ratio().ln();
+        let margin = Z_95 * (1.0 / a + 1.0 / b + 1.0 / c + 1.0 / d).sqrt();
+
+        ((ln_ratio - margin).exp(), (ln_ratio + margin).exp())
+    }
+
+    /// Returns the lift of the table: how much more often both variables are
+    /// present than expected under independence. Undefined when either variable
+    /// is never present.
+    pub fn lift(&self) -> Option<f64> {
+        let first = self.a + self.b;
+        let second = self.a + self.c;
+
+        match first * second {
+            0 => None,
+            product => Some((self.a * self.total()) as f64 / product as f64),
+        }
+    }
+
+    /// Returns Pearson's chi-square statistic of the table, without continuity
+    /// correction. Undefined when any row or column of the table is empty.
+    pub fn chi_square(&self) -> Option<f64> {
+        let expected = self.expected();
+        if expected.iter().any(|e| e.is_nan() || *e <= 0.0) {
+            return None;
+        }
+
+        let observed = [self.a, self.b, self.c, self.d];
+        Some(
+            observed
+                .iter()
+                .zip(expected.iter())
+                .map(|(o, e)| (*o as f64 - e).powi(2) / e)
+                .sum(),
+        )
+    }
+
+    /// Returns the two-sided p-value of Fisher's exact test, summing the
+    /// probability of every table with the same margins that is no more likely
+    /// than this one.
+    pub fn fisher_exact(&self) -> f64 {
+        let n = self.total();
+        let first = self.a + self.b;
+        let second = self.a + self.c;
+
+        let ln_denominator = ln_choose(n, first);
+        let probability = |a: usize| {
+            (ln_choose(second, a) + ln_choose(n - second, first - a) - ln_denominator).exp()
+        };
+
+        let observed = probability(self.a);
+        let low = (first + second).saturating_sub(n);
+        let high = first.min(second);
+
+        let p: f64 = (low..=high)
+            .map(probability)
+            .filter(|p| *p <= observed * (1.0 + 1e-7))
+            .sum();
+
+        p.min(1.0)
+    }
+
+    /// Runs a test of association on the table.
+    pub fn test(&self, test: AssociationTest) -> TestResult {
+        let test = match test {
+            AssociationTest::Auto if self.expected().iter().any(|e| *e < 5.0) => {
+                AssociationTest::Fisher
+            }
+            AssociationTest::Auto => AssociationTest::ChiSquare,
+            test => test,
+        };
+
+        match test {
+            AssociationTest::Fisher => TestResult {
+                test,
+                statistic: None,
+                p_value: Some(self.fisher_exact()),
+            },
+            _ => {
+                let statistic = self.chi_square();
+                TestResult {
+                    test: AssociationTest::ChiSquare,
+                    statistic,
+                    p_value: statistic.map(chi_square_sf),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_statistics() {
+        // Fisher's tea tasting experiment.
+        let tea = ContingencyTable::new(3, 1, 1, 3);
+        assert_eq!(tea.odds_ratio(), 9.0);
+        assert!((tea.fisher_exact() - 0.485_714).abs() < 1e-6);
+        assert_eq!(
+            tea.test(AssociationTest::Auto).test,
+            AssociationTest::Fisher
+        );
+
+        let table = ContingencyTable::new(20, 30, 10, 40);
+        assert!((table.chi_square().unwrap() - 4.761_905).abs() < 1e-6);
+        assert!((table.lift().unwrap() - 4.0 / 3.0).abs() < 1e-9);
+
+        let (lower, upper) = table.odds_ratio_interval();
+        assert!(lower < table.odds_ratio() && table.odds_ratio() < upper);
+        assert!((lower - 1.0901).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,232 @@
+use super::{AssociationTest, ContingencyTable};
+use crate::models::{Subject, SubjectTrait, SubjectTraitLink};
+use crate::stats::trait_holders;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// The association between a pair of traits in a group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TraitPair {
+    /// The name of the first trait.
+    pub first: String,
+    /// The name of the second trait.
+    pub second: String,
+    /// The contingency table of the subjects with and without each trait.
+    pub table: ContingencyTable,
+    /// The odds ratio of the pair.
+    pub odds_ratio: f64,
+    /// The lower bound of the 95% confidence interval of the odds ratio.
+    pub odds_ratio_lower: f64,
+    /// The upper bound of the 95% confidence interval of the odds ratio.
+    pub odds_ratio_upper: f64,
+    /// The test of association that was run.
+    pub test: AssociationTest,
+    /// The test statistic, if the test has one.
+    pub statistic: Option<f64>,
+    /// The p-value of the test, if it is defined for the pair.
+    pub p_value: Option<f64>,
+    /// The lift of the pair, if both traits are present in the group.
+    pub lift: Option<f64>,
+}
+
+impl TraitPair {
+    /// Creates and returns a TraitPair from the contingency table of two traits.
+    fn new(first: &str, second: &str, table: ContingencyTable, test: AssociationTest) -> Self {
+        let (odds_ratio_lower, odds_ratio_upper) = table.odds_ratio_interval();
+        let result = table.test(test);
+
+        Self {
+            first: first.into(),
+            second: second.into(),
+            table,
+            odds_ratio: table.odds_ratio(),
+            odds_ratio_lower,
+            odds_ratio_upper,
+            test: result.test,
+            statistic: result.statistic,
+            p_value: result.p_value,
+            lift: table.lift(),
+        }
+    }
+}
+
+/// The co-occurrence of a set of traits among the subjects of a group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Cooccurrence {
+    /// The ID of the group.
+    pub group_id: i32,
+    /// The number of subjects in the group.
+    pub subject_count: usize,
+    /// The names of the analysed traits, in the order of the matrix.
+    pub traits: Vec<String>,
+    /// The number of subjects with both the trait of the row and the trait of the
+    /// column. The diagonal holds the number of subjects with each trait.
+    pub counts: Vec<Vec<usize>>,
+    /// The association of every pair of distinct traits.
+    pub pairs: Vec<TraitPair>,
+}
+
+impl Cooccurrence {
+    /// Computes the co-occurrence of traits among the subjects of a group from the
+    /// trait links of those subjects.
+    pub fn new(
+        group_id: i32,
+        subjects: &[Subject],
+        links: &[SubjectTraitLink],
+        traits: &[SubjectTrait],
+        test: AssociationTest,
+    ) -> Self {
+        let holders = trait_holders(subjects, links);
+
+        let empty = HashSet::new();
+        let holders: Vec<&HashSet<i32>> = traits
+            .iter()
+            .map(|t| holders.get(&t.id).unwrap_or(&empty))
+            .collect();
+
+        let counts: Vec<Vec<usize>> = holders
+            .iter()
+            .map(|row| {
+                holders
+                    .iter()
+                    .map(|column| row.intersection(column).count())
+                    .collect()
+            })
+            .collect();
+
+        let n = subjects.len();
+        let mut pairs = vec![];
+        for i in 0..traits.len() {
+            for j in (i + 1)..traits.len() {
+                let both = counts[i][j];
+                let first_only = counts[i][i] - both;
+                let second_only = counts[j][j] - both;
+                let table = ContingencyTable::new(
+                    both,
+                    first_only,
+                    second_only,
+                    n - both - first_only - second_only,
+                );
+
+                pairs.push(TraitPair::new(
+                    &traits[i].trait_name,
+                    &traits[j].trait_name,
+                    table,
+                    test,
+                ));
+            }
+        }
+
+        Self {
+            group_id,
+            subject_count: n,
+            traits: traits.iter().map(|t| t.trait_name.clone()).collect(),
+            counts,
+            pairs,
+        }
+    }
+
+    /// Returns the co-occurrence matrix as a TSV with a header row and a column of
+    /// trait names.
+    pub fn counts_tsv(&self) -> String {
+        let mut builder = format!("trait\t{}\n", self.traits.join("\t"));
+
+        for (name, row) in self.traits.iter().zip(self.counts.iter()) {
+            let cells: Vec<String> = row.iter().map(|count| count.to_string()).collect();
+            builder.push_str(&format!("{}\t{}\n", name, cells.join("\t")));
+        }
+
+        builder
+    }
+
+    /// Returns the association of every pair as a TSV with a header row.
+    pub fn pairs_tsv(&self) -> String {
+        let number = |n: Option<f64>| n.map(|n| n.to_string()).unwrap_or_default();
+
+        let mut builder = String::from(
+            "first\tsecond\ta\tb\tc\td\todds_ratio\todds_ratio_lower\todds_ratio_upper\t\
+             test\tstatistic\tp_value\tlift\n",
+        );
+
+        for pair in &self.pairs {
+            let test = match pair.test {
+                AssociationTest::Fisher => "fisher",
+                _ => "chi_square",
+            };
+            let cells = vec![
+                pair.first.clone(),
+                pair.second.clone(),
+                pair.table.a.to_string(),
+                pair.table.b.to_string(),
+                pair.table.c.to_string(),
+                pair.table.d.to_string(),
+                pair.odds_ratio.to_string(),
+                pair.odds_ratio_lower.to_string(),
+                pair.odds_ratio_upper.to_string(),
+                test.into(),
+                number(pair.statistic),
+                number(pair.p_value),
+                number(pair.lift),
+            ];
+
+            builder.push_str(&format!("{}\n", cells.join("\t")));
+        }
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trait_cooccurrence() {
+        let subjects: Vec<Subject> = (1..=4)
+            .map(|id| Subject {
+                id,
+                group_id: 1,
+                age: 40,
+                length_of_stay: 3,
+                admitted_at: None,
+            })
+            .collect();
+        let traits = vec![
+            SubjectTrait {
+                id: 10,
+                parent_id: 0,
+                trait_name: "asthma".into(),
+            },
+            SubjectTrait {
+                id: 11,
+                parent_id: 0,
+                trait_name: "copd".into(),
+            },
+        ];
+        let link = |subject_id, subject_trait_id| SubjectTraitLink {
+            id: 0,
+            subject_id,
+            subject_trait_id,
+            onset_at: None,
+            resolved_at: None,
+            value: None,
+            qualifier: None,
+        };
+        let links = vec![link(1, 10), link(1, 11), link(2, 10), link(3, 11)];
+
+        let cooccurrence = Cooccurrence::new(1, &subjects, &links, &traits, AssociationTest::Auto);
+
+        assert_eq!(cooccurrence.counts, vec![vec![2, 1], vec![1, 2]]);
+        assert_eq!(
+            cooccurrence.pairs[0].table,
+            ContingencyTable::new(1, 1, 1, 1)
+        );
+        assert_eq!(cooccurrence.pairs[0].lift, Some(1.0));
+        assert!(cooccurrence
+            .counts_tsv()
+            .starts_with("trait\tasthma\tcopd\nasthma\t2\t1\n"));
+    }
+}
@@ -0,0 +1,60 @@
+//! Corrections of p-values for multiple testing.
+
+/// Adjusts p-values with the Bonferroni correction, multiplying each by the number
+/// of defined p-values. Undefined p-values are left undefined.
+pub fn bonferroni(p_values: &[Option<f64>]) -> Vec<Option<f64>> {
+    let m = p_values.iter().filter(|p| p.is_some()).count() as f64;
+
+    p_values
+        .iter()
+        .map(|p| p.map(|p| (p * m).min(1.0)))
+        .collect()
+}
+
+/// Adjusts p-values with the Benjamini-Hochberg procedure, controlling the false
+/// discovery rate. Undefined p-values are left undefined.
+pub fn benjamini_hochberg(p_values: &[Option<f64>]) -> Vec<Option<f64>> {
+    let mut ranked: Vec<(usize, f64)> = p_values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.map(|p| (i, p)))
+        .collect();
+    ranked.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    let m = ranked.len() as f64;
+    let mut adjusted = vec![None; p_values.len()];
+    let mut smallest = 1.0f64;
+
+    // Walk from the largest p-value down, keeping the adjusted values monotonic.
+    for (rank, (i, p)) in ranked.iter().enumerate().rev() {
+        smallest = smallest.min(p * m / (rank + 1) as f64);
+        adjusted[*i] = Some(smallest);
+    }
+
+    adjusted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrections() {
+        let p_values = vec![Some(0.01), None, Some(0.04), Some(0.03)];
+        let rounded = |adjusted: Vec<Option<f64>>| -> Vec<Option<f64>> {
+            adjusted
+                .into_iter()
+                .map(|p| p.map(|p| (p * 1e6).round() / 1e6))
+                .collect()
+        };
+
+        assert_eq!(
+            rounded(bonferroni(&p_values)),
+            vec![Some(0.03), None, Some(0.12), Some(0.09)]
+        );
+        assert_eq!(
+            rounded(benjamini_hochberg(&p_values)),
+            vec![Some(0.03), None, Some(0.04), Some(0.04)]
+        );
+    }
+}
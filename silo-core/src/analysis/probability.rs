@@ -0,0 +1,157 @@
+//! Probability distributions used by the statistical tests of the analysis module.
+
+/// The coefficients of the Lanczos approximation with g = 7 and n = 9.
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// Returns the natural logarithm of the gamma function of `x`, for `x > 0`.
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // Reflection formula.
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = LANCZOS[1..]
+        .iter()
+        .enumerate()
+        .fold(LANCZOS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Returns the natural logarithm of `n!`.
+pub fn ln_factorial(n: usize) -> f64 {
+    ln_gamma(n as f64 + 1.0)
+}
+
+/// Returns the natural logarithm of the binomial coefficient `n` choose `k`.
+pub fn ln_choose(n: usize, k: usize) -> f64 {
+    ln_factorial(n) - ln_factorial(k) - ln_factorial(n - k)
+}
+
+/// Returns the complementary error function of `x`, with a fractional error below
+/// 1.2e-7 everywhere.
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+/// Returns the cumulative probability of the standard normal distribution at `z`.
+pub fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / std::f64::consts::SQRT_2)
+}
+
+/// Returns the probability of a chi-square statistic with one degree of freedom
+/// being at least `x`.
+pub fn chi_square_sf(x: f64) -> f64 {
+    erfc((x / 2.0).sqrt())
+}
+
+/// Returns the regularized incomplete beta function `I_x(a, b)`, evaluated with a
+/// continued fraction.
+pub fn regularized_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+
+    // The continued fraction converges quickly only below this point, so use the
+    // symmetry I_x(a, b) = 1 - I_{1-x}(b, a) above it.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        ln_front.exp() * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - ln_front.exp() * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Evaluates the continued fraction of the incomplete beta function with the
+/// modified Lentz method.
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let clamp = |v: f64| match v.abs() < TINY {
+        true => TINY,
+        false => v,
+    };
+
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut h = d;
+
+    for m in 1..=300 {
+        let m = m as f64;
+
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + even * d);
+        c = clamp(1.0 + even / c);
+        h *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + odd * d);
+        c = clamp(1.0 + odd / c);
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+
+    h
+}
+
+/// Returns the probability of a Student's t statistic with `df` degrees of freedom
+/// being at least `|t|` in either direction.
+pub fn student_t_two_sided(t: f64, df: f64) -> f64 {
+    regularized_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+/// Returns the probability of a standard normal statistic being at least `|z|` in
+/// either direction.
+pub fn normal_two_sided(z: f64) -> f64 {
+    erfc(z.abs() / std::f64::consts::SQRT_2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distributions() {
+        assert!((ln_factorial(10) - 3_628_800f64.ln()).abs() < 1e-9);
+        assert!((normal_cdf(1.959_964) - 0.975).abs() < 1e-6);
+        assert!((chi_square_sf(3.841_459) - 0.05).abs() < 1e-6);
+        assert!((student_t_two_sided(2.228_139, 10.0) - 0.05).abs() < 1e-6);
+        assert!((normal_two_sided(-1.959_964) - 0.05).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::models::{Subject, SubjectTrait, SubjectTraitLink};
+use crate::window::TimeWindow;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// A boolean expression over the traits of a subject. A subject has a trait if it
+/// is linked to the trait or to any of its descendants.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TraitExpression {
+    /// Matches subjects with the named trait.
+    Has(String),
+    /// Matches subjects matching every expression.
+    And(Vec<TraitExpression>),
+    /// Matches subjects matching any expression.
+    Or(Vec<TraitExpression>),
+    /// Matches subjects not matching the expression.
+    Not(Box<TraitExpression>),
+}
+
+impl TraitExpression {
+    /// Returns whether or not a subject with the given trait names matches the
+    /// expression.
+    pub fn matches(&self, held: &HashSet<&str>) -> bool {
+        match self {
+            TraitExpression::Has(name) => held.contains(name.as_str()),
+            TraitExpression::And(expressions) => expressions.iter().all(|e| e.matches(held)),
+            TraitExpression::Or(expressions) => expressions.iter().any(|e| e.matches(held)),
+            TraitExpression::Not(expression) => !expression.matches(held),
+        }
+    }
+
+    /// Returns the name of every trait referenced by the expression.
+    pub fn trait_names(&self) -> Vec<&str> {
+        match self {
+            TraitExpression::Has(name) => vec![name.as_str()],
+            TraitExpression::And(expressions) | TraitExpression::Or(expressions) => {
+                expressions.iter().flat_map(|e| e.trait_names()).collect()
+            }
+            TraitExpression::Not(expression) => expression.trait_names(),
+        }
+    }
+}
+
+/// An attribute of a subject that can be filtered on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Attribute {
+    /// The subject's age.
+    Age,
+    /// The subject's length of stay.
+    LengthOfStay,
+}
+
+impl Attribute {
+    /// Returns the value of the attribute for a subject.
+    pub fn value(&self, subject: &Subject) -> i16 {
+        match self {
+            Attribute::Age => subject.age,
+            Attribute::LengthOfStay => subject.length_of_stay,
+        }
+    }
+}
+
+/// Restricts an attribute of subjects to an inclusive range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeFilter {
+    /// The attribute to filter on.
+    pub attribute: Attribute,
+    /// The smallest allowed value, if any.
+    #[serde(default)]
+    pub min: Option<i16>,
+    /// The largest allowed value, if any.
+    #[serde(default)]
+    pub max: Option<i16>,
+}
+
+impl AttributeFilter {
+    /// Returns whether or not a subject passes the filter.
+    pub fn matches(&self, subject: &Subject) -> bool {
+        let value = self.attribute.value(subject);
+
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+/// Defines a cohort as the subjects of one or more source groups that match a
+/// trait expression and every attribute filter. If the definition has a time
+/// window, subjects only hold the traits that applied within it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CohortDefinition {
+    /// The IDs of the groups subjects are selected from.
+    pub source_group_ids: Vec<i32>,
+    /// The expression subjects' traits must match, if any.
+    #[serde(default)]
+    pub traits: Option<TraitExpression>,
+    /// The filters subjects' attributes must pass.
+    #[serde(default)]
+    pub filters: Vec<AttributeFilter>,
+    /// The window of time traits must apply in, if any.
+    #[serde(default)]
+    pub window: Option<TimeWindow>,
+}
+
+impl CohortDefinition {
+    /// Checks that the definition has a source group, only references known
+    /// traits and has a valid time window, if any.
+    pub fn validate(&self, traits: &[SubjectTrait]) -> Result<(), String> {
+        if self.source_group_ids.is_empty() {
+            return Err("a cohort needs at least one source group".into());
+        }
+
+        let known: HashSet<&str> = traits.iter().map(|t| t.trait_name.as_str()).collect();
+        let expression_names = self.traits.iter().flat_map(|e| e.trait_names());
+
+        for name in expression_names {
+            if !known.contains(name) {
+                return Err(format!("unknown trait {}", name));
+            }
+        }
+
+        match &self.window {
+            Some(window) => window.validate(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the sorted IDs of the subjects matching the definition, from the
+    /// subjects of its source groups and their trait links.
+    pub fn select(
+        &self,
+        subjects: &[Subject],
+        links: &[SubjectTraitLink],
+        traits: &[SubjectTrait],
+    ) -> Vec<i32> {
+        let by_id: HashMap<i32, &SubjectTrait> = traits.iter().map(|t| (t.id, t)).collect();
+        let windowed = self.window.as_ref().map(|w| w.filter(subjects, links));
+
+        let mut held: HashMap<i32, HashSet<&str>> = HashMap::new();
+        for link in windowed.as_deref().unwrap_or(links) {
+            let names = held.entry(link.subject_id).or_default();
+
+            // A subject holds every ancestor of its traits. Guard against cycles in
+            // malformed trees.
+            let mut current = by_id.get(&link.subject_trait_id);
+            while let Some(t) = current {
+                if !names.insert(t.trait_name.as_str()) {
+                    break;
+                }
+                current = by_id.get(&t.parent_id);
+            }
+        }
+
+        let none = HashSet::new();
+        let mut ids: Vec<i32> = subjects
+            .iter()
+            .filter(|s| self.filters.iter().all(|f| f.matches(s)))
+            .filter(|s| match &self.traits {
+                Some(expression) => expression.matches(held.get(&s.id).unwrap_or(&none)),
+                None => true,
+            })
+            .map(|s| s.id)
+            .collect();
+
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_cohort() {
+        let definition: CohortDefinition = serde_json::from_str(
+            r#"{
+                "sourceGroupIds": [1],
+                "traits": {"and": [{"has": "respiratory_disease"}, {"not": {"has": "copd"}}]},
+                "filters": [{"attribute": "age", "min": 18}]
+            }"#,
+        )
+        .unwrap();
+
+        let subject = |id, age| Subject {
+            id,
+            group_id: 1,
+            age,
+            length_of_stay: 2,
+            admitted_at: None,
+        };
+        let subjects = vec![
+            subject(1, 40),
+            subject(2, 50),
+            subject(3, 12),
+            subject(4, 30),
+        ];
+        let t = |id, parent_id, name: &str| SubjectTrait {
+            id,
+            parent_id,
+            trait_name: name.into(),
+        };
+        let traits = vec![
+            t(1, 0, "respiratory_disease"),
+            t(2, 1, "asthma"),
+            t(3, 1, "copd"),
+        ];
+        let link = |subject_id, subject_trait_id| SubjectTraitLink {
+            id: 0,
+            subject_id,
+            subject_trait_id,
+            onset_at: None,
+            resolved_at: None,
+            value: None,
+            qualifier: None,
+        };
+        let links = vec![link(1, 2), link(2, 3), link(3, 2)];
+
+        assert_eq!(definition.validate(&traits), Ok(()));
+        assert_eq!(definition.select(&subjects, &links, &traits), vec![1]);
+    }
+}
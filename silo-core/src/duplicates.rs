@@ -0,0 +1,98 @@
+use crate::models::ExternalId;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A set of subjects that are likely duplicates of each other, as they share an
+/// external identifier.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateSet {
+    /// The namespace of the shared identifier.
+    pub namespace: String,
+    /// The shared identifier, normalised.
+    pub value: String,
+    /// The IDs of the subjects sharing the identifier, sorted.
+    pub subject_ids: Vec<i32>,
+    /// The IDs of the groups of the subjects, sorted.
+    pub group_ids: Vec<i32>,
+}
+
+/// Normalises an external identifier for comparison, ignoring surrounding
+/// whitespace and case.
+pub fn normalise(value: &str) -> String {
+    value.trim().to_lowercase()
+}
+
+/// Finds the sets of subjects that share an external identifier, optionally only
+/// within one namespace. Identifiers are compared after normalising them. Sets
+/// are sorted by namespace and identifier.
+pub fn find_duplicates(external_ids: &[ExternalId], namespace: Option<&str>) -> Vec<DuplicateSet> {
+    let mut shared: BTreeMap<(&str, String), Vec<&ExternalId>> = BTreeMap::new();
+    for external_id in external_ids
+        .iter()
+        .filter(|e| namespace.is_none_or(|n| e.namespace == n))
+    {
+        shared
+            .entry((&external_id.namespace, normalise(&external_id.value)))
+            .or_default()
+            .push(external_id);
+    }
+
+    shared
+        .into_iter()
+        .filter_map(|((namespace, value), ids)| {
+            let mut subject_ids: Vec<i32> = ids.iter().map(|e| e.subject_id).collect();
+            subject_ids.sort();
+            subject_ids.dedup();
+            if subject_ids.len() < 2 {
+                return None;
+            }
+
+            let mut group_ids: Vec<i32> = ids.iter().map(|e| e.group_id).collect();
+            group_ids.sort();
+            group_ids.dedup();
+
+            Some(DuplicateSet {
+                namespace: namespace.into(),
+                value,
+                subject_ids,
+                group_ids,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_across_groups() {
+        let id = |subject_id, group_id, namespace: &str, value: &str| ExternalId {
+            id: 0,
+            subject_id,
+            group_id,
+            namespace: namespace.into(),
+            value: value.into(),
+        };
+        let ids = vec![
+            id(1, 1, "mrn", "A100"),
+            id(7, 2, "mrn", " a100"),
+            id(1, 1, "study", "S1"),
+            id(8, 2, "study", "S2"),
+            id(9, 3, "study", "s1"),
+            id(2, 1, "mrn", "A200"),
+        ];
+
+        let duplicates = find_duplicates(&ids, None);
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates[0].namespace, "mrn");
+        assert_eq!(duplicates[0].value, "a100");
+        assert_eq!(duplicates[0].subject_ids, vec![1, 7]);
+        assert_eq!(duplicates[1].subject_ids, vec![1, 9]);
+        assert_eq!(duplicates[1].group_ids, vec![1, 3]);
+
+        assert_eq!(find_duplicates(&ids, Some("study")).len(), 1);
+    }
+}
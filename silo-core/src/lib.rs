@@ -2,11 +2,35 @@
 
 //! Silo's core crate, containing an actor system and logging.
 
-/// The internal logging module.
-mod logging;
+/// Structured logging with levels per module and spans carrying request IDs.
+pub mod logging;
 
 /// The service module.
 pub mod service;
 
 /// The models module.
 pub mod models;
+
+/// Saved definitions of cohorts of subjects.
+pub mod cohort;
+
+/// Matching of controls to cases.
+pub mod matching;
+
+/// Descriptive statistics of groups of subjects.
+pub mod stats;
+
+/// Association analyses of the traits of groups of subjects.
+pub mod analysis;
+
+/// Seeded random samples and train, validation and test splits of subjects.
+pub mod sampling;
+
+/// Time windows over when subjects held their traits.
+pub mod window;
+
+/// Detection of duplicate subjects by their external identifiers.
+pub mod duplicates;
+
+/// Differentially private counts and histograms.
+pub mod privacy;
@@ -0,0 +1,101 @@
+use ::log::LevelFilter;
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+/// Filter sets the most verbose level logged by each module, written like
+/// `info,silo_http=debug,oxidizer=warn`. The most specific module wins, and
+/// modules without a level of their own use the default.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    default: LevelFilter,
+    /// Levels by module, most specific first.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    /// Creates and returns a Filter logging every module at a level.
+    pub fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            modules: vec![],
+        }
+    }
+
+    /// Sets the level of a module and the modules within it.
+    pub fn with_module(mut self, module: &str, level: LevelFilter) -> Self {
+        self.modules.retain(|(m, _)| m != module);
+        self.modules.push((module.to_string(), level));
+        self.modules
+            .sort_by_key(|(module, _)| Reverse(module.len()));
+        self
+    }
+
+    /// Returns the level logged by a target, such as silo_http::api.
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                target == module
+                    || (target.starts_with(module.as_str())
+                        && target[module.len()..].starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// Returns the most verbose level logged by any module.
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::max)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(LevelFilter::Info)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = Filter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let parse = |level: &str| {
+                level
+                    .parse::<LevelFilter>()
+                    .map_err(|_| format!("unknown log level {}", level))
+            };
+            filter = match directive.split_once('=') {
+                Some((module, level)) => filter.with_module(module.trim(), parse(level.trim())?),
+                None => Filter {
+                    default: parse(directive)?,
+                    ..filter
+                },
+            };
+        }
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_by_module() {
+        let filter: Filter = "warn, silo_http=debug,silo_http::api::metrics=error"
+            .parse()
+            .unwrap();
+
+        assert_eq!(filter.level("oxidizer"), LevelFilter::Warn);
+        assert_eq!(filter.level("silo_http"), LevelFilter::Debug);
+        assert_eq!(filter.level("silo_http::api"), LevelFilter::Debug);
+        assert_eq!(filter.level("silo_http_extra"), LevelFilter::Warn);
+        assert_eq!(filter.level("silo_http::api::metrics"), LevelFilter::Error);
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+        assert!("silo_http=loud".parse::<Filter>().is_err());
+    }
+}
@@ -1,19 +1,188 @@
-use pretty_env_logger;
+use super::filter::Filter;
+use super::span::{current, Context};
 
-pub struct Logger {}
+use ::log::{Log, Metadata, Record, SetLoggerError};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+use std::env;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// The format log lines are written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// One JSON object per line.
+    Json,
+    /// Human-readable text.
+    Text,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "text" => Ok(Format::Text),
+            _ => Err(format!("unknown log format {}", s)),
+        }
+    }
+}
+
+/// Logger writes structured log lines to stderr.
+#[derive(Debug, Clone)]
+pub struct Logger {
+    filter: Filter,
+    format: Format,
+}
 
 impl Logger {
     /// Creates and returns a new instance of the Logger.
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(filter: Filter, format: Format) -> Self {
+        Self { filter, format }
     }
 
-    /// Initializes the logger.
-    pub fn init(&self) -> Result<(), std::io::Error> {
-        // TODO: replace with a custom logging solution.
-        // For now, I'm just using pretty-env-logger.
-        pretty_env_logger::init();
+    /// Creates a Logger configured by the environment: SILO_LOG sets the
+    /// levels, such as `info,silo_http=debug` (info by default), and
+    /// SILO_LOG_FORMAT is json (the default) or text.
+    pub fn from_env() -> Result<Self, String> {
+        let filter = match env::var("SILO_LOG") {
+            Ok(levels) => levels.parse()?,
+            Err(_) => Filter::default(),
+        };
+        let format = match env::var("SILO_LOG_FORMAT") {
+            Ok(format) => format.parse()?,
+            Err(_) => Format::Json,
+        };
+        Ok(Self::new(filter, format))
+    }
 
+    /// Initializes the logger as the logger of the log crate.
+    pub fn init(&self) -> Result<(), SetLoggerError> {
+        ::log::set_boxed_logger(Box::new(self.clone()))?;
+        ::log::set_max_level(self.filter.max_level());
         Ok(())
     }
+
+    /// Returns the line of a record, logged within a context.
+    fn line(&self, record: &Record, context: Option<&Context>) -> String {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let spans = context.map_or(&[][..], |c| c.spans.as_slice());
+        let request_id = context.and_then(|c| c.request_id.as_deref());
+
+        match self.format {
+            Format::Json => {
+                let mut line = json!({
+                    "timestamp": timestamp,
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                if let Some(id) = request_id {
+                    line["requestId"] = json!(id);
+                }
+                if !spans.is_empty() {
+                    line["spans"] = spans.iter().map(|s| json!(s.name)).collect();
+                    let fields: Map<String, Value> = spans
+                        .iter()
+                        .flat_map(|s| s.fields.iter())
+                        .map(|(name, value)| (name.to_string(), json!(value)))
+                        .collect();
+                    line["fields"] = Value::Object(fields);
+                }
+                line.to_string()
+            }
+            Format::Text => {
+                let mut line = format!("{} {:<5} {}", timestamp, record.level(), record.target());
+                if let Some(id) = request_id {
+                    line.push_str(&format!(" request_id={}", id));
+                }
+                for span in spans {
+                    let fields: Vec<String> = span
+                        .fields
+                        .iter()
+                        .map(|(name, value)| format!("{}={}", name, value))
+                        .collect();
+                    line.push_str(&format!(" {}{{{}}}", span.name, fields.join(" ")));
+                }
+                line.push_str(&format!(": {}", record.args()));
+                line
+            }
+        }
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new(Filter::default(), Format::Json)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.line(record, current().as_deref());
+        let _ = writeln!(io::stderr().lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::span::{in_request, in_span, Span};
+    use super::*;
+    use ::log::Level;
+
+    /// Returns the line of a record logged by silo_http::api in the current
+    /// context.
+    fn line(logger: &Logger) -> String {
+        logger.line(
+            &Record::builder()
+                .args(format_args!("listed traits"))
+                .level(Level::Info)
+                .target("silo_http::api")
+                .build(),
+            current().as_deref(),
+        )
+    }
+
+    #[tokio::test]
+    async fn lines_carry_request_and_spans() {
+        let logger = Logger::default();
+        let request = Span::new("http").with_field("method", "GET");
+        let call = Span::new("service").with_field("call", "get_traits");
+
+        let json = in_request("abc123", request, async {
+            in_span(call, async { line(&logger) }).await
+        })
+        .await;
+        let json: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["message"], "listed traits");
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["requestId"], "abc123");
+        assert_eq!(json["spans"], json!(["http", "service"]));
+        assert_eq!(
+            json["fields"],
+            json!({"method": "GET", "call": "get_traits"})
+        );
+
+        // Lines outside any request carry neither.
+        let json: Value = serde_json::from_str(&line(&logger)).unwrap();
+        assert!(json.get("requestId").is_none());
+
+        let text = Logger::new(Filter::default(), Format::Text);
+        let span = Span::new("http").with_field("method", "GET");
+        let line = in_request("abc123", span, async { line(&text) }).await;
+        assert!(line
+            .ends_with("INFO  silo_http::api request_id=abc123 http{method=GET}: listed traits"));
+    }
 }
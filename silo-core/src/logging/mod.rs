@@ -1,3 +1,11 @@
-//! A module for pretty-printed logs that wraps around env_logger and the default log crate.
+//! Structured logging. Lines are written as JSON or text, the level logged is
+//! set per module, and spans carry the ID of the request being handled onto
+//! every line logged within them.
 
-pub mod log;
+mod filter;
+mod log;
+mod span;
+
+pub use self::filter::Filter;
+pub use self::log::{Format, Logger};
+pub use self::span::{in_request, in_span, request_id, Instrumented, Span};
@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Instant;
+
+/// A named unit of work, such as handling a request or calling the database,
+/// whose fields are added to every line logged within it.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub(super) name: &'static str,
+    pub(super) fields: Vec<(&'static str, String)>,
+}
+
+impl Span {
+    /// Creates and returns a Span without fields.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            fields: vec![],
+        }
+    }
+
+    /// Adds a field to the span.
+    pub fn with_field(mut self, name: &'static str, value: impl Display) -> Self {
+        self.fields.push((name, value.to_string()));
+        self
+    }
+}
+
+/// The request and spans lines are logged within.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Context {
+    pub(super) request_id: Option<Arc<str>>,
+    pub(super) spans: Vec<Arc<Span>>,
+}
+
+thread_local! {
+    /// The context of the future being polled on this thread, if any.
+    static CURRENT: RefCell<Option<Arc<Context>>> = const { RefCell::new(None) };
+}
+
+/// Returns the context of the future being polled on this thread.
+pub(super) fn current() -> Option<Arc<Context>> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Returns the ID of the request being handled, if any.
+pub fn request_id() -> Option<String> {
+    current().and_then(|c| c.request_id.as_ref().map(|id| id.to_string()))
+}
+
+/// Runs a future within a span of a new request.
+pub fn in_request<F: Future>(request_id: &str, span: Span, future: F) -> Instrumented<F> {
+    let context = Context {
+        request_id: Some(request_id.into()),
+        spans: vec![Arc::new(span)],
+    };
+    Instrumented::new(context, future)
+}
+
+/// Runs a future within a span, inside the spans of the future creating it.
+pub fn in_span<F: Future>(span: Span, future: F) -> Instrumented<F> {
+    let mut context = current().map_or_else(Context::default, |c| (*c).clone());
+    context.spans.push(Arc::new(span));
+    Instrumented::new(context, future)
+}
+
+/// Restores the context of the outer future when polling an inner one ends,
+/// even if it panics.
+struct Restore(Option<Arc<Context>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        CURRENT.with(|c| *c.borrow_mut() = outer);
+    }
+}
+
+/// A future polled within a span, which logs how long the span took at debug
+/// level once the future completes.
+pub struct Instrumented<F> {
+    context: Arc<Context>,
+    future: Pin<Box<F>>,
+    started: Option<Instant>,
+}
+
+impl<F> Instrumented<F> {
+    fn new(context: Context, future: F) -> Self {
+        Self {
+            context: Arc::new(context),
+            future: Box::pin(future),
+            started: None,
+        }
+    }
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let started = *this.started.get_or_insert_with(Instant::now);
+
+        let outer = CURRENT.with(|c| c.replace(Some(this.context.clone())));
+        let _restore = Restore(outer);
+
+        let poll = this.future.as_mut().poll(cx);
+        if poll.is_ready() {
+            if let Some(span) = this.context.spans.last() {
+                ::log::debug!("{} finished in {:?}", span.name, started.elapsed());
+            }
+        }
+        poll
+    }
+}
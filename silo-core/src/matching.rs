@@ -0,0 +1,440 @@
+use crate::analysis::{variance, Sample};
+use crate::models::Subject;
+use crate::stats::mean;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Options for matching controls to cases.
+#[derive(Debug, Clone)]
+pub struct MatchingOptions {
+    /// The largest allowed difference in age, in years, or None to ignore age.
+    pub age_tolerance: Option<i16>,
+    /// The largest allowed difference in length of stay, in days, or None to
+    /// ignore length of stay.
+    pub length_of_stay_tolerance: Option<i16>,
+    /// The IDs of the traits controls must share with their case, present or absent.
+    pub trait_ids: Vec<i32>,
+    /// The number of controls matched to every case.
+    pub ratio: usize,
+    /// Allows a control to be matched to more than one case.
+    pub with_replacement: bool,
+    /// The seed of the random number generator that breaks ties.
+    pub seed: u64,
+}
+
+impl Default for MatchingOptions {
+    fn default() -> Self {
+        Self {
+            age_tolerance: Some(5),
+            length_of_stay_tolerance: Some(3),
+            trait_ids: vec![],
+            ratio: 1,
+            with_replacement: false,
+            seed: 0,
+        }
+    }
+}
+
+/// The controls matched to a single case. Subjects are identified by their IDs,
+/// or by another identifier such as a token once mapped.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchedSet<Id = i32> {
+    /// The ID of the case subject.
+    pub case_id: Id,
+    /// The IDs of the matched control subjects, closest first.
+    pub control_ids: Vec<Id>,
+}
+
+/// Compares a single covariate between cases and controls.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CovariateBalance {
+    /// The name of the attribute or trait.
+    pub name: String,
+    /// The mean of the covariate among cases. For traits, the prevalence.
+    pub case_mean: Option<f64>,
+    /// The mean of the covariate among controls. For traits, the prevalence.
+    pub control_mean: Option<f64>,
+    /// The standardized mean difference of the covariate.
+    pub standardized_difference: Option<f64>,
+}
+
+/// The balance of every matched covariate between cases and a set of controls.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Balance {
+    /// The number of controls.
+    pub control_count: usize,
+    /// The balance of each covariate.
+    pub covariates: Vec<CovariateBalance>,
+}
+
+/// The result of matching controls from a pool group to a case group.
+/// Subjects are identified by their IDs, or by another identifier such as a
+/// token once mapped.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchingReport<Id = i32> {
+    /// The ID of the case group.
+    pub case_group_id: i32,
+    /// The ID of the pool group controls were picked from.
+    pub pool_group_id: i32,
+    /// The ID of the group the controls were written into, once written.
+    pub control_group_id: Option<i32>,
+    /// The number of controls matched to every case.
+    pub ratio: usize,
+    /// The seed that reproduces the matching.
+    pub seed: u64,
+    /// The controls matched to each case.
+    pub sets: Vec<MatchedSet<Id>>,
+    /// The IDs of the cases matched to fewer controls than the ratio.
+    pub incomplete_case_ids: Vec<Id>,
+    /// The balance between the cases and the whole pool.
+    pub before: Balance,
+    /// The balance between the cases and the matched controls.
+    pub after: Balance,
+}
+
+impl MatchingReport {
+    /// Returns the IDs of every matched control, each once, sorted.
+    pub fn control_ids(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self
+            .sets
+            .iter()
+            .flat_map(|set| set.control_ids.iter().cloned())
+            .collect();
+
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    /// Returns the report with every subject ID mapped, such as to a token.
+    pub fn map_ids<T, F: FnMut(i32) -> T>(self, mut f: F) -> MatchingReport<T> {
+        MatchingReport {
+            case_group_id: self.case_group_id,
+            pool_group_id: self.pool_group_id,
+            control_group_id: self.control_group_id,
+            ratio: self.ratio,
+            seed: self.seed,
+            sets: self
+                .sets
+                .into_iter()
+                .map(|set| MatchedSet {
+                    case_id: f(set.case_id),
+                    control_ids: set.control_ids.into_iter().map(&mut f).collect(),
+                })
+                .collect(),
+            incomplete_case_ids: self.incomplete_case_ids.into_iter().map(&mut f).collect(),
+            before: self.before,
+            after: self.after,
+        }
+    }
+}
+
+/// Matches controls from a pool to every case. In each of `ratio` rounds, the
+/// cases take turns in a seeded random order to pick the closest eligible
+/// control by age and length of stay. Subjects of the case group are never
+/// picked as controls.
+pub fn match_controls(
+    cases: Sample,
+    pool: Sample,
+    trait_names: &HashMap<i32, String>,
+    options: &MatchingOptions,
+) -> MatchingReport {
+    let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+
+    let case_traits = held_traits(&cases, &options.trait_ids);
+    let pool_traits = held_traits(&pool, &options.trait_ids);
+    let none = HashSet::new();
+
+    let case_ids: HashSet<i32> = cases.subjects.iter().map(|s| s.id).collect();
+    let mut candidates: Vec<&Subject> = pool
+        .subjects
+        .iter()
+        .filter(|s| !case_ids.contains(&s.id))
+        .collect();
+    candidates.sort_by_key(|s| s.id);
+    candidates.dedup_by_key(|s| s.id);
+    candidates.shuffle(&mut rng);
+
+    let mut order: Vec<&Subject> = cases.subjects.iter().collect();
+    order.sort_by_key(|s| s.id);
+    order.shuffle(&mut rng);
+
+    let mut sets: Vec<MatchedSet> = order
+        .iter()
+        .map(|case| MatchedSet {
+            case_id: case.id,
+            control_ids: vec![],
+        })
+        .collect();
+    let mut used: HashSet<i32> = HashSet::new();
+
+    for _ in 0..options.ratio {
+        for (case, set) in order.iter().zip(sets.iter_mut()) {
+            let traits = case_traits.get(&case.id).unwrap_or(&none);
+
+            let best = candidates
+                .iter()
+                .filter(|c| options.with_replacement || !used.contains(&c.id))
+                .filter(|c| !set.control_ids.contains(&c.id))
+                .filter(|c| within(case.age, c.age, options.age_tolerance))
+                .filter(|c| {
+                    within(
+                        case.length_of_stay,
+                        c.length_of_stay,
+                        options.length_of_stay_tolerance,
+                    )
+                })
+                .filter(|c| pool_traits.get(&c.id).unwrap_or(&none) == traits)
+                // The candidates are shuffled, so ties are broken randomly.
+                .min_by_key(|c| distance(case, c, options));
+
+            if let Some(control) = best {
+                set.control_ids.push(control.id);
+                used.insert(control.id);
+            }
+        }
+    }
+
+    sets.sort_by_key(|set| set.case_id);
+
+    let incomplete_case_ids = sets
+        .iter()
+        .filter(|set| set.control_ids.len() < options.ratio)
+        .map(|set| set.case_id)
+        .collect();
+
+    let matched: Vec<&Subject> = candidates
+        .iter()
+        .cloned()
+        .filter(|s| used.contains(&s.id))
+        .collect();
+    let cases_subjects: Vec<&Subject> = cases.subjects.iter().collect();
+
+    let balance_of = |controls: &[&Subject]| {
+        balance(
+            &cases_subjects,
+            &case_traits,
+            controls,
+            &pool_traits,
+            trait_names,
+            &options.trait_ids,
+        )
+    };
+
+    MatchingReport {
+        case_group_id: cases.group_id,
+        pool_group_id: pool.group_id,
+        control_group_id: None,
+        ratio: options.ratio,
+        seed: options.seed,
+        sets,
+        incomplete_case_ids,
+        before: balance_of(&candidates),
+        after: balance_of(&matched),
+    }
+}
+
+/// Returns the IDs of the matched traits held by each subject of a sample.
+fn held_traits(sample: &Sample, trait_ids: &[i32]) -> HashMap<i32, HashSet<i32>> {
+    let mut held: HashMap<i32, HashSet<i32>> = HashMap::new();
+    for link in sample
+        .links
+        .iter()
+        .filter(|l| trait_ids.contains(&l.subject_trait_id))
+    {
+        held.entry(link.subject_id)
+            .or_default()
+            .insert(link.subject_trait_id);
+    }
+
+    held
+}
+
+/// Returns whether or not two values are within a tolerance, if there is one.
+fn within(a: i16, b: i16, tolerance: Option<i16>) -> bool {
+    tolerance.is_none_or(|tolerance| (i32::from(a) - i32::from(b)).abs() <= i32::from(tolerance))
+}
+
+/// Returns the distance between a case and a control over the matched attributes.
+fn distance(case: &Subject, control: &Subject, options: &MatchingOptions) -> i32 {
+    let difference = |a: i16, b: i16, tolerance: Option<i16>| match tolerance {
+        Some(_) => (i32::from(a) - i32::from(b)).abs(),
+        None => 0,
+    };
+
+    difference(case.age, control.age, options.age_tolerance)
+        + difference(
+            case.length_of_stay,
+            control.length_of_stay,
+            options.length_of_stay_tolerance,
+        )
+}
+
+/// Returns the balance of the attributes and matched traits between cases and
+/// controls.
+fn balance(
+    cases: &[&Subject],
+    case_traits: &HashMap<i32, HashSet<i32>>,
+    controls: &[&Subject],
+    control_traits: &HashMap<i32, HashSet<i32>>,
+    trait_names: &HashMap<i32, String>,
+    trait_ids: &[i32],
+) -> Balance {
+    let attribute = |subjects: &[&Subject], value: fn(&Subject) -> i16| -> Vec<f64> {
+        subjects.iter().map(|s| value(s).into()).collect()
+    };
+    let presence = |subjects: &[&Subject], held: &HashMap<i32, HashSet<i32>>, id: i32| {
+        subjects
+            .iter()
+            .map(|s| match held.get(&s.id).is_some_and(|h| h.contains(&id)) {
+                true => 1.0,
+                false => 0.0,
+            })
+            .collect::<Vec<f64>>()
+    };
+
+    let mut covariates = vec![
+        covariate_balance(
+            "age",
+            &attribute(cases, |s| s.age),
+            &attribute(controls, |s| s.age),
+        ),
+        covariate_balance(
+            "length_of_stay",
+            &attribute(cases, |s| s.length_of_stay),
+            &attribute(controls, |s| s.length_of_stay),
+        ),
+    ];
+
+    for id in trait_ids {
+        let name = trait_names
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string());
+
+        covariates.push(covariate_balance(
+            &name,
+            &presence(cases, case_traits, *id),
+            &presence(controls, control_traits, *id),
+        ));
+    }
+
+    Balance {
+        control_count: controls.len(),
+        covariates,
+    }
+}
+
+/// Returns the balance of a covariate, with the standardized mean difference
+/// computed against the pooled variance of cases and controls.
+fn covariate_balance(name: &str, cases: &[f64], controls: &[f64]) -> CovariateBalance {
+    let (case_mean, control_mean) = (mean(cases), mean(controls));
+
+    let standardized_difference = match (case_mean, control_mean) {
+        (Some(a), Some(b)) => {
+            let pooled = (variance(cases).unwrap_or(0.0) + variance(controls).unwrap_or(0.0)) / 2.0;
+            match pooled > 0.0 {
+                true => Some((a - b) / pooled.sqrt()),
+                false if a == b => Some(0.0),
+                false => None,
+            }
+        }
+        _ => None,
+    };
+
+    CovariateBalance {
+        name: name.into(),
+        case_mean,
+        control_mean,
+        standardized_difference,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SubjectTraitLink;
+
+    fn subject(id: i32, group_id: i32, age: i16) -> Subject {
+        Subject {
+            id,
+            group_id,
+            age,
+            length_of_stay: 3,
+            admitted_at: None,
+        }
+    }
+
+    #[test]
+    fn extreme_tolerances() {
+        assert!(!within(i16::MIN, i16::MAX, Some(5)));
+        assert!(within(-100, 100, Some(200)));
+        assert!(within(i16::MAX, i16::MAX - 5, Some(5)));
+        assert!(within(i16::MIN, i16::MAX, None));
+    }
+
+    #[test]
+    fn match_without_replacement() {
+        let cases = vec![subject(1, 1, 40), subject(2, 1, 60)];
+        let pool = vec![
+            subject(10, 2, 41),
+            subject(11, 2, 39),
+            subject(12, 2, 61),
+            subject(13, 2, 80),
+            subject(14, 2, 59),
+        ];
+        let links = vec![SubjectTraitLink {
+            id: 0,
+            subject_id: 1,
+            subject_trait_id: 7,
+            onset_at: None,
+            resolved_at: None,
+            value: None,
+            qualifier: None,
+        }];
+        let options = MatchingOptions {
+            ratio: 2,
+            seed: 42,
+            ..MatchingOptions::default()
+        };
+        let run = |options: &MatchingOptions| {
+            match_controls(
+                Sample {
+                    group_id: 1,
+                    subjects: &cases,
+                    links: &links,
+                },
+                Sample {
+                    group_id: 2,
+                    subjects: &pool,
+                    links: &[],
+                },
+                &HashMap::new(),
+                options,
+            )
+        };
+
+        let report = run(&options);
+        assert_eq!(report.control_ids(), vec![10, 11, 12, 14]);
+        assert!(report.incomplete_case_ids.is_empty());
+        assert_eq!(report.before.control_count, 5);
+        assert_eq!(report.after.control_count, 4);
+        assert_eq!(run(&options).sets, report.sets);
+
+        // Case 1 holds trait 7, which no control shares.
+        let report = run(&MatchingOptions {
+            trait_ids: vec![7],
+            ..options
+        });
+        assert_eq!(report.incomplete_case_ids, vec![1]);
+        assert_eq!(report.sets[1].control_ids.len(), 2);
+    }
+}
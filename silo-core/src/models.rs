@@ -6,3 +6,27 @@ pub use subject_trait::SubjectTrait;
 
 mod subject;
 pub use subject::Subject;
+
+mod subject_trait_link;
+pub use subject_trait_link::SubjectTraitLink;
+
+mod cohort;
+pub use cohort::{Cohort, CohortMaterialisation};
+
+mod external_id;
+pub use external_id::ExternalId;
+
+mod pseudonym;
+pub use pseudonym::{Pseudonym, PseudonymExport};
+
+mod privacy_budget;
+pub use privacy_budget::PrivacyBudget;
+
+mod entity_counts;
+pub use entity_counts::EntityCounts;
+
+mod migration_status;
+pub use migration_status::MigrationStatus;
+
+mod pool_state;
+pub use pool_state::PoolState;
@@ -0,0 +1,48 @@
+use crate::cohort::CohortDefinition;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// A named, saved cohort definition that can be materialised into a Group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Cohort {
+    /// The cohort's unique ID.
+    pub id: i32,
+    /// The cohort's unique name.
+    pub name: String,
+    /// A description of the cohort, if any.
+    pub description: Option<String>,
+    /// The definition of the cohort's subjects.
+    pub definition: CohortDefinition,
+    /// The version of the definition, incremented every time it changes.
+    pub version: i32,
+    /// The ID of the group the cohort was last materialised into, if any.
+    pub group_id: Option<i32>,
+    /// When the cohort was created.
+    pub created_at: DateTime<Utc>,
+    /// When the cohort was last updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Records a single materialisation of a version of a Cohort into a Group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CohortMaterialisation {
+    /// The materialisation's unique ID.
+    pub id: i32,
+    /// The ID of the materialised cohort.
+    pub cohort_id: i32,
+    /// The version of the cohort's definition that was materialised.
+    pub version: i32,
+    /// The definition that was materialised, if it was recorded. Cohorts keep
+    /// only their latest definition, so this traces a version back to the
+    /// definition that produced it.
+    pub definition: Option<CohortDefinition>,
+    /// The ID of the group the cohort was materialised into.
+    pub group_id: i32,
+    /// The number of subjects selected by the definition.
+    pub subject_count: i32,
+    /// When the cohort was materialised.
+    pub materialised_at: DateTime<Utc>,
+}
@@ -0,0 +1,14 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The number of subjects, traits and groups stored.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityCounts {
+    /// The number of subjects.
+    pub subjects: i64,
+    /// The number of traits.
+    pub traits: i64,
+    /// The number of groups.
+    pub groups: i64,
+}
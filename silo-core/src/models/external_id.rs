@@ -0,0 +1,19 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Identifies a Subject outside of silo, such as by its MRN or a study ID. The
+/// namespace and value of an ExternalId are unique within the Subject's group.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalId {
+    /// The ID of the entity.
+    pub id: i32,
+    /// The ID of the identified Subject.
+    pub subject_id: i32,
+    /// The ID of the group of the identified Subject.
+    pub group_id: i32,
+    /// The system that issued the identifier, such as mrn.
+    pub namespace: String,
+    /// The identifier itself.
+    pub value: String,
+}
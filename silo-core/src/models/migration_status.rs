@@ -0,0 +1,19 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The schema migrations applied to a database.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStatus {
+    /// The version of the last migration applied, if any has been.
+    pub applied: Option<i64>,
+    /// The version of the last migration known to the server.
+    pub latest: i64,
+}
+
+impl MigrationStatus {
+    /// Returns whether every known migration has been applied.
+    pub fn is_current(&self) -> bool {
+        self.applied.is_some_and(|applied| applied >= self.latest)
+    }
+}
@@ -0,0 +1,12 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The usage of the database connection pool.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolState {
+    /// The number of connections taken from the pool.
+    pub in_use: i64,
+    /// The most connections the pool opens.
+    pub max_open: i64,
+}
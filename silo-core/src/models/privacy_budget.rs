@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The privacy loss an API key may spend on differentially private queries.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivacyBudget {
+    /// The ID of the budget.
+    pub id: i32,
+    /// The API key the budget belongs to.
+    pub api_key: String,
+    /// The total epsilon the key may spend.
+    pub epsilon_total: f64,
+    /// The epsilon the key has spent so far.
+    pub epsilon_spent: f64,
+    /// The time the budget was last changed.
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PrivacyBudget {
+    /// Returns the epsilon the key has left to spend.
+    pub fn remaining(&self) -> f64 {
+        (self.epsilon_total - self.epsilon_spent).max(0.0)
+    }
+}
@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Records an export of a group whose subject identifiers were replaced with
+/// tokens.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PseudonymExport {
+    /// The ID of the export.
+    pub id: i32,
+    /// The ID of the exported group.
+    pub group_id: i32,
+    /// The method tokens were made with: hmac or random.
+    pub method: String,
+    /// The time of the export.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Maps a token of a PseudonymExport back to the Subject it replaced.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Pseudonym {
+    /// The ID of the entity.
+    pub id: i32,
+    /// The ID of the PseudonymExport the token was made for.
+    pub export_id: i32,
+    /// The ID of the Subject the token replaced.
+    pub subject_id: i32,
+    /// The token.
+    pub token: String,
+}
@@ -1,7 +1,9 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::Serialize;
 
 /// Contains information about a single subject for analysis.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Subject {
     /// The subject's unique ID.
@@ -12,4 +14,6 @@ pub struct Subject {
     pub age: i16,
     /// The subject's length of stay.
     pub length_of_stay: i16,
+    /// The time the subject was admitted, if known.
+    pub admitted_at: Option<DateTime<Utc>>,
 }
@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Links a single SubjectTrait to a Subject.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectTraitLink {
+    /// The ID of the link.
+    pub id: i32,
+    /// The ID of the linked Subject.
+    pub subject_id: i32,
+    /// The ID of the linked SubjectTrait.
+    pub subject_trait_id: i32,
+    /// The time the trait started to apply, if known.
+    pub onset_at: Option<DateTime<Utc>>,
+    /// The time the trait stopped applying, if it has.
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// The value of the trait, such as a lab result, if it has one.
+    pub value: Option<f64>,
+    /// The qualifier of the trait, such as its severity or whether it is
+    /// confirmed or suspected, if it has one.
+    pub qualifier: Option<String>,
+}
+
+impl SubjectTraitLink {
+    /// Returns the severity of the trait as an ordinal from its qualifier: 1 for
+    /// mild, 2 for moderate and 3 for severe. Returns None for any other
+    /// qualifier.
+    pub fn severity(&self) -> Option<i16> {
+        match self.qualifier.as_deref().map(str::to_lowercase).as_deref() {
+            Some("mild") => Some(1),
+            Some("moderate") => Some(2),
+            Some("severe") => Some(3),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::stats::HistogramBin;
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+/// The noise added to a query to make its result differentially private.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Mechanism {
+    /// Adds Laplace noise, giving pure epsilon-differential privacy. Counts get
+    /// noise from the discrete Laplace distribution.
+    Laplace,
+    /// Adds Gaussian noise, giving (epsilon, delta)-differential privacy.
+    Gaussian,
+}
+
+impl Mechanism {
+    /// Returns the name of the mechanism.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mechanism::Laplace => "laplace",
+            Mechanism::Gaussian => "gaussian",
+        }
+    }
+}
+
+impl FromStr for Mechanism {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "laplace" => Ok(Mechanism::Laplace),
+            "gaussian" => Ok(Mechanism::Gaussian),
+            _ => Err(format!("unknown noise mechanism {}", s)),
+        }
+    }
+}
+
+/// The mechanism and privacy parameters of a differentially private query.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseParams {
+    /// The mechanism noise is drawn from.
+    pub mechanism: Mechanism,
+    /// The privacy loss of the query.
+    pub epsilon: f64,
+    /// The probability the privacy loss exceeds epsilon. Only used by the
+    /// Gaussian mechanism.
+    pub delta: Option<f64>,
+}
+
+impl NoiseParams {
+    /// Checks that epsilon is positive and that the Gaussian mechanism has an
+    /// epsilon of at most 1 and a delta between 0 and 1. Returns the name of the
+    /// invalid parameter, epsilon or delta, and why it is invalid.
+    pub fn validate(&self) -> Result<(), (&'static str, String)> {
+        if !(self.epsilon > 0.0 && self.epsilon.is_finite()) {
+            return Err(("epsilon", "must be a positive number".into()));
+        }
+
+        match (self.mechanism, self.delta) {
+            (Mechanism::Laplace, _) => Ok(()),
+            (Mechanism::Gaussian, _) if self.epsilon > 1.0 => Err((
+                "epsilon",
+                "the gaussian mechanism needs an epsilon of at most 1".into(),
+            )),
+            (Mechanism::Gaussian, Some(delta)) if delta > 0.0 && delta < 1.0 => Ok(()),
+            (Mechanism::Gaussian, _) => Err((
+                "delta",
+                "the gaussian mechanism needs a delta between 0 and 1".into(),
+            )),
+        }
+    }
+
+    /// Returns the scale of the noise for a query whose result changes by at most
+    /// `sensitivity` when one subject is added or removed: the scale of the
+    /// Laplace distribution, or the standard deviation of the Gaussian one.
+    pub fn scale(&self, sensitivity: f64) -> f64 {
+        match self.mechanism {
+            Mechanism::Laplace => sensitivity / self.epsilon,
+            Mechanism::Gaussian => {
+                let delta = self.delta.unwrap_or_default();
+                (2.0 * (1.25 / delta).ln()).sqrt() * sensitivity / self.epsilon
+            }
+        }
+    }
+
+    /// Draws noise for a query of the given sensitivity. The noise is a
+    /// floating-point number, whose low-order bits can reveal the value it was
+    /// added to (Mironov, 2012), so results must be rounded before they are
+    /// released.
+    pub fn noise<R: Rng>(&self, rng: &mut R, sensitivity: f64) -> f64 {
+        let scale = self.scale(sensitivity);
+
+        match self.mechanism {
+            // Inverts the CDF of the Laplace distribution, redrawing the one value
+            // it is infinite at.
+            Mechanism::Laplace => loop {
+                let u = rng.gen::<f64>() - 0.5;
+                if u.abs() < 0.5 {
+                    break -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln();
+                }
+            },
+            // The Box-Muller transform. The first uniform is in (0, 1] so its
+            // logarithm is finite.
+            Mechanism::Gaussian => {
+                let u1 = 1.0 - rng.gen::<f64>();
+                let u2 = rng.gen::<f64>();
+                scale * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+            }
+        }
+    }
+
+    /// Returns a count with noise added, clamped to zero. Adding or removing a
+    /// subject changes a count by at most one. The Laplace mechanism adds whole
+    /// numbers, so no floating-point noise is released. Gaussian noise is rounded
+    /// to a whole number, which hides its low-order bits but isn't an exact
+    /// sample of the discrete Gaussian distribution.
+    pub fn noisy_count<R: Rng>(&self, rng: &mut R, count: usize) -> usize {
+        match self.mechanism {
+            Mechanism::Laplace => {
+                let noise = discrete_laplace(rng, self.scale(1.0));
+                (count as i64).saturating_add(noise).max(0) as usize
+            }
+            Mechanism::Gaussian => (count as f64 + self.noise(rng, 1.0)).round().max(0.0) as usize,
+        }
+    }
+
+    /// Adds noise to the count of every bin of a histogram. As a subject falls in
+    /// exactly one bin, the whole histogram has a sensitivity of one.
+    pub fn noisy_histogram<R: Rng>(&self, rng: &mut R, bins: &[HistogramBin]) -> Vec<HistogramBin> {
+        bins.iter()
+            .map(|bin| HistogramBin {
+                count: self.noisy_count(rng, bin.count),
+                ..bin.clone()
+            })
+            .collect()
+    }
+}
+
+/// Draws a whole number from the discrete Laplace distribution of the given
+/// scale, where k has a probability proportional to exp(-|k| / scale), as the
+/// difference of two geometric draws.
+fn discrete_laplace<R: Rng>(rng: &mut R, scale: f64) -> i64 {
+    // The number of failures before the first success of trials that succeed
+    // with a probability of 1 - exp(-1 / scale). The uniform is in (0, 1] so its
+    // logarithm is finite.
+    let mut geometric = || (-scale * (1.0 - rng.gen::<f64>()).ln()).floor() as i64;
+    geometric() - geometric()
+}
+
+/// Returns a histogram of values with bins of `bin_width` from `lower` to
+/// `upper`. Unlike a histogram fitted to the values, its bins don't reveal the
+/// smallest and largest value. Values outside the range are counted in the first
+/// or last bin.
+pub fn fixed_histogram(
+    values: &[f64],
+    lower: f64,
+    upper: f64,
+    bin_width: f64,
+) -> Vec<HistogramBin> {
+    if !(bin_width > 0.0 && upper > lower) {
+        return vec![];
+    }
+
+    let len = ((upper - lower) / bin_width).ceil() as usize;
+    let mut bins: Vec<HistogramBin> = (0..len)
+        .map(|bin| HistogramBin {
+            lower: lower + bin as f64 * bin_width,
+            upper: (lower + (bin + 1) as f64 * bin_width).min(upper),
+            count: 0,
+        })
+        .collect();
+
+    for value in values.iter().filter(|v| v.is_finite()) {
+        let bin = ((value - lower) / bin_width).floor().max(0.0) as usize;
+        bins[bin.min(len - 1)].count += 1;
+    }
+
+    bins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn moments(params: &NoiseParams) -> (f64, f64) {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let draws: Vec<f64> = (0..20_000).map(|_| params.noise(&mut rng, 1.0)).collect();
+
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        let variance = draws.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / draws.len() as f64;
+        (mean, variance)
+    }
+
+    #[test]
+    fn noise_distributions() {
+        let laplace = NoiseParams {
+            mechanism: Mechanism::Laplace,
+            epsilon: 0.5,
+            delta: None,
+        };
+        let (mean, variance) = moments(&laplace);
+        // A Laplace distribution of scale b has a variance of 2b^2.
+        assert!(mean.abs() < 0.1);
+        assert!((variance / 8.0 - 1.0).abs() < 0.1);
+
+        let gaussian = NoiseParams {
+            mechanism: Mechanism::Gaussian,
+            epsilon: 1.0,
+            delta: Some(1e-5),
+        };
+        let (mean, variance) = moments(&gaussian);
+        assert!(mean.abs() < 0.1);
+        assert!((variance / gaussian.scale(1.0).powi(2) - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn discrete_laplace_counts() {
+        let params = NoiseParams {
+            mechanism: Mechanism::Laplace,
+            epsilon: 0.5,
+            delta: None,
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let draws: Vec<f64> = (0..20_000)
+            .map(|_| params.noisy_count(&mut rng, 1_000) as f64 - 1_000.0)
+            .collect();
+
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        let variance = draws.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / draws.len() as f64;
+        // A discrete Laplace distribution with q = exp(-1 / b) has a variance of
+        // 2q / (1 - q)^2.
+        let q = (-1.0 / params.scale(1.0)).exp();
+        assert!(mean.abs() < 0.1);
+        assert!((variance / (2.0 * q / (1.0 - q).powi(2)) - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn validation() {
+        let params = |mechanism, epsilon, delta| NoiseParams {
+            mechanism,
+            epsilon,
+            delta,
+        };
+
+        assert!(params(Mechanism::Laplace, 2.0, None).validate().is_ok());
+        let invalid = |params: NoiseParams| params.validate().unwrap_err().0;
+        assert_eq!(invalid(params(Mechanism::Laplace, 0.0, None)), "epsilon");
+        assert_eq!(invalid(params(Mechanism::Gaussian, 0.5, None)), "delta");
+        assert_eq!(
+            invalid(params(Mechanism::Gaussian, 2.0, Some(1e-5))),
+            "epsilon"
+        );
+        assert!(params(Mechanism::Gaussian, 0.5, Some(1e-5))
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn fixed_bins() {
+        let bins = fixed_histogram(&[-5.0, 3.0, 12.0, 99.0], 0.0, 25.0, 10.0);
+
+        let counts: Vec<usize> = bins.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![2, 1, 1]);
+        assert_eq!(bins[2].upper, 25.0);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::models::{Subject, SubjectTraitLink};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// Specifies how many subjects to draw in a sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    /// Draws a fixed number of subjects, or every subject if there are fewer.
+    Count(usize),
+    /// Draws a proportion of subjects between 0 and 1, rounded to the nearest
+    /// subject.
+    Fraction(f64),
+}
+
+/// Specifies how subjects are divided into strata before they are split, so
+/// that every set gets the same share of each stratum.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stratification {
+    /// Stratifies subjects by whether or not they have the trait with this ID.
+    Trait(i32),
+    /// Stratifies subjects by age bands of this width, in years.
+    AgeBand(i16),
+}
+
+/// One of the sets subjects are split into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SplitSet {
+    /// The training set.
+    Train,
+    /// The validation set.
+    Validation,
+    /// The test set.
+    Test,
+}
+
+impl SplitSet {
+    /// Every set, in order.
+    pub const ALL: [SplitSet; 3] = [SplitSet::Train, SplitSet::Validation, SplitSet::Test];
+
+    /// Returns the name of the set.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SplitSet::Train => "train",
+            SplitSet::Validation => "validation",
+            SplitSet::Test => "test",
+        }
+    }
+}
+
+/// Options for splitting subjects into train, validation and test sets.
+#[derive(Debug, Clone)]
+pub struct SplitOptions {
+    /// The proportion of subjects in the training set.
+    pub train: f64,
+    /// The proportion of subjects in the validation set.
+    pub validation: f64,
+    /// The proportion of subjects in the test set.
+    pub test: f64,
+    /// The strata subjects are split within, if any.
+    pub stratify: Option<Stratification>,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self {
+            train: 0.7,
+            validation: 0.15,
+            test: 0.15,
+            stratify: None,
+        }
+    }
+}
+
+impl SplitOptions {
+    /// Checks that the proportions are non-negative and not all zero.
+    pub fn validate(&self) -> Result<(), String> {
+        let proportions = [self.train, self.validation, self.test];
+
+        if proportions.iter().any(|p| !p.is_finite() || *p < 0.0) {
+            return Err("split proportions must be non-negative".into());
+        }
+        if proportions.iter().sum::<f64>() <= 0.0 {
+            return Err("at least one split proportion must be positive".into());
+        }
+        if let Some(Stratification::AgeBand(width)) = self.stratify {
+            if width <= 0 {
+                return Err("age bands must be at least a year wide".into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Draws random samples and splits of subjects. Every draw from a Sampler with
+/// the same seed and the same subjects gives the same result, regardless of the
+/// order the subjects are given in.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    seed: u64,
+}
+
+impl Sampler {
+    /// Creates and returns a Sampler with a seed.
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Returns the seed of the Sampler.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Draws a random sample of subjects without replacement, returning their
+    /// sorted IDs.
+    pub fn sample(&self, subjects: &[Subject], size: SampleSize) -> Vec<i32> {
+        let mut ids = sorted_ids(subjects.iter());
+        let count = match size {
+            SampleSize::Count(count) => count,
+            SampleSize::Fraction(fraction) => {
+                (ids.len() as f64 * fraction.clamp(0.0, 1.0)).round() as usize
+            }
+        };
+
+        ids.shuffle(&mut ChaCha8Rng::seed_from_u64(self.seed));
+        ids.truncate(count);
+        ids.sort();
+        ids
+    }
+
+    /// Splits subjects into train, validation and test sets, returning the set of
+    /// each subject by ID. Within each stratum, subjects are shuffled and divided
+    /// by the cumulative proportions, rounded to the nearest subject.
+    pub fn split(
+        &self,
+        subjects: &[Subject],
+        links: &[SubjectTraitLink],
+        options: &SplitOptions,
+    ) -> BTreeMap<i32, SplitSet> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+
+        let mut strata: BTreeMap<i64, Vec<&Subject>> = BTreeMap::new();
+        match &options.stratify {
+            Some(Stratification::Trait(trait_id)) => {
+                let holders: HashSet<i32> = links
+                    .iter()
+                    .filter(|l| l.subject_trait_id == *trait_id)
+                    .map(|l| l.subject_id)
+                    .collect();
+
+                for s in subjects {
+                    let stratum = holders.contains(&s.id) as i64;
+                    strata.entry(stratum).or_default().push(s);
+                }
+            }
+            Some(Stratification::AgeBand(width)) => {
+                for s in subjects {
+                    let band = i64::from(s.age).div_euclid(i64::from(*width).max(1));
+                    strata.entry(band).or_default().push(s);
+                }
+            }
+            None => {
+                strata.insert(0, subjects.iter().collect());
+            }
+        }
+
+        let total = options.train + options.validation + options.test;
+        let cuts = [
+            options.train / total,
+            (options.train + options.validation) / total,
+        ];
+
+        let mut assignments = BTreeMap::new();
+        for stratum in strata.values() {
+            let mut ids = sorted_ids(stratum.iter().cloned());
+            ids.shuffle(&mut rng);
+
+            let n = ids.len() as f64;
+            let train_end = (n * cuts[0]).round() as usize;
+            let validation_end = (n * cuts[1]).round() as usize;
+
+            for (i, id) in ids.into_iter().enumerate() {
+                let set = match i {
+                    i if i < train_end => SplitSet::Train,
+                    i if i < validation_end => SplitSet::Validation,
+                    _ => SplitSet::Test,
+                };
+                assignments.insert(id, set);
+            }
+        }
+
+        assignments
+    }
+}
+
+/// Returns the unique IDs of subjects, sorted, so that draws don't depend on
+/// the order subjects were fetched in.
+fn sorted_ids<'a>(subjects: impl Iterator<Item = &'a Subject>) -> Vec<i32> {
+    let mut ids: Vec<i32> = subjects.map(|s| s.id).collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subjects(n: i32) -> Vec<Subject> {
+        (1..=n)
+            .map(|id| Subject {
+                id,
+                group_id: 1,
+                age: (id * 7 % 90) as i16,
+                length_of_stay: 2,
+                admitted_at: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reproducible_sample() {
+        let subjects = subjects(100);
+        let mut reversed = subjects.clone();
+        reversed.reverse();
+
+        let sample = Sampler::new(7).sample(&subjects, SampleSize::Fraction(0.1));
+        assert_eq!(sample.len(), 10);
+        assert_eq!(
+            Sampler::new(7).sample(&reversed, SampleSize::Count(10)),
+            sample
+        );
+        assert_ne!(
+            Sampler::new(8).sample(&subjects, SampleSize::Count(10)),
+            sample
+        );
+    }
+
+    #[test]
+    fn stratified_split() {
+        let subjects = subjects(40);
+        // Subjects 1 to 10 have trait 3.
+        let links: Vec<SubjectTraitLink> = (1..=10)
+            .map(|subject_id| SubjectTraitLink {
+                id: 0,
+                subject_id,
+                subject_trait_id: 3,
+                onset_at: None,
+                resolved_at: None,
+                value: None,
+                qualifier: None,
+            })
+            .collect();
+        let options = SplitOptions {
+            train: 0.6,
+            validation: 0.2,
+            test: 0.2,
+            stratify: Some(Stratification::Trait(3)),
+        };
+
+        let split = Sampler::new(1).split(&subjects, &links, &options);
+        assert_eq!(split.len(), 40);
+
+        let count = |set: SplitSet, ids: std::ops::RangeInclusive<i32>| {
+            ids.filter(|id| split[id] == set).count()
+        };
+        assert_eq!(count(SplitSet::Train, 1..=10), 6);
+        assert_eq!(count(SplitSet::Test, 1..=10), 2);
+        assert_eq!(count(SplitSet::Validation, 11..=40), 6);
+    }
+}
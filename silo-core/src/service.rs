@@ -1,4 +1,4 @@
-use crate::logging::log::Logger;
+use crate::logging::Logger;
 use actix::prelude::*;
 use log::info;
 
@@ -12,7 +12,7 @@ impl Service {
     /// Create and return a new Service.
     pub fn new() -> Self {
         Self {
-            logger: Logger::new(),
+            logger: Logger::default(),
         }
     }
 
@@ -25,3 +25,9 @@ impl Service {
         system.run().or(Err("error starting the actix system"))
     }
 }
+
+impl Default for Service {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,362 @@
+use crate::models::{Subject, SubjectTrait, SubjectTraitLink};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// The most bins a histogram may have.
+pub const MAX_BINS: f64 = 1000.0;
+
+/// A single bin of a histogram, covering values in `[lower, upper)`.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBin {
+    /// The inclusive lower bound of the bin.
+    pub lower: f64,
+    /// The exclusive upper bound of the bin.
+    pub upper: f64,
+    /// The number of values in the bin.
+    pub count: usize,
+}
+
+/// Summarises the distribution of a set of values.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Distribution {
+    /// The number of values.
+    pub count: usize,
+    /// The mean of the values.
+    pub mean: Option<f64>,
+    /// The median of the values.
+    pub median: Option<f64>,
+    /// The first quartile of the values.
+    pub q1: Option<f64>,
+    /// The third quartile of the values.
+    pub q3: Option<f64>,
+    /// The interquartile range of the values.
+    pub iqr: Option<f64>,
+    /// The smallest value.
+    pub min: Option<f64>,
+    /// The largest value.
+    pub max: Option<f64>,
+    /// A histogram of the values with fixed-width bins.
+    pub histogram: Vec<HistogramBin>,
+}
+
+impl Distribution {
+    /// Creates and returns a Distribution of values, binning the histogram by
+    /// `bin_width`.
+    pub fn new(values: &[f64], bin_width: f64) -> Self {
+        let mut sorted: Vec<f64> = values.iter().cloned().filter(|v| v.is_finite()).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let q1 = quantile(&sorted, 0.25);
+        let q3 = quantile(&sorted, 0.75);
+
+        Self {
+            count: sorted.len(),
+            mean: mean(&sorted),
+            median: quantile(&sorted, 0.5),
+            q1,
+            q3,
+            iqr: q1.and_then(|q1| q3.map(|q3| q3 - q1)),
+            min: sorted.first().cloned(),
+            max: sorted.last().cloned(),
+            histogram: histogram(&sorted, bin_width),
+        }
+    }
+}
+
+/// The prevalence of a single trait in a group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TraitPrevalence {
+    /// The ID of the trait.
+    pub trait_id: i32,
+    /// The name of the trait.
+    pub trait_name: String,
+    /// The number of subjects with the trait.
+    pub count: usize,
+    /// The proportion of subjects with the trait.
+    pub prevalence: f64,
+}
+
+/// Options for computing the descriptive statistics of a group.
+#[derive(Debug, Clone)]
+pub struct StatsOptions {
+    /// The width of each bin of the age histogram, in years.
+    pub age_bin_width: f64,
+    /// The width of each bin of the length of stay histogram, in days.
+    pub length_of_stay_bin_width: f64,
+}
+
+impl StatsOptions {
+    /// Checks that the histograms of subjects have positive bin widths and at
+    /// most MAX_BINS bins each.
+    pub fn validate(&self, subjects: &[Subject]) -> Result<(), String> {
+        let range = |values: Vec<i16>| {
+            let lower = values.iter().min().map_or(0.0, |v| f64::from(*v));
+            let upper = values.iter().max().map_or(0.0, |v| f64::from(*v));
+            (lower, upper)
+        };
+
+        let (lower, upper) = range(subjects.iter().map(|s| s.age).collect());
+        check_bins(lower, upper, self.age_bin_width)
+            .map_err(|message| format!("age: {}", message))?;
+
+        let (lower, upper) = range(subjects.iter().map(|s| s.length_of_stay).collect());
+        check_bins(lower, upper, self.length_of_stay_bin_width)
+            .map_err(|message| format!("length of stay: {}", message))
+    }
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        Self {
+            age_bin_width: 10.0,
+            length_of_stay_bin_width: 7.0,
+        }
+    }
+}
+
+/// Descriptive statistics of the subjects in a group.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupStats {
+    /// The ID of the group.
+    pub group_id: i32,
+    /// The number of subjects in the group.
+    pub subject_count: usize,
+    /// The distribution of the subjects' ages.
+    pub age: Distribution,
+    /// The distribution of the subjects' lengths of stay.
+    pub length_of_stay: Distribution,
+    /// The prevalence of every trait present in the group, most frequent first.
+    pub traits: Vec<TraitPrevalence>,
+}
+
+impl GroupStats {
+    /// Computes the statistics of a group from its subjects, the trait links of
+    /// those subjects and every trait.
+    pub fn new(
+        group_id: i32,
+        subjects: &[Subject],
+        links: &[SubjectTraitLink],
+        traits: &[SubjectTrait],
+        options: &StatsOptions,
+    ) -> Self {
+        let ages: Vec<f64> = subjects.iter().map(|s| s.age.into()).collect();
+        let stays: Vec<f64> = subjects.iter().map(|s| s.length_of_stay.into()).collect();
+
+        Self {
+            group_id,
+            subject_count: subjects.len(),
+            age: Distribution::new(&ages, options.age_bin_width),
+            length_of_stay: Distribution::new(&stays, options.length_of_stay_bin_width),
+            traits: trait_prevalences(subjects, links, traits),
+        }
+    }
+}
+
+/// Returns the IDs of the subjects with each trait, keyed by trait ID. Links of
+/// other subjects are ignored.
+pub fn trait_holders(
+    subjects: &[Subject],
+    links: &[SubjectTraitLink],
+) -> HashMap<i32, HashSet<i32>> {
+    let subject_ids: HashSet<i32> = subjects.iter().map(|s| s.id).collect();
+
+    let mut holders: HashMap<i32, HashSet<i32>> = HashMap::new();
+    for link in links.iter().filter(|l| subject_ids.contains(&l.subject_id)) {
+        holders
+            .entry(link.subject_trait_id)
+            .or_default()
+            .insert(link.subject_id);
+    }
+
+    holders
+}
+
+/// Counts the subjects with each trait, sorted by count and then by name.
+pub fn trait_prevalences(
+    subjects: &[Subject],
+    links: &[SubjectTraitLink],
+    traits: &[SubjectTrait],
+) -> Vec<TraitPrevalence> {
+    let holders = trait_holders(subjects, links);
+
+    let mut prevalences: Vec<TraitPrevalence> = traits
+        .iter()
+        .filter_map(|t| {
+            let count = holders.get(&t.id)?.len();
+            Some(TraitPrevalence {
+                trait_id: t.id,
+                trait_name: t.trait_name.clone(),
+                count,
+                prevalence: count as f64 / subjects.len() as f64,
+            })
+        })
+        .collect();
+
+    prevalences.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.trait_name.cmp(&b.trait_name))
+    });
+
+    prevalences
+}
+
+/// Returns the mean of values.
+pub fn mean(values: &[f64]) -> Option<f64> {
+    match values.len() {
+        0 => None,
+        len => Some(values.iter().sum::<f64>() / len as f64),
+    }
+}
+
+/// Returns the quantile `q` of sorted values, interpolating linearly between the
+/// closest ranks.
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = q * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+/// Checks that a histogram of values from `lower` to `upper` has a positive,
+/// finite bin width and at most MAX_BINS bins.
+pub fn check_bins(lower: f64, upper: f64, bin_width: f64) -> Result<(), String> {
+    if !(bin_width.is_finite() && bin_width > 0.0) {
+        return Err("the bin width must be a positive number".into());
+    }
+
+    match (upper - lower) / bin_width <= MAX_BINS {
+        true => Ok(()),
+        false => Err(format!("the histogram may have at most {} bins", MAX_BINS)),
+    }
+}
+
+/// Returns a histogram of sorted values with bins of `bin_width` aligned to
+/// multiples of the width. Empty bins between the first and last are included.
+pub fn histogram(sorted: &[f64], bin_width: f64) -> Vec<HistogramBin> {
+    let (first, last) = match (sorted.first(), sorted.last()) {
+        (Some(first), Some(last)) if bin_width.is_finite() && bin_width > 0.0 => (*first, *last),
+        _ => return vec![],
+    };
+
+    let first_bin = (first / bin_width).floor() as i64;
+    let last_bin = (last / bin_width).floor() as i64;
+
+    let mut bins: Vec<HistogramBin> = (first_bin..=last_bin)
+        .map(|bin| HistogramBin {
+            lower: bin as f64 * bin_width,
+            upper: (bin + 1) as f64 * bin_width,
+            count: 0,
+        })
+        .collect();
+
+    for value in sorted {
+        let bin = (value / bin_width).floor() as i64 - first_bin;
+        bins[bin as usize].count += 1;
+    }
+
+    bins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject(id: i32, age: i16, length_of_stay: i16) -> Subject {
+        Subject {
+            id,
+            group_id: 1,
+            age,
+            length_of_stay,
+            admitted_at: None,
+        }
+    }
+
+    #[test]
+    fn bin_limits() {
+        let options = StatsOptions::default();
+        let subjects = vec![subject(1, 20, 2), subject(2, 80, 30)];
+        assert!(options.validate(&subjects).is_ok());
+
+        for width in &[0.0, -1.0, f64::NAN, f64::INFINITY, 0.000001] {
+            let options = StatsOptions {
+                age_bin_width: *width,
+                ..StatsOptions::default()
+            };
+            assert!(options.validate(&subjects).is_err());
+        }
+    }
+
+    #[test]
+    fn distribution() {
+        let d = Distribution::new(&[1.0, 2.0, 3.0, 4.0, 15.0], 10.0);
+
+        assert_eq!(d.mean, Some(5.0));
+        assert_eq!(d.median, Some(3.0));
+        assert_eq!(d.iqr, Some(2.0));
+        assert_eq!(
+            d.histogram,
+            vec![
+                HistogramBin {
+                    lower: 0.0,
+                    upper: 10.0,
+                    count: 4
+                },
+                HistogramBin {
+                    lower: 10.0,
+                    upper: 20.0,
+                    count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn group_trait_prevalence() {
+        let subjects = vec![subject(1, 30, 2), subject(2, 50, 4)];
+        let traits = vec![
+            SubjectTrait {
+                id: 10,
+                parent_id: 0,
+                trait_name: "asthma".into(),
+            },
+            SubjectTrait {
+                id: 11,
+                parent_id: 0,
+                trait_name: "copd".into(),
+            },
+        ];
+        let link = |id, subject_id, subject_trait_id| SubjectTraitLink {
+            id,
+            subject_id,
+            subject_trait_id,
+            onset_at: None,
+            resolved_at: None,
+            value: None,
+            qualifier: None,
+        };
+        let links = vec![
+            link(1, 1, 11),
+            link(2, 2, 11),
+            link(3, 2, 10),
+            link(4, 2, 10),
+        ];
+
+        let stats = GroupStats::new(1, &subjects, &links, &traits, &StatsOptions::default());
+
+        assert_eq!(stats.subject_count, 2);
+        assert_eq!(stats.traits[0].trait_name, "copd");
+        assert_eq!(stats.traits[0].prevalence, 1.0);
+        assert_eq!(stats.traits[1].count, 1);
+    }
+}
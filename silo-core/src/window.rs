@@ -0,0 +1,167 @@
+use crate::models::{Subject, SubjectTraitLink};
+use chrono::{DateTime, Duration, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The start and end of a window of time, either of which may be open.
+type Bounds = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// A window of time a trait must apply in for a subject to hold it. A trait
+/// applies from its onset until its resolution. A link without an onset is
+/// taken to have always applied, and a link without a resolution to still apply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeWindow {
+    /// Matches traits that applied when the subject was admitted.
+    AtAdmission,
+    /// Matches traits that applied at any time within this many days of the
+    /// subject's admission.
+    WithinDays(i64),
+    /// Matches traits that applied at any time between two times. Either end may
+    /// be left open.
+    Between {
+        /// The start of the window, if any.
+        #[serde(default)]
+        from: Option<DateTime<Utc>>,
+        /// The end of the window, if any.
+        #[serde(default)]
+        to: Option<DateTime<Utc>>,
+    },
+}
+
+impl TimeWindow {
+    /// Checks that the window isn't empty.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TimeWindow::WithinDays(days) if *days < 0 => {
+                Err("the window must be a non-negative number of days".into())
+            }
+            TimeWindow::Between {
+                from: Some(from),
+                to: Some(to),
+            } if to < from => Err("the window must end after it starts".into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the start and end of the window for a subject, or None if the
+    /// window is relative to an admission the subject doesn't have.
+    fn bounds(&self, subject: &Subject) -> Option<Bounds> {
+        match self {
+            TimeWindow::AtAdmission => subject.admitted_at.map(|at| (Some(at), Some(at))),
+            TimeWindow::WithinDays(days) => subject
+                .admitted_at
+                .map(|at| (Some(at), Some(at + Duration::days(*days)))),
+            TimeWindow::Between { from, to } => Some((*from, *to)),
+        }
+    }
+
+    /// Returns whether or not a trait link of a subject applied within the
+    /// window. Links of subjects without an admission never match windows
+    /// relative to admission.
+    pub fn contains(&self, subject: &Subject, link: &SubjectTraitLink) -> bool {
+        let (from, to) = match self.bounds(subject) {
+            Some(bounds) => bounds,
+            None => return false,
+        };
+
+        let started = match (link.onset_at, to) {
+            (Some(onset), Some(to)) => onset <= to,
+            _ => true,
+        };
+        // A trait resolved at the start of the window no longer applies in it.
+        let ongoing = match (link.resolved_at, from) {
+            (Some(resolved), Some(from)) => resolved > from,
+            _ => true,
+        };
+
+        started && ongoing
+    }
+
+    /// Returns the trait links of the given subjects that applied within the
+    /// window.
+    pub fn filter(
+        &self,
+        subjects: &[Subject],
+        links: &[SubjectTraitLink],
+    ) -> Vec<SubjectTraitLink> {
+        let by_id: HashMap<i32, &Subject> = subjects.iter().map(|s| (s.id, s)).collect();
+
+        links
+            .iter()
+            .filter(|l| match by_id.get(&l.subject_id) {
+                Some(subject) => self.contains(subject, l),
+                None => false,
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn windows() {
+        let admitted_at = Utc.with_ymd_and_hms(2020, 3, 1, 12, 0, 0).unwrap();
+        let subjects = vec![
+            Subject {
+                id: 1,
+                group_id: 1,
+                age: 50,
+                length_of_stay: 4,
+                admitted_at: Some(admitted_at),
+            },
+            Subject {
+                id: 2,
+                group_id: 1,
+                age: 60,
+                length_of_stay: 2,
+                admitted_at: None,
+            },
+        ];
+        let link = |id, subject_id, onset_days: Option<i64>, resolved_days: Option<i64>| {
+            SubjectTraitLink {
+                id,
+                subject_id,
+                subject_trait_id: 1,
+                onset_at: onset_days.map(|d| admitted_at + Duration::days(d)),
+                resolved_at: resolved_days.map(|d| admitted_at + Duration::days(d)),
+                value: None,
+                qualifier: None,
+            }
+        };
+        let links = vec![
+            // Present before admission and never resolved.
+            link(1, 1, Some(-400), None),
+            // Resolved before admission.
+            link(2, 1, Some(-30), Some(-2)),
+            // Started 10 days after admission.
+            link(3, 1, Some(10), Some(20)),
+            // Untimed.
+            link(4, 1, None, None),
+            link(5, 2, None, None),
+        ];
+        let ids = |window: TimeWindow| -> Vec<i32> {
+            window
+                .filter(&subjects, &links)
+                .iter()
+                .map(|l| l.id)
+                .collect()
+        };
+
+        assert_eq!(ids(TimeWindow::AtAdmission), vec![1, 4]);
+        assert_eq!(ids(TimeWindow::WithinDays(30)), vec![1, 3, 4]);
+        assert_eq!(
+            ids(TimeWindow::Between {
+                from: Some(admitted_at - Duration::days(10)),
+                to: Some(admitted_at - Duration::days(5)),
+            }),
+            vec![1, 2, 4, 5]
+        );
+        assert!(TimeWindow::WithinDays(-1).validate().is_err());
+    }
+}
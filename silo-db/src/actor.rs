@@ -1,14 +1,12 @@
 use crate::errors::*;
-use crate::models::*;
 use crate::service::Service;
 use actix::prelude::*;
-use async_trait::async_trait;
 use silo_core::models;
-use tokio::task;
+use std::sync::Arc;
 
-/// Actix actor for the DB.
+/// Actix actor for the DB, through which other actors call the Service.
 pub struct DbActor {
-    service: &'static dyn Service,
+    service: Arc<dyn Service>,
 }
 
 impl Actor for DbActor {
@@ -17,52 +15,687 @@ impl Actor for DbActor {
 
 impl DbActor {
     /// Creates and returns a DbActor from a Service.
-    pub fn new(service: &'static dyn Service) -> Self {
+    pub fn new(service: Arc<dyn Service>) -> Self {
         Self { service }
     }
 }
 
-/// Inserts a Subject into the DB.
-#[derive(Message)]
+/// Implements the handler of each message, which calls a method of the Service
+/// with the fields of the message.
+macro_rules! service_handlers {
+    ($($message:ty => $result:ty, |$service:ident, $msg:ident| $call:expr;)*) => {$(
+        impl Handler<$message> for DbActor {
+            type Result = ResponseFuture<Result<$result, DatabaseError>>;
+
+            fn handle(&mut self, $msg: $message, _: &mut Self::Context) -> Self::Result {
+                let $service = self.service.clone();
+
+                Box::pin(async move { $call.await })
+            }
+        }
+    )*};
+}
+
+/// Inserts a SubjectTrait into the database.
+#[derive(Debug, Clone, Message)]
 #[rtype(result = "Result<i32, DatabaseError>")]
-pub struct InsertSubject(&'static models::Subject);
+pub struct InsertSubjectTrait(pub models::SubjectTrait);
 
-impl Handler<InsertSubject> for DbActor {
-    type Result = ResponseFuture<Result<i32, DatabaseError>>;
+/// Inserts a Subject into the database.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<i32, DatabaseError>")]
+pub struct InsertSubject(pub models::Subject);
 
-    fn handle(&mut self, msg: InsertSubject, ctx: &mut Self::Context) -> Self::Result {
-        let query = self.service.insert_subject(msg.0);
+/// Inserts a Group into the database.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<i32, DatabaseError>")]
+pub struct InsertGroup(pub models::Group);
 
-        Box::pin(async move { query.await })
-    }
+/// Adds a SubjectTrait to a Subject from a SubjectTraitLink, with its timing,
+/// value and qualifier, if any.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<i32, DatabaseError>")]
+pub struct InsertSubjectSubjectTrait(pub models::SubjectTraitLink);
+
+/// Finds all traits.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::SubjectTrait>, DatabaseError>")]
+pub struct GetTraits;
+
+/// Finds all groups.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::Group>, DatabaseError>")]
+pub struct GetGroups;
+
+/// Finds all subject traits for a subject by ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::SubjectTrait>, DatabaseError>")]
+pub struct FindSubjectTraitsBySubjectId(pub i32);
+
+/// Finds a single Group by ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::Group>, DatabaseError>")]
+pub struct FindGroupById(pub i32);
+
+/// Finds a single Subject by ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::Subject>, DatabaseError>")]
+pub struct FindSubjectById(pub i32);
+
+/// Finds a Subject by its ID if it is in a Group, either directly or as a
+/// member.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::Subject>, DatabaseError>")]
+pub struct FindSubjectInGroup {
+    /// The ID of the group.
+    pub group_id: i32,
+    /// The ID of the subject.
+    pub subject_id: i32,
 }
 
-/// Inserts a SubjectTrait into the DB.
-#[derive(Message)]
+/// Finds all subjects in a single Group by its ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::Subject>, DatabaseError>")]
+pub struct FindSubjectsByGroupId(pub i32);
+
+/// Finds a single SubjectTrait by ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::SubjectTrait>, DatabaseError>")]
+pub struct FindSubjectTraitById(pub i32);
+
+/// Finds a single SubjectTrait by trait name.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::SubjectTrait>, DatabaseError>")]
+pub struct FindSubjectTraitByName(pub String);
+
+/// Finds the trait links of every subject in a single Group by its ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::SubjectTraitLink>, DatabaseError>")]
+pub struct FindSubjectTraitLinksByGroupId(pub i32);
+
+/// Inserts a Cohort into the database.
+#[derive(Debug, Clone, Message)]
 #[rtype(result = "Result<i32, DatabaseError>")]
-pub struct InsertSubjectTrait(&'static models::SubjectTrait);
+pub struct InsertCohort(pub models::Cohort);
 
-impl Handler<InsertSubjectTrait> for DbActor {
-    type Result = ResponseFuture<Result<i32, DatabaseError>>;
+/// Updates an existing Cohort.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<(), DatabaseError>")]
+pub struct UpdateCohort(pub models::Cohort);
 
-    fn handle(&mut self, msg: InsertSubjectTrait, ctx: &mut Self::Context) -> Self::Result {
-        let query = self.service.insert_subject_trait(msg.0);
+/// Finds all cohorts.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::Cohort>, DatabaseError>")]
+pub struct GetCohorts;
 
-        Box::pin(async move { query.await })
-    }
+/// Finds a single Cohort by ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::Cohort>, DatabaseError>")]
+pub struct FindCohortById(pub i32);
+
+/// Deletes a Cohort and its materialisation records by ID. Materialised groups
+/// are kept. Returns whether or not the cohort existed.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<bool, DatabaseError>")]
+pub struct DeleteCohort(pub i32);
+
+/// Inserts a CohortMaterialisation with the subjects it adds to its Group.
+/// The members of earlier materialisations are kept, but only those of the
+/// latest one are members of the Group.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<i32, DatabaseError>")]
+pub struct InsertCohortMaterialisation {
+    /// The materialisation.
+    pub materialisation: models::CohortMaterialisation,
+    /// The IDs of the subjects it adds.
+    pub subject_ids: Vec<i32>,
 }
 
-/// Inserts a Group into the DB.
-#[derive(Message)]
+/// Finds every materialisation of a Cohort by its ID, oldest first.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::CohortMaterialisation>, DatabaseError>")]
+pub struct FindCohortMaterialisationsByCohortId(pub i32);
+
+/// Inserts a new Group for each set of subjects, with the subjects as its
+/// members. Either every Group is inserted or none is.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<i32>, DatabaseError>")]
+pub struct InsertGroupsWithMembers(pub Vec<Vec<i32>>);
+
+/// Updates the attributes of an existing Subject.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<(), DatabaseError>")]
+pub struct UpdateSubject(pub models::Subject);
+
+/// Inserts a Subject, or updates the Subject of its Group with any of the
+/// given external IDs. External IDs the Subject doesn't have yet are added.
+/// Returns the ID of the Subject and whether or not it was inserted.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<(i32, bool), DatabaseError>")]
+pub struct UpsertSubject {
+    /// The subject.
+    pub subject: models::Subject,
+    /// The external IDs of the subject.
+    pub external_ids: Vec<models::ExternalId>,
+}
+
+/// Inserts an ExternalId into the database.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<i32, DatabaseError>")]
+pub struct InsertExternalId(pub models::ExternalId);
+
+/// Finds all external IDs.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::ExternalId>, DatabaseError>")]
+pub struct GetExternalIds;
+
+/// Finds all external IDs of a Subject by its ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::ExternalId>, DatabaseError>")]
+pub struct FindExternalIdsBySubjectId(pub i32);
+
+/// Finds the Subject of a Group with an external ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::Subject>, DatabaseError>")]
+pub struct FindSubjectByExternalId {
+    /// The ID of the group.
+    pub group_id: i32,
+    /// The namespace of the external ID.
+    pub namespace: String,
+    /// The value of the external ID.
+    pub value: String,
+}
+
+/// Inserts a PseudonymExport and the tokens made for it, returning the ID of
+/// the export.
+#[derive(Debug, Clone, Message)]
 #[rtype(result = "Result<i32, DatabaseError>")]
-pub struct InsertGroup(&'static models::Group);
+pub struct InsertPseudonymExport {
+    /// The export.
+    pub export: models::PseudonymExport,
+    /// The pseudonym of each subject in the export.
+    pub pseudonyms: Vec<models::Pseudonym>,
+}
+
+/// Finds every token made for a PseudonymExport by its ID.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::Pseudonym>, DatabaseError>")]
+pub struct FindPseudonymsByExportId(pub i32);
+
+/// Finds the pseudonyms with any of the given tokens, from every export.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<models::Pseudonym>, DatabaseError>")]
+pub struct FindPseudonymsByTokens(pub Vec<String>);
+
+/// Sets the total epsilon of an API key's PrivacyBudget, creating the budget if
+/// the key doesn't have one. The epsilon already spent is kept.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<models::PrivacyBudget, DatabaseError>")]
+pub struct SetPrivacyBudget {
+    /// The API key.
+    pub api_key: String,
+    /// The total epsilon the key may spend.
+    pub epsilon_total: f64,
+}
+
+/// Finds the PrivacyBudget of an API key.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<models::PrivacyBudget>, DatabaseError>")]
+pub struct FindPrivacyBudgetByApiKey(pub String);
+
+/// Spends epsilon from an API key's PrivacyBudget. Returns false without
+/// spending anything if the key has no budget or too little of it left.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<bool, DatabaseError>")]
+pub struct SpendPrivacyBudget {
+    /// The API key.
+    pub api_key: String,
+    /// The epsilon to spend.
+    pub epsilon: f64,
+}
+
+/// Counts the subjects, traits and groups stored.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<models::EntityCounts, DatabaseError>")]
+pub struct CountEntities;
+
+/// Checks that the database responds to queries.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<(), DatabaseError>")]
+pub struct Ping;
+
+/// Returns the migrations applied to the database and those known to this
+/// build.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<models::MigrationStatus, DatabaseError>")]
+pub struct GetMigrationStatus;
+
+/// Returns how many connections of the pool are in use and how many it opens
+/// at most.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<models::PoolState, DatabaseError>")]
+pub struct GetPoolState;
+
+service_handlers! {
+    InsertSubjectTrait => i32, |service, msg| service.insert_subject_trait(&msg.0);
+    InsertSubject => i32, |service, msg| service.insert_subject(&msg.0);
+    InsertGroup => i32, |service, msg| service.insert_group(&msg.0);
+    InsertSubjectSubjectTrait => i32, |service, msg| service.insert_subject_subject_trait(&msg.0);
+    GetTraits => Vec<models::SubjectTrait>, |service, _msg| service.get_traits();
+    GetGroups => Vec<models::Group>, |service, _msg| service.get_groups();
+    FindSubjectTraitsBySubjectId => Vec<models::SubjectTrait>, |service, msg| service.find_subject_trats_by_subject_id(msg.0);
+    FindGroupById => Option<models::Group>, |service, msg| service.find_group_by_id(msg.0);
+    FindSubjectById => Option<models::Subject>, |service, msg| service.find_subject_by_id(msg.0);
+    FindSubjectInGroup => Option<models::Subject>, |service, msg| service.find_subject_in_group(msg.group_id, msg.subject_id);
+    FindSubjectsByGroupId => Vec<models::Subject>, |service, msg| service.find_subjects_by_group_id(msg.0);
+    FindSubjectTraitById => Option<models::SubjectTrait>, |service, msg| service.find_subject_trait_by_id(msg.0);
+    FindSubjectTraitByName => Option<models::SubjectTrait>, |service, msg| service.find_subject_trait_by_name(&msg.0);
+    FindSubjectTraitLinksByGroupId => Vec<models::SubjectTraitLink>, |service, msg| service.find_subject_trait_links_by_group_id(msg.0);
+    InsertCohort => i32, |service, msg| service.insert_cohort(&msg.0);
+    UpdateCohort => (), |service, msg| service.update_cohort(&msg.0);
+    GetCohorts => Vec<models::Cohort>, |service, _msg| service.get_cohorts();
+    FindCohortById => Option<models::Cohort>, |service, msg| service.find_cohort_by_id(msg.0);
+    DeleteCohort => bool, |service, msg| service.delete_cohort(msg.0);
+    InsertCohortMaterialisation => i32, |service, msg| service.insert_cohort_materialisation(&msg.materialisation, &msg.subject_ids);
+    FindCohortMaterialisationsByCohortId => Vec<models::CohortMaterialisation>, |service, msg| service.find_cohort_materialisations_by_cohort_id(msg.0);
+    InsertGroupsWithMembers => Vec<i32>, |service, msg| service.insert_groups_with_members(&msg.0);
+    UpdateSubject => (), |service, msg| service.update_subject(&msg.0);
+    UpsertSubject => (i32, bool), |service, msg| service.upsert_subject(&msg.subject, &msg.external_ids);
+    InsertExternalId => i32, |service, msg| service.insert_external_id(&msg.0);
+    GetExternalIds => Vec<models::ExternalId>, |service, _msg| service.get_external_ids();
+    FindExternalIdsBySubjectId => Vec<models::ExternalId>, |service, msg| service.find_external_ids_by_subject_id(msg.0);
+    FindSubjectByExternalId => Option<models::Subject>, |service, msg| service.find_subject_by_external_id(msg.group_id, &msg.namespace, &msg.value);
+    InsertPseudonymExport => i32, |service, msg| service.insert_pseudonym_export(&msg.export, &msg.pseudonyms);
+    FindPseudonymsByExportId => Vec<models::Pseudonym>, |service, msg| service.find_pseudonyms_by_export_id(msg.0);
+    FindPseudonymsByTokens => Vec<models::Pseudonym>, |service, msg| service.find_pseudonyms_by_tokens(&msg.0);
+    SetPrivacyBudget => models::PrivacyBudget, |service, msg| service.set_privacy_budget(&msg.api_key, msg.epsilon_total);
+    FindPrivacyBudgetByApiKey => Option<models::PrivacyBudget>, |service, msg| service.find_privacy_budget_by_api_key(&msg.0);
+    SpendPrivacyBudget => bool, |service, msg| service.spend_privacy_budget(&msg.api_key, msg.epsilon);
+    CountEntities => models::EntityCounts, |service, _msg| service.count_entities();
+    Ping => (), |service, _msg| service.ping();
+    GetMigrationStatus => models::MigrationStatus, |service, _msg| service.migration_status();
+    GetPoolState => models::PoolState, |service, _msg| service.pool_state();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// A Service which records every call and returns empty results, or its
+    /// error if it has one.
+    #[derive(Default)]
+    struct MockService {
+        calls: Mutex<Vec<String>>,
+        error: Option<DatabaseError>,
+    }
+
+    impl MockService {
+        /// Creates and returns a MockService failing every call with the error.
+        fn failing(error: DatabaseError) -> Self {
+            Self {
+                error: Some(error),
+                ..Self::default()
+            }
+        }
+
+        /// Records a call and returns the value, or the error of the mock.
+        fn respond<T>(&self, call: String, value: T) -> Result<T, DatabaseError> {
+            self.calls.lock().unwrap().push(call);
+
+            match &self.error {
+                Some(error) => Err(error.clone()),
+                None => Ok(value),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Service for MockService {
+        async fn insert_subject_trait(
+            &self,
+            subject_trait: &models::SubjectTrait,
+        ) -> Result<i32, DatabaseError> {
+            self.respond(format!("insert_subject_trait {:?}", subject_trait), 0)
+        }
+
+        async fn insert_subject(&self, subject: &models::Subject) -> Result<i32, DatabaseError> {
+            self.respond(format!("insert_subject {:?}", subject), 0)
+        }
+
+        async fn insert_group(&self, group: &models::Group) -> Result<i32, DatabaseError> {
+            self.respond(format!("insert_group {:?}", group), 0)
+        }
+
+        async fn insert_subject_subject_trait(
+            &self,
+            link: &models::SubjectTraitLink,
+        ) -> Result<i32, DatabaseError> {
+            self.respond(format!("insert_subject_subject_trait {:?}", link), 0)
+        }
+
+        async fn get_traits(&self) -> Result<Vec<models::SubjectTrait>, DatabaseError> {
+            self.respond("get_traits".to_string(), Vec::new())
+        }
+
+        async fn get_groups(&self) -> Result<Vec<models::Group>, DatabaseError> {
+            self.respond("get_groups".to_string(), Vec::new())
+        }
+
+        async fn find_subject_trats_by_subject_id(
+            &self,
+            id: i32,
+        ) -> Result<Vec<models::SubjectTrait>, DatabaseError> {
+            self.respond(
+                format!("find_subject_trats_by_subject_id {:?}", id),
+                Vec::new(),
+            )
+        }
+
+        async fn find_group_by_id(&self, id: i32) -> Result<Option<models::Group>, DatabaseError> {
+            self.respond(format!("find_group_by_id {:?}", id), None)
+        }
+
+        async fn find_subject_by_id(
+            &self,
+            id: i32,
+        ) -> Result<Option<models::Subject>, DatabaseError> {
+            self.respond(format!("find_subject_by_id {:?}", id), None)
+        }
+
+        async fn find_subject_in_group(
+            &self,
+            group_id: i32,
+            subject_id: i32,
+        ) -> Result<Option<models::Subject>, DatabaseError> {
+            self.respond(
+                format!("find_subject_in_group {:?}", (group_id, subject_id)),
+                None,
+            )
+        }
+
+        async fn find_subjects_by_group_id(
+            &self,
+            id: i32,
+        ) -> Result<Vec<models::Subject>, DatabaseError> {
+            self.respond(format!("find_subjects_by_group_id {:?}", id), Vec::new())
+        }
+
+        async fn find_subject_trait_by_id(
+            &self,
+            id: i32,
+        ) -> Result<Option<models::SubjectTrait>, DatabaseError> {
+            self.respond(format!("find_subject_trait_by_id {:?}", id), None)
+        }
+
+        async fn find_subject_trait_by_name(
+            &self,
+            trait_name: &str,
+        ) -> Result<Option<models::SubjectTrait>, DatabaseError> {
+            self.respond(format!("find_subject_trait_by_name {:?}", trait_name), None)
+        }
+
+        async fn find_subject_trait_links_by_group_id(
+            &self,
+            id: i32,
+        ) -> Result<Vec<models::SubjectTraitLink>, DatabaseError> {
+            self.respond(
+                format!("find_subject_trait_links_by_group_id {:?}", id),
+                Vec::new(),
+            )
+        }
+
+        async fn insert_cohort(&self, cohort: &models::Cohort) -> Result<i32, DatabaseError> {
+            self.respond(format!("insert_cohort {:?}", cohort), 0)
+        }
+
+        async fn update_cohort(&self, cohort: &models::Cohort) -> Result<(), DatabaseError> {
+            self.respond(format!("update_cohort {:?}", cohort), ())
+        }
+
+        async fn get_cohorts(&self) -> Result<Vec<models::Cohort>, DatabaseError> {
+            self.respond("get_cohorts".to_string(), Vec::new())
+        }
+
+        async fn find_cohort_by_id(
+            &self,
+            id: i32,
+        ) -> Result<Option<models::Cohort>, DatabaseError> {
+            self.respond(format!("find_cohort_by_id {:?}", id), None)
+        }
+
+        async fn delete_cohort(&self, id: i32) -> Result<bool, DatabaseError> {
+            self.respond(format!("delete_cohort {:?}", id), false)
+        }
+
+        async fn insert_cohort_materialisation(
+            &self,
+            materialisation: &models::CohortMaterialisation,
+            subject_ids: &[i32],
+        ) -> Result<i32, DatabaseError> {
+            self.respond(
+                format!(
+                    "insert_cohort_materialisation {:?}",
+                    (materialisation, subject_ids)
+                ),
+                0,
+            )
+        }
+
+        async fn find_cohort_materialisations_by_cohort_id(
+            &self,
+            id: i32,
+        ) -> Result<Vec<models::CohortMaterialisation>, DatabaseError> {
+            self.respond(
+                format!("find_cohort_materialisations_by_cohort_id {:?}", id),
+                Vec::new(),
+            )
+        }
+
+        async fn insert_groups_with_members(
+            &self,
+            members: &[Vec<i32>],
+        ) -> Result<Vec<i32>, DatabaseError> {
+            self.respond(
+                format!("insert_groups_with_members {:?}", members),
+                (1..=members.len() as i32).collect(),
+            )
+        }
+
+        async fn update_subject(&self, subject: &models::Subject) -> Result<(), DatabaseError> {
+            self.respond(format!("update_subject {:?}", subject), ())
+        }
+
+        async fn upsert_subject(
+            &self,
+            subject: &models::Subject,
+            external_ids: &[models::ExternalId],
+        ) -> Result<(i32, bool), DatabaseError> {
+            self.respond(
+                format!("upsert_subject {:?}", (subject, external_ids)),
+                (0, false),
+            )
+        }
+
+        async fn insert_external_id(
+            &self,
+            external_id: &models::ExternalId,
+        ) -> Result<i32, DatabaseError> {
+            self.respond(format!("insert_external_id {:?}", external_id), 0)
+        }
+
+        async fn get_external_ids(&self) -> Result<Vec<models::ExternalId>, DatabaseError> {
+            self.respond("get_external_ids".to_string(), Vec::new())
+        }
+
+        async fn find_external_ids_by_subject_id(
+            &self,
+            id: i32,
+        ) -> Result<Vec<models::ExternalId>, DatabaseError> {
+            self.respond(
+                format!("find_external_ids_by_subject_id {:?}", id),
+                Vec::new(),
+            )
+        }
+
+        async fn find_subject_by_external_id(
+            &self,
+            group_id: i32,
+            namespace: &str,
+            value: &str,
+        ) -> Result<Option<models::Subject>, DatabaseError> {
+            self.respond(
+                format!(
+                    "find_subject_by_external_id {:?}",
+                    (group_id, namespace, value)
+                ),
+                None,
+            )
+        }
+
+        async fn insert_pseudonym_export(
+            &self,
+            export: &models::PseudonymExport,
+            pseudonyms: &[models::Pseudonym],
+        ) -> Result<i32, DatabaseError> {
+            self.respond(
+                format!("insert_pseudonym_export {:?}", (export, pseudonyms)),
+                0,
+            )
+        }
+
+        async fn find_pseudonyms_by_export_id(
+            &self,
+            id: i32,
+        ) -> Result<Vec<models::Pseudonym>, DatabaseError> {
+            self.respond(format!("find_pseudonyms_by_export_id {:?}", id), Vec::new())
+        }
+
+        async fn find_pseudonyms_by_tokens(
+            &self,
+            tokens: &[String],
+        ) -> Result<Vec<models::Pseudonym>, DatabaseError> {
+            self.respond(
+                format!("find_pseudonyms_by_tokens {:?}", tokens),
+                Vec::new(),
+            )
+        }
+
+        async fn set_privacy_budget(
+            &self,
+            api_key: &str,
+            epsilon_total: f64,
+        ) -> Result<models::PrivacyBudget, DatabaseError> {
+            self.respond(
+                format!("set_privacy_budget {:?}", (api_key, epsilon_total)),
+                models::PrivacyBudget {
+                    id: 0,
+                    api_key: api_key.to_string(),
+                    epsilon_total,
+                    epsilon_spent: 0.0,
+                    updated_at: chrono::Utc::now(),
+                },
+            )
+        }
+
+        async fn find_privacy_budget_by_api_key(
+            &self,
+            api_key: &str,
+        ) -> Result<Option<models::PrivacyBudget>, DatabaseError> {
+            self.respond(
+                format!("find_privacy_budget_by_api_key {:?}", api_key),
+                None,
+            )
+        }
+
+        async fn spend_privacy_budget(
+            &self,
+            api_key: &str,
+            epsilon: f64,
+        ) -> Result<bool, DatabaseError> {
+            self.respond(
+                format!("spend_privacy_budget {:?}", (api_key, epsilon)),
+                false,
+            )
+        }
+
+        async fn count_entities(&self) -> Result<models::EntityCounts, DatabaseError> {
+            self.respond(
+                "count_entities".to_string(),
+                models::EntityCounts::default(),
+            )
+        }
+
+        async fn ping(&self) -> Result<(), DatabaseError> {
+            self.respond("ping".to_string(), ())
+        }
+
+        async fn migration_status(&self) -> Result<models::MigrationStatus, DatabaseError> {
+            self.respond(
+                "migration_status".to_string(),
+                models::MigrationStatus {
+                    applied: None,
+                    latest: 0,
+                },
+            )
+        }
+
+        async fn pool_state(&self) -> Result<models::PoolState, DatabaseError> {
+            self.respond("pool_state".to_string(), models::PoolState::default())
+        }
+    }
+
+    #[actix_rt::test]
+    async fn messages_call_the_service() {
+        let service = Arc::new(MockService::default());
+        let db = DbActor::new(service.clone()).start();
+
+        let group = db.send(FindGroupById(7)).await.unwrap().unwrap();
+        assert!(group.is_none());
+        let subject = db
+            .send(FindSubjectInGroup {
+                group_id: 7,
+                subject_id: 3,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(subject.is_none());
+        let budget = db
+            .send(SetPrivacyBudget {
+                api_key: "key".to_string(),
+                epsilon_total: 2.0,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(budget.epsilon_total, 2.0);
+
+        assert_eq!(
+            *service.calls.lock().unwrap(),
+            vec![
+                "find_group_by_id 7",
+                "find_subject_in_group (7, 3)",
+                "set_privacy_budget (\"key\", 2.0)",
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn service_errors_are_returned() {
+        let service = MockService::failing(DatabaseError::Unavailable("restarting".to_string()));
+        let db = DbActor::new(Arc::new(service)).start();
 
-impl Handler<InsertGroup> for DbActor {
-    type Result = ResponseFuture<Result<i32, DatabaseError>>;
+        let error = db.send(Ping).await.unwrap().unwrap_err();
+        assert!(error.is_unavailable());
+        let error = db.send(GetTraits).await.unwrap().unwrap_err();
+        assert!(error.is_unavailable());
 
-    fn handle(&mut self, msg: InsertGroup, ctx: &mut Self::Context) -> Self::Result {
-        let query = self.service.insert_group(msg.0);
+        let service = MockService::failing(DatabaseError::Query("syntax".to_string()));
+        let db = DbActor::new(Arc::new(service)).start();
 
-        Box::pin(async move { query.await })
+        let error = db.send(CountEntities).await.unwrap().unwrap_err();
+        assert!(!error.is_unavailable());
     }
 }
@@ -1,3 +1,8 @@
+use std::time::Duration;
+
+/// The default maximum number of connections in the pool.
+pub const DEFAULT_POOL_SIZE: u64 = 50;
+
 /// DatabaseConfig contains database connection credentials and other configuration.
 #[derive(Debug)]
 pub struct DatabaseConfig {
@@ -11,4 +16,38 @@ pub struct DatabaseConfig {
     pub database_port: String,
     /// The name of the database.
     pub database_name: String,
+    /// The maximum number of connections in the pool.
+    pub pool_size: u64,
+}
+
+/// ConnectRetry configures how connecting to the database is retried, waiting
+/// twice as long after each failed attempt.
+#[derive(Debug, Clone)]
+pub struct ConnectRetry {
+    /// The number of attempts made before giving up.
+    pub attempts: u32,
+    /// The time waited after the first failed attempt.
+    pub initial_delay: Duration,
+    /// The longest time waited between attempts.
+    pub max_delay: Duration,
+}
+
+impl ConnectRetry {
+    /// Returns the time to wait after a failed attempt, counted from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+impl Default for ConnectRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 10,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
 }
@@ -1,4 +1,6 @@
+use log::{error, warn};
 use oxidizer::*;
+use silo_core::models;
 use tokio;
 
 use crate::config::*;
@@ -7,6 +9,11 @@ use crate::errors::*;
 use crate::migrations;
 
 /// Connection represents a connection to a database.
+///
+/// The connection is a pool which checks each connection as it is taken from
+/// the pool, replacing those that were closed, so it recovers by itself once a
+/// restarted database accepts connections again. Until then, calls fail with
+/// DatabaseError::Unavailable.
 pub struct Connection {
     /// The database connection object.
     pub db: DB,
@@ -16,11 +23,89 @@ impl Connection {
     /// Attempts to connect to a database and returns a Connection on success.
     pub async fn connect(config: &DatabaseConfig) -> Result<Self, ConnectionError> {
         let uri = postgres_conn_str(config);
-        match DB::connect(&uri, 50, None).await {
-            Ok(db) => Ok(Self { db }),
-            Err(_) => Err(ConnectionError("error connecting to database".into())),
+        let conn = match DB::connect(&uri, config.pool_size, None).await {
+            Ok(db) => Self { db },
+            Err(_) => return Err(ConnectionError("error connecting to database".into())),
+        };
+
+        // The pool opens connections lazily, so make sure one can be opened.
+        match conn.ping().await {
+            Ok(_) => Ok(conn),
+            Err(e) => Err(ConnectionError(format!(
+                "error connecting to database: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Attempts to connect to a database until it succeeds or runs out of
+    /// attempts, waiting longer after each failure.
+    pub async fn connect_with_retry(
+        config: &DatabaseConfig,
+        retry: &ConnectRetry,
+    ) -> Result<Self, ConnectionError> {
+        let mut attempt = 1;
+        loop {
+            match Self::connect(config).await {
+                Ok(conn) => return Ok(conn),
+                Err(e) if attempt >= retry.attempts => return Err(e),
+                Err(e) => {
+                    let delay = retry.delay(attempt);
+                    warn!(
+                        "{} (attempt {} of {}), retrying in {:?}",
+                        e, attempt, retry.attempts, delay
+                    );
+                    tokio::time::delay_for(delay).await;
+                    attempt += 1;
+                }
+            }
         }
     }
+
+    /// Checks that the database responds to queries.
+    pub async fn ping(&self) -> Result<(), DatabaseError> {
+        match self.db.execute("SELECT 1", &[]).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::from(e)),
+        }
+    }
+
+    /// Returns how many connections of the pool are in use and how many it
+    /// opens at most.
+    pub async fn pool_state(&self) -> models::PoolState {
+        let state = self.db.pool().state().await;
+
+        models::PoolState {
+            in_use: state.in_use as i64,
+            max_open: state.max_open as i64,
+        }
+    }
+
+    /// Returns the version of the last migration applied to the database, if
+    /// any has been.
+    pub async fn schema_version(&self) -> Result<Option<i64>, DatabaseError> {
+        let rows = self
+            .db
+            .query("SELECT MAX(version) FROM refinery_schema_history", &[])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(rows
+            .first()
+            .and_then(|row| row.get::<_, Option<i32>>(0))
+            .map(i64::from))
+    }
+
+    /// Returns the version of the last migration known to this build.
+    pub fn latest_schema_version() -> i64 {
+        migrations::runner()
+            .get_migrations()
+            .iter()
+            .map(|m| i64::from(m.version()))
+            .max()
+            .unwrap_or(0)
+    }
+
     /// Attempts to run migrations.
     pub async fn migrate(&self) -> Result<(), DatabaseError> {
         let runner = migrations::runner();
@@ -28,8 +113,8 @@ impl Connection {
         match self.db.migrate(runner).await {
             Ok(_) => Ok(()),
             Err(e) => {
-                println!("{:?}", e);
-                Err(DatabaseError("error running migrations".into()))
+                error!("{:?}", e);
+                Err(DatabaseError::Query("error running migrations".into()))
             }
         }
     }
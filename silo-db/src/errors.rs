@@ -12,10 +12,56 @@ impl Display for ConnectionError {
 
 /// DatabaseError is returned when a database operation fails.
 #[derive(Debug, Clone)]
-pub struct DatabaseError(pub String);
+pub enum DatabaseError {
+    /// The database could not be reached, such as while it restarts. The
+    /// operation may succeed if it is retried later.
+    Unavailable(String),
+    /// The operation conflicts with existing data, such as a value that must be
+    /// unique.
+    Conflict(String),
+    /// The operation itself failed.
+    Query(String),
+}
+
+impl DatabaseError {
+    /// Returns whether the database could not be reached.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(self, DatabaseError::Unavailable(_))
+    }
+
+    /// Returns whether the operation conflicts with existing data.
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, DatabaseError::Conflict(_))
+    }
+}
 
 impl Display for DatabaseError {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "{}", self.0)
+        match self {
+            DatabaseError::Unavailable(message) => {
+                write!(f, "the database is unavailable: {}", message)
+            }
+            DatabaseError::Conflict(message) => write!(f, "{}", message),
+            DatabaseError::Query(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<oxidizer::Error> for DatabaseError {
+    fn from(e: oxidizer::Error) -> Self {
+        match &e {
+            // The pool could neither reuse nor open a connection.
+            oxidizer::Error::MobcError(_) => DatabaseError::Unavailable(format!("{:?}", e)),
+            oxidizer::Error::PostgresError(pg) if pg.is_closed() => {
+                DatabaseError::Unavailable(format!("{:?}", e))
+            }
+            // A unique index was violated.
+            oxidizer::Error::PostgresError(pg)
+                if pg.code().map(|code| code.code()) == Some("23505") =>
+            {
+                DatabaseError::Conflict(format!("{:?}", e))
+            }
+            _ => DatabaseError::Query(format!("{:?}", e)),
+        }
     }
 }
@@ -1,6 +1,21 @@
 use oxidizer::create_migration_module;
 use oxidizer::entity::IEntity;
+use oxidizer::*;
 
-use crate::models::Subject;
+use crate::models::Group;
+
+/// The Subject entity as the table was first created. Columns added since are
+/// added by later migrations, so the SQL of this one must not change.
+#[derive(Entity, Default)]
+pub struct Subject {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Group", key = "id")]
+    pub group_id: i32,
+
+    pub age: i16,
+    pub length_of_stay: i16,
+}
 
 create_migration_module!(Subject);
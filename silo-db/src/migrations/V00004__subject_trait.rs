@@ -1,6 +1,21 @@
 use oxidizer::create_migration_module;
 use oxidizer::entity::IEntity;
+use oxidizer::*;
 
-use crate::models::SubjectSubjectTrait;
+use crate::models::{Subject, SubjectTrait};
+
+/// The join table of subjects and their traits when it was created, before
+/// links had timings, values or qualifiers.
+#[derive(Entity, Default)]
+pub struct SubjectSubjectTrait {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Subject", key = "id")]
+    pub subject_id: i32,
+
+    #[relation(model = "SubjectTrait", key = "id")]
+    pub subject_trait_id: i32,
+}
 
 create_migration_module!(SubjectSubjectTrait);
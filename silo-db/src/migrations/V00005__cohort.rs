@@ -0,0 +1,6 @@
+use oxidizer::create_migration_module;
+use oxidizer::entity::IEntity;
+
+use crate::models::Cohort;
+
+create_migration_module!(Cohort);
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use oxidizer::create_migration_module;
+use oxidizer::entity::IEntity;
+use oxidizer::*;
+
+use crate::models::{Cohort, Group};
+
+/// CohortMaterialisation as its table was created. The definition column is
+/// added by V00014.
+#[derive(Entity, Default)]
+pub struct CohortMaterialisation {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Cohort", key = "id")]
+    pub cohort_id: i32,
+    pub version: i32,
+
+    #[relation(model = "Group", key = "id")]
+    pub group_id: i32,
+    pub subject_count: i32,
+
+    pub materialised_at: DateTime<Utc>,
+}
+
+create_migration_module!(CohortMaterialisation);
@@ -0,0 +1,24 @@
+use oxidizer::create_migration_module;
+use oxidizer::entity::IEntity;
+use oxidizer::*;
+
+use crate::models::{Group, Subject};
+
+/// GroupMember as its table was created, when a subject could be a member of
+/// a Group only once. V00015 replaces the index.
+#[derive(Entity, Default)]
+#[index(name = "group_member_unique", columns = "group_id, subject_id", unique)]
+pub struct GroupMember {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Group", key = "id")]
+    pub group_id: i32,
+
+    #[relation(model = "Subject", key = "id")]
+    pub subject_id: i32,
+
+    pub materialisation_id: Option<i32>,
+}
+
+create_migration_module!(GroupMember);
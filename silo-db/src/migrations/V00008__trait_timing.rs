@@ -0,0 +1,9 @@
+/// Adds admission times to subjects and onset and resolution times to their
+/// traits.
+pub fn migration() -> String {
+    "ALTER TABLE subject ADD COLUMN admitted_at TIMESTAMPTZ; \
+     ALTER TABLE subject_subject_trait \
+     ADD COLUMN onset_at TIMESTAMPTZ, \
+     ADD COLUMN resolved_at TIMESTAMPTZ;"
+        .into()
+}
@@ -0,0 +1,7 @@
+/// Adds values and qualifiers to the traits of subjects.
+pub fn migration() -> String {
+    "ALTER TABLE subject_subject_trait \
+     ADD COLUMN value DOUBLE PRECISION, \
+     ADD COLUMN qualifier TEXT;"
+        .into()
+}
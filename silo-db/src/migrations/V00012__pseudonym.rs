@@ -0,0 +1,6 @@
+use oxidizer::create_migration_module;
+use oxidizer::entity::IEntity;
+
+use crate::models::Pseudonym;
+
+create_migration_module!(Pseudonym);
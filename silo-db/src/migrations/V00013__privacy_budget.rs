@@ -0,0 +1,6 @@
+use oxidizer::create_migration_module;
+use oxidizer::entity::IEntity;
+
+use crate::models::PrivacyBudget;
+
+create_migration_module!(PrivacyBudget);
@@ -0,0 +1,9 @@
+/// Records the definition each cohort materialisation was produced from.
+/// Materialisations of a cohort's current version are given its definition;
+/// earlier ones were overwritten and are left without one.
+pub fn migration() -> String {
+    "ALTER TABLE cohort_materialisation ADD COLUMN definition TEXT; \
+     UPDATE cohort_materialisation m SET definition = c.definition FROM cohort c \
+     WHERE m.cohort_id = c.id AND m.version = c.version;"
+        .into()
+}
@@ -0,0 +1,9 @@
+/// Keeps the members of every materialisation of a Group rather than only the
+/// latest, so a subject is unique within a materialisation instead of within
+/// the Group.
+pub fn migration() -> String {
+    "DROP INDEX group_member_unique; \
+     CREATE UNIQUE INDEX group_member_materialisation_unique \
+     ON group_member (materialisation_id, subject_id);"
+        .into()
+}
@@ -0,0 +1,13 @@
+/// Normalises the values of external IDs, which are now stored and looked up
+/// without surrounding whitespace and in lower case. Values that would then
+/// clash with another external ID of their Group are left as they are, and are
+/// reported by the duplicate check.
+pub fn migration() -> String {
+    "UPDATE subject_external_id e SET value = LOWER(BTRIM(e.value, E' \\t\\r\\n')) \
+     WHERE e.value <> LOWER(BTRIM(e.value, E' \\t\\r\\n')) AND NOT EXISTS ( \
+         SELECT 1 FROM subject_external_id o \
+         WHERE o.group_id = e.group_id AND o.namespace = e.namespace AND o.id <> e.id \
+         AND LOWER(BTRIM(o.value, E' \\t\\r\\n')) = LOWER(BTRIM(e.value, E' \\t\\r\\n')) \
+     );"
+    .into()
+}
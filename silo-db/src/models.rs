@@ -1,6 +1,10 @@
+use chrono::{DateTime, Utc};
 use oxidizer::*;
 
 use silo_core::models;
+use std::convert::TryFrom;
+
+use crate::errors::DatabaseError;
 
 /// Contains a single trait which can be applied to subjects.
 #[derive(Entity, Default)]
@@ -39,6 +43,8 @@ pub struct Subject {
 
     pub age: i16,
     pub length_of_stay: i16,
+
+    pub admitted_at: Option<DateTime<Utc>>,
 }
 
 impl From<&models::Subject> for Subject {
@@ -48,6 +54,7 @@ impl From<&models::Subject> for Subject {
             group_id: item.group_id,
             age: item.age,
             length_of_stay: item.length_of_stay,
+            admitted_at: item.admitted_at,
         }
     }
 }
@@ -78,4 +85,296 @@ pub struct SubjectSubjectTrait {
 
     #[relation(model = "SubjectTrait", key = "id")]
     pub subject_trait_id: i32,
+
+    /// The time the trait started to apply, if known.
+    pub onset_at: Option<DateTime<Utc>>,
+    /// The time the trait stopped applying, if it has.
+    pub resolved_at: Option<DateTime<Utc>>,
+
+    /// The value of the trait, if it has one.
+    pub value: Option<f64>,
+    /// The qualifier of the trait, if it has one.
+    pub qualifier: Option<String>,
+}
+
+impl From<&models::SubjectTraitLink> for SubjectSubjectTrait {
+    fn from(item: &models::SubjectTraitLink) -> Self {
+        Self {
+            id: item.id,
+            subject_id: item.subject_id,
+            subject_trait_id: item.subject_trait_id,
+            onset_at: item.onset_at,
+            resolved_at: item.resolved_at,
+            value: item.value,
+            qualifier: item.qualifier.clone(),
+        }
+    }
+}
+
+/// Cohort stores a saved cohort definition as JSON.
+#[derive(Entity, Default)]
+#[index(name = "cohort_name_unique", columns = "name", unique)]
+pub struct Cohort {
+    #[primary_key]
+    pub id: i32,
+
+    pub name: String,
+    pub description: Option<String>,
+
+    /// The CohortDefinition, serialized as JSON.
+    pub definition: String,
+    pub version: i32,
+
+    pub group_id: Option<i32>,
+
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<&models::Cohort> for Cohort {
+    type Error = DatabaseError;
+
+    fn try_from(item: &models::Cohort) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: item.id,
+            name: item.name.clone(),
+            description: item.description.clone(),
+            definition: serde_json::to_string(&item.definition)
+                .map_err(|e| DatabaseError::Query(format!("{:?}", e)))?,
+            version: item.version,
+            group_id: item.group_id,
+            created_at: item.created_at,
+            updated_at: item.updated_at,
+        })
+    }
+}
+
+impl TryFrom<&Cohort> for models::Cohort {
+    type Error = DatabaseError;
+
+    fn try_from(item: &Cohort) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: item.id,
+            name: item.name.clone(),
+            description: item.description.clone(),
+            definition: serde_json::from_str(&item.definition)
+                .map_err(|e| DatabaseError::Query(format!("{:?}", e)))?,
+            version: item.version,
+            group_id: item.group_id,
+            created_at: item.created_at,
+            updated_at: item.updated_at,
+        })
+    }
+}
+
+/// CohortMaterialisation records which version of a Cohort produced the
+/// membership of a Group.
+#[derive(Entity, Default)]
+pub struct CohortMaterialisation {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Cohort", key = "id")]
+    pub cohort_id: i32,
+    pub version: i32,
+
+    /// The materialised CohortDefinition, serialized as JSON.
+    pub definition: Option<String>,
+
+    #[relation(model = "Group", key = "id")]
+    pub group_id: i32,
+    pub subject_count: i32,
+
+    pub materialised_at: DateTime<Utc>,
+}
+
+impl TryFrom<&models::CohortMaterialisation> for CohortMaterialisation {
+    type Error = DatabaseError;
+
+    fn try_from(item: &models::CohortMaterialisation) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: item.id,
+            cohort_id: item.cohort_id,
+            version: item.version,
+            definition: item
+                .definition
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()
+                .map_err(|e| DatabaseError::Query(format!("{:?}", e)))?,
+            group_id: item.group_id,
+            subject_count: item.subject_count,
+            materialised_at: item.materialised_at,
+        })
+    }
+}
+
+impl TryFrom<&CohortMaterialisation> for models::CohortMaterialisation {
+    type Error = DatabaseError;
+
+    fn try_from(item: &CohortMaterialisation) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: item.id,
+            cohort_id: item.cohort_id,
+            version: item.version,
+            definition: item
+                .definition
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(|e| DatabaseError::Query(format!("{:?}", e)))?,
+            group_id: item.group_id,
+            subject_count: item.subject_count,
+            materialised_at: item.materialised_at,
+        })
+    }
+}
+
+/// GroupMember adds a Subject to a Group other than the one it was inserted
+/// into, such as the Group a Cohort was materialised into. Members of earlier
+/// materialisations are kept, so a Subject may be listed more than once.
+#[derive(Entity, Default)]
+#[index(
+    name = "group_member_materialisation_unique",
+    columns = "materialisation_id, subject_id",
+    unique
+)]
+pub struct GroupMember {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Group", key = "id")]
+    pub group_id: i32,
+
+    #[relation(model = "Subject", key = "id")]
+    pub subject_id: i32,
+
+    /// The CohortMaterialisation that added the member, if any.
+    pub materialisation_id: Option<i32>,
+}
+
+/// SubjectExternalId identifies a Subject outside of silo. The namespace and
+/// value are unique within a Group.
+#[derive(Entity, Default)]
+#[index(
+    name = "subject_external_id_unique",
+    columns = "group_id, namespace, value",
+    unique
+)]
+pub struct SubjectExternalId {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Subject", key = "id")]
+    pub subject_id: i32,
+
+    #[relation(model = "Group", key = "id")]
+    pub group_id: i32,
+
+    pub namespace: String,
+    pub value: String,
+}
+
+impl From<&models::ExternalId> for SubjectExternalId {
+    fn from(item: &models::ExternalId) -> Self {
+        Self {
+            id: item.id,
+            subject_id: item.subject_id,
+            group_id: item.group_id,
+            namespace: item.namespace.clone(),
+            value: item.value.clone(),
+        }
+    }
+}
+
+impl From<&SubjectExternalId> for models::ExternalId {
+    fn from(item: &SubjectExternalId) -> Self {
+        Self {
+            id: item.id,
+            subject_id: item.subject_id,
+            group_id: item.group_id,
+            namespace: item.namespace.clone(),
+            value: item.value.clone(),
+        }
+    }
+}
+
+/// PseudonymExport records an export whose subject identifiers were replaced
+/// with tokens.
+#[derive(Entity, Default)]
+pub struct PseudonymExport {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "Group", key = "id")]
+    pub group_id: i32,
+    pub method: String,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&models::PseudonymExport> for PseudonymExport {
+    fn from(item: &models::PseudonymExport) -> Self {
+        Self {
+            id: item.id,
+            group_id: item.group_id,
+            method: item.method.clone(),
+            created_at: item.created_at,
+        }
+    }
+}
+
+/// Pseudonym maps a token of a PseudonymExport back to a Subject. Only
+/// administrators may read it back through the API.
+#[derive(Entity, Default)]
+pub struct Pseudonym {
+    #[primary_key]
+    pub id: i32,
+
+    #[relation(model = "PseudonymExport", key = "id")]
+    pub export_id: i32,
+
+    #[relation(model = "Subject", key = "id")]
+    pub subject_id: i32,
+
+    #[indexed]
+    pub token: String,
+}
+
+impl From<&Pseudonym> for models::Pseudonym {
+    fn from(item: &Pseudonym) -> Self {
+        Self {
+            id: item.id,
+            export_id: item.export_id,
+            subject_id: item.subject_id,
+            token: item.token.clone(),
+        }
+    }
+}
+
+/// PrivacyBudget tracks the epsilon an API key has spent on differentially
+/// private queries.
+#[derive(Entity, Default)]
+#[index(name = "privacy_budget_api_key_unique", columns = "api_key", unique)]
+pub struct PrivacyBudget {
+    #[primary_key]
+    pub id: i32,
+
+    pub api_key: String,
+    pub epsilon_total: f64,
+    pub epsilon_spent: f64,
+
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<&PrivacyBudget> for models::PrivacyBudget {
+    fn from(item: &PrivacyBudget) -> Self {
+        Self {
+            id: item.id,
+            api_key: item.api_key.clone(),
+            epsilon_total: item.epsilon_total,
+            epsilon_spent: item.epsilon_spent,
+            updated_at: item.updated_at,
+        }
+    }
 }
@@ -1,5 +1,7 @@
 use async_trait::async_trait;
+use chrono::Utc;
 use oxidizer::*;
+use silo_core::duplicates::normalise;
 use silo_core::models;
 
 use crate::config::DatabaseConfig;
@@ -7,6 +9,7 @@ use crate::connection::*;
 use crate::db_utils::postgres_conn_str;
 use crate::errors::*;
 use crate::models as db_models;
+use std::convert::TryFrom;
 
 /// A trait of methods implemented by the Service.
 #[async_trait]
@@ -20,11 +23,11 @@ pub trait Service: Sync + Send {
     async fn insert_subject(&self, subject: &models::Subject) -> Result<i32, DatabaseError>;
     /// Inserts a Group into the database.
     async fn insert_group(&self, group: &models::Group) -> Result<i32, DatabaseError>;
-    /// Adds a SubjectTrait to a Subject by the Subject and Trait IDs.
+    /// Adds a SubjectTrait to a Subject from a SubjectTraitLink, with its timing,
+    /// value and qualifier, if any.
     async fn insert_subject_subject_trait(
         &self,
-        subject_id: i32,
-        subject_trait_id: i32,
+        link: &models::SubjectTraitLink,
     ) -> Result<i32, DatabaseError>;
     /// Finds all traits.
     async fn get_traits(&self) -> Result<Vec<models::SubjectTrait>, DatabaseError>;
@@ -39,6 +42,13 @@ pub trait Service: Sync + Send {
     async fn find_group_by_id(&self, id: i32) -> Result<Option<models::Group>, DatabaseError>;
     /// Finds a single Subject by ID.
     async fn find_subject_by_id(&self, id: i32) -> Result<Option<models::Subject>, DatabaseError>;
+    /// Finds a Subject by its ID if it is in a Group, either directly or as a
+    /// member.
+    async fn find_subject_in_group(
+        &self,
+        group_id: i32,
+        subject_id: i32,
+    ) -> Result<Option<models::Subject>, DatabaseError>;
     /// Finds all subjects in a single Group by its ID.
     async fn find_subjects_by_group_id(
         &self,
@@ -54,8 +64,207 @@ pub trait Service: Sync + Send {
         &self,
         trait_name: &str,
     ) -> Result<Option<models::SubjectTrait>, DatabaseError>;
+    /// Finds the trait links of every subject in a single Group by its ID.
+    async fn find_subject_trait_links_by_group_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::SubjectTraitLink>, DatabaseError>;
+    /// Inserts a Cohort into the database.
+    async fn insert_cohort(&self, cohort: &models::Cohort) -> Result<i32, DatabaseError>;
+    /// Updates an existing Cohort.
+    async fn update_cohort(&self, cohort: &models::Cohort) -> Result<(), DatabaseError>;
+    /// Finds all cohorts.
+    async fn get_cohorts(&self) -> Result<Vec<models::Cohort>, DatabaseError>;
+    /// Finds a single Cohort by ID.
+    async fn find_cohort_by_id(&self, id: i32) -> Result<Option<models::Cohort>, DatabaseError>;
+    /// Deletes a Cohort and its materialisation records by ID. Materialised groups
+    /// are kept, with the members of their latest remaining materialisation, or
+    /// of the cohort's latest one if no other cohort was materialised into them.
+    /// Returns whether or not the cohort existed.
+    async fn delete_cohort(&self, id: i32) -> Result<bool, DatabaseError>;
+    /// Inserts a CohortMaterialisation with the subjects it adds to its Group.
+    /// The members of earlier materialisations are kept, but only those of the
+    /// latest one are members of the Group.
+    async fn insert_cohort_materialisation(
+        &self,
+        materialisation: &models::CohortMaterialisation,
+        subject_ids: &[i32],
+    ) -> Result<i32, DatabaseError>;
+    /// Finds every materialisation of a Cohort by its ID, oldest first.
+    async fn find_cohort_materialisations_by_cohort_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::CohortMaterialisation>, DatabaseError>;
+    /// Inserts a new Group for each set of subjects, with the subjects as its
+    /// members, and returns the IDs of the Groups in the same order. Either
+    /// every Group is inserted or none is.
+    async fn insert_groups_with_members(
+        &self,
+        members: &[Vec<i32>],
+    ) -> Result<Vec<i32>, DatabaseError>;
+    /// Updates the attributes of an existing Subject.
+    async fn update_subject(&self, subject: &models::Subject) -> Result<(), DatabaseError>;
+    /// Inserts a Subject, or updates the Subject of its Group with any of the
+    /// given external IDs. External IDs the Subject doesn't have yet are added.
+    /// Returns the ID of the Subject and whether or not it was inserted. Either
+    /// every change is made or none is, and DatabaseError::Conflict is returned
+    /// if the external IDs belong to more than one Subject.
+    async fn upsert_subject(
+        &self,
+        subject: &models::Subject,
+        external_ids: &[models::ExternalId],
+    ) -> Result<(i32, bool), DatabaseError>;
+    /// Inserts an ExternalId into the database, with its value normalised.
+    async fn insert_external_id(
+        &self,
+        external_id: &models::ExternalId,
+    ) -> Result<i32, DatabaseError>;
+    /// Finds all external IDs.
+    async fn get_external_ids(&self) -> Result<Vec<models::ExternalId>, DatabaseError>;
+    /// Finds all external IDs of a Subject by its ID.
+    async fn find_external_ids_by_subject_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::ExternalId>, DatabaseError>;
+    /// Finds the Subject of a Group with an external ID, compared after
+    /// normalising it.
+    async fn find_subject_by_external_id(
+        &self,
+        group_id: i32,
+        namespace: &str,
+        value: &str,
+    ) -> Result<Option<models::Subject>, DatabaseError>;
+    /// Inserts a PseudonymExport and the tokens made for it, returning the ID of
+    /// the export.
+    async fn insert_pseudonym_export(
+        &self,
+        export: &models::PseudonymExport,
+        pseudonyms: &[models::Pseudonym],
+    ) -> Result<i32, DatabaseError>;
+    /// Finds every token made for a PseudonymExport by its ID.
+    async fn find_pseudonyms_by_export_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::Pseudonym>, DatabaseError>;
+    /// Finds the pseudonyms with any of the given tokens, from every export.
+    async fn find_pseudonyms_by_tokens(
+        &self,
+        tokens: &[String],
+    ) -> Result<Vec<models::Pseudonym>, DatabaseError>;
+    /// Sets the total epsilon of an API key's PrivacyBudget, creating the budget if
+    /// the key doesn't have one. The epsilon already spent is kept.
+    async fn set_privacy_budget(
+        &self,
+        api_key: &str,
+        epsilon_total: f64,
+    ) -> Result<models::PrivacyBudget, DatabaseError>;
+    /// Finds the PrivacyBudget of an API key.
+    async fn find_privacy_budget_by_api_key(
+        &self,
+        api_key: &str,
+    ) -> Result<Option<models::PrivacyBudget>, DatabaseError>;
+    /// Spends epsilon from an API key's PrivacyBudget. Returns false without
+    /// spending anything if the key has no budget or too little of it left.
+    async fn spend_privacy_budget(
+        &self,
+        api_key: &str,
+        epsilon: f64,
+    ) -> Result<bool, DatabaseError>;
+    /// Counts the subjects, traits and groups stored.
+    async fn count_entities(&self) -> Result<models::EntityCounts, DatabaseError>;
+
+    /// Checks that the database responds to queries.
+    async fn ping(&self) -> Result<(), DatabaseError>;
+
+    /// Returns the migrations applied to the database and those known to this
+    /// build.
+    async fn migration_status(&self) -> Result<models::MigrationStatus, DatabaseError>;
+
+    /// Returns how many connections of the pool are in use and how many it
+    /// opens at most.
+    async fn pool_state(&self) -> Result<models::PoolState, DatabaseError>;
 }
 
+/// Calls a macro with the name, arguments and result of every method of the
+/// Service, so that a wrapper of the Service can implement all of them at once.
+#[macro_export]
+macro_rules! service_methods {
+    ($callback:ident) => {
+        $callback! {
+            insert_subject_trait(subject_trait: &silo_core::models::SubjectTrait) -> i32;
+            insert_subject(subject: &silo_core::models::Subject) -> i32;
+            insert_group(group: &silo_core::models::Group) -> i32;
+            insert_subject_subject_trait(link: &silo_core::models::SubjectTraitLink) -> i32;
+            get_traits() -> Vec<silo_core::models::SubjectTrait>;
+            get_groups() -> Vec<silo_core::models::Group>;
+            find_subject_trats_by_subject_id(id: i32) -> Vec<silo_core::models::SubjectTrait>;
+            find_group_by_id(id: i32) -> Option<silo_core::models::Group>;
+            find_subject_by_id(id: i32) -> Option<silo_core::models::Subject>;
+            find_subject_in_group(
+                group_id: i32,
+                subject_id: i32
+            ) -> Option<silo_core::models::Subject>;
+            find_subjects_by_group_id(id: i32) -> Vec<silo_core::models::Subject>;
+            find_subject_trait_by_id(id: i32) -> Option<silo_core::models::SubjectTrait>;
+            find_subject_trait_by_name(trait_name: &str) -> Option<silo_core::models::SubjectTrait>;
+            find_subject_trait_links_by_group_id(
+                id: i32
+            ) -> Vec<silo_core::models::SubjectTraitLink>;
+            insert_cohort(cohort: &silo_core::models::Cohort) -> i32;
+            update_cohort(cohort: &silo_core::models::Cohort) -> ();
+            get_cohorts() -> Vec<silo_core::models::Cohort>;
+            find_cohort_by_id(id: i32) -> Option<silo_core::models::Cohort>;
+            delete_cohort(id: i32) -> bool;
+            insert_cohort_materialisation(
+                materialisation: &silo_core::models::CohortMaterialisation,
+                subject_ids: &[i32]
+            ) -> i32;
+            find_cohort_materialisations_by_cohort_id(
+                id: i32
+            ) -> Vec<silo_core::models::CohortMaterialisation>;
+            insert_groups_with_members(members: &[Vec<i32>]) -> Vec<i32>;
+            update_subject(subject: &silo_core::models::Subject) -> ();
+            upsert_subject(
+                subject: &silo_core::models::Subject,
+                external_ids: &[silo_core::models::ExternalId]
+            ) -> (i32, bool);
+            insert_external_id(external_id: &silo_core::models::ExternalId) -> i32;
+            get_external_ids() -> Vec<silo_core::models::ExternalId>;
+            find_external_ids_by_subject_id(id: i32) -> Vec<silo_core::models::ExternalId>;
+            find_subject_by_external_id(
+                group_id: i32,
+                namespace: &str,
+                value: &str
+            ) -> Option<silo_core::models::Subject>;
+            insert_pseudonym_export(
+                export: &silo_core::models::PseudonymExport,
+                pseudonyms: &[silo_core::models::Pseudonym]
+            ) -> i32;
+            find_pseudonyms_by_export_id(id: i32) -> Vec<silo_core::models::Pseudonym>;
+            find_pseudonyms_by_tokens(tokens: &[String]) -> Vec<silo_core::models::Pseudonym>;
+            set_privacy_budget(
+                api_key: &str,
+                epsilon_total: f64
+            ) -> silo_core::models::PrivacyBudget;
+            find_privacy_budget_by_api_key(
+                api_key: &str
+            ) -> Option<silo_core::models::PrivacyBudget>;
+            spend_privacy_budget(api_key: &str, epsilon: f64) -> bool;
+            count_entities() -> silo_core::models::EntityCounts;
+            ping() -> ();
+            migration_status() -> silo_core::models::MigrationStatus;
+            pool_state() -> silo_core::models::PoolState;
+        }
+    };
+}
+
+/// Selects the IDs of the subjects added to the Group $1 by membership: the
+/// members of its latest materialisation if it has been materialised, and
+/// otherwise those added to it directly.
+const GROUP_MEMBERS: &str = "SELECT subject_id FROM group_member WHERE group_id = $1 \
+     AND materialisation_id IS NOT DISTINCT FROM \
+     (SELECT MAX(id) FROM cohort_materialisation WHERE group_id = $1)";
+
 /// An implementation of the service itself.
 pub struct ServiceImpl {
     conn: Box<Connection>,
@@ -77,42 +286,37 @@ impl Service for ServiceImpl {
         let mut st = db_models::SubjectTrait::from(subject_trait);
         match st.save(&self.conn.db).await {
             Ok(_) => Ok(st.id),
-            Err(e) => Err(DatabaseError(format!("{:?}", e))),
+            Err(e) => Err(DatabaseError::from(e)),
         }
     }
     async fn insert_subject(&self, subject: &models::Subject) -> Result<i32, DatabaseError> {
         let mut s = db_models::Subject::from(subject);
         match s.save(&self.conn.db).await {
             Ok(_) => Ok(s.id),
-            Err(e) => Err(DatabaseError(format!("{:?}", e))),
+            Err(e) => Err(DatabaseError::from(e)),
         }
     }
     async fn insert_group(&self, group: &models::Group) -> Result<i32, DatabaseError> {
         let mut g = db_models::Group::from(group);
         match g.save(&self.conn.db).await {
             Ok(_) => Ok(g.id),
-            Err(e) => Err(DatabaseError(format!("{:?}", e))),
+            Err(e) => Err(DatabaseError::from(e)),
         }
     }
     async fn insert_subject_subject_trait(
         &self,
-        subject_id: i32,
-        subject_trait_id: i32,
+        link: &models::SubjectTraitLink,
     ) -> Result<i32, DatabaseError> {
-        let mut sst = db_models::SubjectSubjectTrait {
-            id: 0,
-            subject_id,
-            subject_trait_id,
-        };
+        let mut sst = db_models::SubjectSubjectTrait::from(link);
         match sst.save(&self.conn.db).await {
             Ok(_) => Ok(sst.id),
-            Err(e) => Err(DatabaseError(format!("{:?}", e))),
+            Err(e) => Err(DatabaseError::from(e)),
         }
     }
     async fn get_traits(&self) -> Result<Vec<models::SubjectTrait>, DatabaseError> {
         let t = db_models::SubjectTrait::find(&self.conn.db, "id > 0", &[])
             .await
-            .or_else(|e| Err(DatabaseError(format!("{:?}", e))))?;
+            .or_else(|e| Err(DatabaseError::from(e)))?;
 
         Ok(t.iter()
             .map(|x| models::SubjectTrait {
@@ -125,7 +329,7 @@ impl Service for ServiceImpl {
     async fn get_groups(&self) -> Result<Vec<models::Group>, DatabaseError> {
         let g = db_models::Group::find(&self.conn.db, "id > 0", &[])
             .await
-            .or_else(|e| Err(DatabaseError(format!("{:?}", e))))?;
+            .or_else(|e| Err(DatabaseError::from(e)))?;
 
         Ok(g.iter().map(|x| models::Group { id: x.id }).collect())
     }
@@ -135,14 +339,14 @@ impl Service for ServiceImpl {
     ) -> Result<Vec<models::SubjectTrait>, DatabaseError> {
         let st = db_models::SubjectSubjectTrait::find(&self.conn.db, "subject_id = $1", &[&id])
             .await
-            .or_else(|e| Err(DatabaseError(format!("{:?}", e))))?;
+            .or_else(|e| Err(DatabaseError::from(e)))?;
 
         let mut sub_traits = vec![];
         for s in st {
             let sub_trait =
                 db_models::SubjectTrait::first(&self.conn.db, "id = $1", &[&s.subject_trait_id])
                     .await
-                    .or_else(|e| Err(DatabaseError(format!("{:?}", e))))?;
+                    .or_else(|e| Err(DatabaseError::from(e)))?;
 
             match sub_trait {
                 Some(s) => {
@@ -162,7 +366,7 @@ impl Service for ServiceImpl {
         let g = match db_models::Group::first(&self.conn.db, "id = $1", &[&id]).await {
             Ok(Some(g)) => g,
             Ok(None) => return Ok(None),
-            Err(e) => return Err(DatabaseError(format!("{:?}", e))),
+            Err(e) => return Err(DatabaseError::from(e)),
         };
 
         Ok(Some(models::Group { id: g.id }))
@@ -171,7 +375,7 @@ impl Service for ServiceImpl {
         let s = match db_models::Subject::first(&self.conn.db, "id = $1", &[&id]).await {
             Ok(Some(s)) => s,
             Ok(None) => return Ok(None),
-            Err(e) => return Err(DatabaseError(format!("{:?}", e))),
+            Err(e) => return Err(DatabaseError::from(e)),
         };
 
         Ok(Some(models::Subject {
@@ -179,23 +383,50 @@ impl Service for ServiceImpl {
             group_id: s.group_id,
             age: s.age,
             length_of_stay: s.length_of_stay,
+            admitted_at: s.admitted_at,
+        }))
+    }
+    async fn find_subject_in_group(
+        &self,
+        group_id: i32,
+        subject_id: i32,
+    ) -> Result<Option<models::Subject>, DatabaseError> {
+        let s = db_models::Subject::first(
+            &self.conn.db,
+            &format!("id = $2 AND (group_id = $1 OR id IN ({}))", GROUP_MEMBERS),
+            &[&group_id, &subject_id],
+        )
+        .await
+        .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(s.map(|s| models::Subject {
+            id: s.id,
+            group_id: s.group_id,
+            age: s.age,
+            length_of_stay: s.length_of_stay,
+            admitted_at: s.admitted_at,
         }))
     }
     async fn find_subjects_by_group_id(
         &self,
         id: i32,
     ) -> Result<Vec<models::Subject>, DatabaseError> {
-        let subjects = db_models::Subject::find(&self.conn.db, "group_id = $1", &[&id])
-            .await
-            .or_else(|e| Err(DatabaseError(format!("{:?}", e))))?
-            .iter()
-            .map(|s| models::Subject {
-                id: s.id,
-                group_id: s.group_id,
-                age: s.age,
-                length_of_stay: s.length_of_stay,
-            })
-            .collect();
+        let subjects = db_models::Subject::find(
+            &self.conn.db,
+            &format!("group_id = $1 OR id IN ({})", GROUP_MEMBERS),
+            &[&id],
+        )
+        .await
+        .or_else(|e| Err(DatabaseError::from(e)))?
+        .iter()
+        .map(|s| models::Subject {
+            id: s.id,
+            group_id: s.group_id,
+            age: s.age,
+            length_of_stay: s.length_of_stay,
+            admitted_at: s.admitted_at,
+        })
+        .collect();
 
         Ok(subjects)
     }
@@ -206,7 +437,7 @@ impl Service for ServiceImpl {
         let st = match db_models::SubjectTrait::first(&self.conn.db, "id = $1", &[&id]).await {
             Ok(Some(st)) => st,
             Ok(None) => return Ok(None),
-            Err(e) => return Err(DatabaseError(format!("{:?}", e))),
+            Err(e) => return Err(DatabaseError::from(e)),
         };
 
         Ok(Some(models::SubjectTrait {
@@ -221,7 +452,7 @@ impl Service for ServiceImpl {
     ) -> Result<Option<models::SubjectTrait>, DatabaseError> {
         let st = db_models::SubjectTrait::first(&self.conn.db, "trait_name = $1", &[&trait_name])
             .await
-            .or_else(|e| Err(DatabaseError(format!("{:?}", e))))?;
+            .or_else(|e| Err(DatabaseError::from(e)))?;
 
         let s = match st {
             Some(s) => s,
@@ -234,4 +465,463 @@ impl Service for ServiceImpl {
             trait_name: s.trait_name,
         }))
     }
+    async fn find_subject_trait_links_by_group_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::SubjectTraitLink>, DatabaseError> {
+        let links = db_models::SubjectSubjectTrait::find(
+            &self.conn.db,
+            &format!(
+                "subject_id IN (SELECT id FROM subject WHERE group_id = $1) \
+                 OR subject_id IN ({})",
+                GROUP_MEMBERS
+            ),
+            &[&id],
+        )
+        .await
+        .or_else(|e| Err(DatabaseError::from(e)))?
+        .iter()
+        .map(|l| models::SubjectTraitLink {
+            id: l.id,
+            subject_id: l.subject_id,
+            subject_trait_id: l.subject_trait_id,
+            onset_at: l.onset_at,
+            resolved_at: l.resolved_at,
+            value: l.value,
+            qualifier: l.qualifier.clone(),
+        })
+        .collect();
+
+        Ok(links)
+    }
+
+    async fn insert_cohort(&self, cohort: &models::Cohort) -> Result<i32, DatabaseError> {
+        let mut c = db_models::Cohort::try_from(cohort)?;
+        match c.save(&self.conn.db).await {
+            Ok(_) => Ok(c.id),
+            Err(e) => Err(DatabaseError::from(e)),
+        }
+    }
+    async fn update_cohort(&self, cohort: &models::Cohort) -> Result<(), DatabaseError> {
+        let mut c = db_models::Cohort::try_from(cohort)?;
+        match c.save(&self.conn.db).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::from(e)),
+        }
+    }
+    async fn get_cohorts(&self) -> Result<Vec<models::Cohort>, DatabaseError> {
+        db_models::Cohort::find(&self.conn.db, "id > 0", &[])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?
+            .iter()
+            .map(models::Cohort::try_from)
+            .collect()
+    }
+    async fn find_cohort_by_id(&self, id: i32) -> Result<Option<models::Cohort>, DatabaseError> {
+        match db_models::Cohort::first(&self.conn.db, "id = $1", &[&id]).await {
+            Ok(Some(c)) => Ok(Some(models::Cohort::try_from(&c)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(DatabaseError::from(e)),
+        }
+    }
+    async fn delete_cohort(&self, id: i32) -> Result<bool, DatabaseError> {
+        // Each call may take a different connection from the pool, so the
+        // deletion is a single statement, which Postgres runs in one
+        // transaction, and every part of it sees the rows as they were before.
+        // Where only this cohort was materialised into a group, the members of
+        // its latest materialisation become direct members of the group. The
+        // members of its other materialisations go with their records.
+        let rows = self
+            .conn
+            .db
+            .query(
+                "WITH materialisations AS ( \
+                     DELETE FROM cohort_materialisation WHERE cohort_id = $1 \
+                     RETURNING id, group_id \
+                 ), kept AS ( \
+                     SELECT MAX(id) AS id, group_id FROM materialisations \
+                     WHERE group_id NOT IN \
+                     (SELECT group_id FROM cohort_materialisation WHERE cohort_id <> $1) \
+                     GROUP BY group_id \
+                 ), direct AS ( \
+                     DELETE FROM group_member \
+                     WHERE materialisation_id IS NULL \
+                     AND group_id IN (SELECT group_id FROM kept) \
+                 ), history AS ( \
+                     DELETE FROM group_member \
+                     WHERE materialisation_id IN (SELECT id FROM materialisations) \
+                     AND materialisation_id NOT IN (SELECT id FROM kept) \
+                 ), members AS ( \
+                     UPDATE group_member SET materialisation_id = NULL \
+                     WHERE materialisation_id IN (SELECT id FROM kept) \
+                 ) \
+                 DELETE FROM cohort WHERE id = $1 RETURNING id",
+                &[&id],
+            )
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(!rows.is_empty())
+    }
+    async fn insert_cohort_materialisation(
+        &self,
+        materialisation: &models::CohortMaterialisation,
+        subject_ids: &[i32],
+    ) -> Result<i32, DatabaseError> {
+        // A single statement, so a materialisation is never recorded without
+        // its members.
+        let m = db_models::CohortMaterialisation::try_from(materialisation)?;
+        let subject_ids = subject_ids.to_vec();
+        let rows = self
+            .conn
+            .db
+            .query(
+                "WITH materialisation AS ( \
+                     INSERT INTO cohort_materialisation \
+                     (cohort_id, version, definition, group_id, subject_count, materialised_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6) RETURNING id \
+                 ), members AS ( \
+                     INSERT INTO group_member (group_id, subject_id, materialisation_id) \
+                     SELECT DISTINCT $4::INTEGER, subject_id, materialisation.id \
+                     FROM materialisation, UNNEST($7::INTEGER[]) AS subject_id \
+                 ) \
+                 SELECT id FROM materialisation",
+                &[
+                    &m.cohort_id,
+                    &m.version,
+                    &m.definition,
+                    &m.group_id,
+                    &m.subject_count,
+                    &m.materialised_at,
+                    &subject_ids,
+                ],
+            )
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        match rows.first() {
+            Some(row) => Ok(row.get(0)),
+            None => Err(DatabaseError::Query(
+                "the materialisation was not recorded".into(),
+            )),
+        }
+    }
+    async fn find_cohort_materialisations_by_cohort_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::CohortMaterialisation>, DatabaseError> {
+        db_models::CohortMaterialisation::find(&self.conn.db, "cohort_id = $1 ORDER BY id", &[&id])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?
+            .iter()
+            .map(models::CohortMaterialisation::try_from)
+            .collect()
+    }
+    async fn insert_groups_with_members(
+        &self,
+        members: &[Vec<i32>],
+    ) -> Result<Vec<i32>, DatabaseError> {
+        // A single statement, so a failure leaves no orphan groups. Groups are
+        // numbered in the order their IDs were drawn from the sequence, and the
+        // members are flattened into the number of their group and their ID.
+        let count = members.len() as i32;
+        let (numbers, subject_ids): (Vec<i64>, Vec<i32>) = members
+            .iter()
+            .enumerate()
+            .flat_map(|(n, ids)| ids.iter().map(move |id| (n as i64 + 1, *id)))
+            .unzip();
+
+        let rows = self
+            .conn
+            .db
+            .query(
+                "WITH new_groups AS ( \
+                     INSERT INTO subject_group (id) \
+                     SELECT nextval(pg_get_serial_sequence('subject_group', 'id')) \
+                     FROM generate_series(1, $1::INTEGER) \
+                     RETURNING id \
+                 ), numbered AS ( \
+                     SELECT id, ROW_NUMBER() OVER (ORDER BY id) AS n FROM new_groups \
+                 ), members AS ( \
+                     INSERT INTO group_member (group_id, subject_id) \
+                     SELECT DISTINCT numbered.id, m.subject_id \
+                     FROM numbered \
+                     JOIN UNNEST($2::BIGINT[], $3::INTEGER[]) AS m (n, subject_id) \
+                     ON m.n = numbered.n \
+                 ) \
+                 SELECT id FROM numbered ORDER BY n",
+                &[&count, &numbers, &subject_ids],
+            )
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+    async fn update_subject(&self, subject: &models::Subject) -> Result<(), DatabaseError> {
+        let mut s = db_models::Subject::from(subject);
+        match s.save(&self.conn.db).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::from(e)),
+        }
+    }
+    async fn upsert_subject(
+        &self,
+        subject: &models::Subject,
+        external_ids: &[models::ExternalId],
+    ) -> Result<(i32, bool), DatabaseError> {
+        // A single statement, so a failure, such as another upsert adding one of
+        // the external IDs first, leaves no Subject without its external IDs.
+        // Nothing is changed when the external IDs belong to several Subjects.
+        let (namespaces, values): (Vec<String>, Vec<String>) = external_ids
+            .iter()
+            .map(|e| (e.namespace.clone(), normalise(&e.value)))
+            .unzip();
+
+        let rows = self
+            .conn
+            .db
+            .query(
+                "WITH given AS ( \
+                     SELECT DISTINCT namespace, value \
+                     FROM UNNEST($5::TEXT[], $6::TEXT[]) AS g (namespace, value) \
+                 ), matched AS ( \
+                     SELECT DISTINCT e.subject_id FROM subject_external_id e \
+                     JOIN given ON e.namespace = given.namespace AND e.value = given.value \
+                     WHERE e.group_id = $1 \
+                 ), updated AS ( \
+                     UPDATE subject SET age = $2, length_of_stay = $3, admitted_at = $4 \
+                     WHERE id IN (SELECT subject_id FROM matched) \
+                     AND (SELECT COUNT(*) FROM matched) = 1 \
+                     RETURNING id \
+                 ), inserted AS ( \
+                     INSERT INTO subject (group_id, age, length_of_stay, admitted_at) \
+                     SELECT $1, $2, $3, $4 WHERE NOT EXISTS (SELECT 1 FROM matched) \
+                     RETURNING id \
+                 ), target AS ( \
+                     SELECT id, FALSE AS inserted FROM updated \
+                     UNION ALL SELECT id, TRUE AS inserted FROM inserted \
+                 ), added AS ( \
+                     INSERT INTO subject_external_id (subject_id, group_id, namespace, value) \
+                     SELECT target.id, $1, given.namespace, given.value FROM target, given \
+                     WHERE NOT EXISTS ( \
+                         SELECT 1 FROM subject_external_id e WHERE e.group_id = $1 \
+                         AND e.namespace = given.namespace AND e.value = given.value \
+                     ) \
+                 ) \
+                 SELECT id, inserted FROM target",
+                &[
+                    &subject.group_id,
+                    &subject.age,
+                    &subject.length_of_stay,
+                    &subject.admitted_at,
+                    &namespaces,
+                    &values,
+                ],
+            )
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        match rows.first() {
+            Some(row) => Ok((row.get(0), row.get(1))),
+            None => Err(DatabaseError::Conflict(
+                "the external IDs given for one subject belong to several subjects".into(),
+            )),
+        }
+    }
+    async fn insert_external_id(
+        &self,
+        external_id: &models::ExternalId,
+    ) -> Result<i32, DatabaseError> {
+        let mut e = db_models::SubjectExternalId::from(external_id);
+        e.value = normalise(&e.value);
+        match e.save(&self.conn.db).await {
+            Ok(_) => Ok(e.id),
+            Err(e) => Err(DatabaseError::from(e)),
+        }
+    }
+    async fn get_external_ids(&self) -> Result<Vec<models::ExternalId>, DatabaseError> {
+        let ids = db_models::SubjectExternalId::find(&self.conn.db, "id > 0", &[])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(ids.iter().map(models::ExternalId::from).collect())
+    }
+    async fn find_external_ids_by_subject_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::ExternalId>, DatabaseError> {
+        let ids = db_models::SubjectExternalId::find(&self.conn.db, "subject_id = $1", &[&id])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(ids.iter().map(models::ExternalId::from).collect())
+    }
+    async fn find_subject_by_external_id(
+        &self,
+        group_id: i32,
+        namespace: &str,
+        value: &str,
+    ) -> Result<Option<models::Subject>, DatabaseError> {
+        let e = db_models::SubjectExternalId::first(
+            &self.conn.db,
+            "group_id = $1 AND namespace = $2 AND value = $3",
+            &[&group_id, &namespace, &normalise(value)],
+        )
+        .await
+        .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        match e {
+            Some(e) => self.find_subject_by_id(e.subject_id).await,
+            None => Ok(None),
+        }
+    }
+    async fn insert_pseudonym_export(
+        &self,
+        export: &models::PseudonymExport,
+        pseudonyms: &[models::Pseudonym],
+    ) -> Result<i32, DatabaseError> {
+        // A single statement, so an export is never recorded without every one
+        // of its tokens.
+        let subject_ids: Vec<i32> = pseudonyms.iter().map(|p| p.subject_id).collect();
+        let tokens: Vec<String> = pseudonyms.iter().map(|p| p.token.clone()).collect();
+        let rows = self
+            .conn
+            .db
+            .query(
+                "WITH export AS ( \
+                     INSERT INTO pseudonym_export (group_id, method, created_at) \
+                     VALUES ($1, $2, $3) RETURNING id \
+                 ), tokens AS ( \
+                     INSERT INTO pseudonym (export_id, subject_id, token) \
+                     SELECT export.id, p.subject_id, p.token \
+                     FROM export, UNNEST($4::INTEGER[], $5::TEXT[]) AS p (subject_id, token) \
+                 ) \
+                 SELECT id FROM export",
+                &[
+                    &export.group_id,
+                    &export.method,
+                    &export.created_at,
+                    &subject_ids,
+                    &tokens,
+                ],
+            )
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        match rows.first() {
+            Some(row) => Ok(row.get(0)),
+            None => Err(DatabaseError::Query("the export was not recorded".into())),
+        }
+    }
+    async fn find_pseudonyms_by_export_id(
+        &self,
+        id: i32,
+    ) -> Result<Vec<models::Pseudonym>, DatabaseError> {
+        let pseudonyms = db_models::Pseudonym::find(&self.conn.db, "export_id = $1", &[&id])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(pseudonyms.iter().map(models::Pseudonym::from).collect())
+    }
+    async fn find_pseudonyms_by_tokens(
+        &self,
+        tokens: &[String],
+    ) -> Result<Vec<models::Pseudonym>, DatabaseError> {
+        let tokens = tokens.to_vec();
+        let pseudonyms = db_models::Pseudonym::find(&self.conn.db, "token = ANY($1)", &[&tokens])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(pseudonyms.iter().map(models::Pseudonym::from).collect())
+    }
+    async fn set_privacy_budget(
+        &self,
+        api_key: &str,
+        epsilon_total: f64,
+    ) -> Result<models::PrivacyBudget, DatabaseError> {
+        let existing = db_models::PrivacyBudget::first(&self.conn.db, "api_key = $1", &[&api_key])
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        let mut b = match existing {
+            Some(b) => b,
+            None => db_models::PrivacyBudget {
+                api_key: api_key.into(),
+                ..Default::default()
+            },
+        };
+        b.epsilon_total = epsilon_total;
+        b.updated_at = Utc::now();
+        b.save(&self.conn.db)
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(models::PrivacyBudget::from(&b))
+    }
+    async fn find_privacy_budget_by_api_key(
+        &self,
+        api_key: &str,
+    ) -> Result<Option<models::PrivacyBudget>, DatabaseError> {
+        match db_models::PrivacyBudget::first(&self.conn.db, "api_key = $1", &[&api_key]).await {
+            Ok(b) => Ok(b.as_ref().map(models::PrivacyBudget::from)),
+            Err(e) => Err(DatabaseError::from(e)),
+        }
+    }
+    async fn spend_privacy_budget(
+        &self,
+        api_key: &str,
+        epsilon: f64,
+    ) -> Result<bool, DatabaseError> {
+        // Checks and spends in one statement so concurrent queries can't overspend.
+        let updated = self
+            .conn
+            .db
+            .execute(
+                "UPDATE privacy_budget SET epsilon_spent = epsilon_spent + $2, updated_at = $3 \
+                 WHERE api_key = $1 AND epsilon_spent + $2 <= epsilon_total",
+                &[&api_key, &epsilon, &Utc::now()],
+            )
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        Ok(updated == 1)
+    }
+    async fn count_entities(&self) -> Result<models::EntityCounts, DatabaseError> {
+        let rows = self
+            .conn
+            .db
+            .query(
+                "SELECT (SELECT COUNT(*) FROM subject), (SELECT COUNT(*) FROM subject_trait), \
+                 (SELECT COUNT(*) FROM subject_group)",
+                &[],
+            )
+            .await
+            .or_else(|e| Err(DatabaseError::from(e)))?;
+
+        match rows.first() {
+            Some(row) => Ok(models::EntityCounts {
+                subjects: row.get(0),
+                traits: row.get(1),
+                groups: row.get(2),
+            }),
+            None => Err(DatabaseError::Query(
+                "counting entities returned no rows".into(),
+            )),
+        }
+    }
+
+    async fn ping(&self) -> Result<(), DatabaseError> {
+        self.conn.ping().await
+    }
+
+    async fn migration_status(&self) -> Result<models::MigrationStatus, DatabaseError> {
+        Ok(models::MigrationStatus {
+            applied: self.conn.schema_version().await?,
+            latest: Connection::latest_schema_version(),
+        })
+    }
+
+    async fn pool_state(&self) -> Result<models::PoolState, DatabaseError> {
+        Ok(self.conn.pool_state().await)
+    }
 }
@@ -0,0 +1,27 @@
+use std::env;
+use std::process::Command;
+
+/// Records the git commit the crate is built from for the version endpoint.
+/// SILO_GIT_COMMIT overrides it when building outside a repository.
+fn main() {
+    println!("cargo:rerun-if-env-changed=SILO_GIT_COMMIT");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+    // Branch heads move to packed-refs when git packs them.
+    println!("cargo:rerun-if-changed=../.git/packed-refs");
+
+    let commit = env::var("SILO_GIT_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_string())
+    });
+
+    println!(
+        "cargo:rustc-env=SILO_GIT_COMMIT={}",
+        commit.unwrap_or_else(|| "unknown".into())
+    );
+}
@@ -1,25 +1,106 @@
 use silo_core::models;
+use silo_core::window::TimeWindow;
 use silo_db;
+use silo_db::actor::DbActor;
+use silo_transform::actor::{
+    GenerateMatrix, GroupMatrix, MatrixSelection, MatrixSplit, TraitOutput, TransformActor,
+    TransformError,
+};
+use silo_transform::codebook::CodebookFormat;
 use silo_transform::matrix::*;
+use silo_transform::rollup::RollupLevel;
 
+use actix::{Actor, Addr};
 use actix_cors::Cors;
 use actix_rt;
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use chrono::{DateTime, Utc};
 use futures;
+use log::{error, info};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio;
 use tokio::runtime::Runtime;
 
+mod analysis;
+mod cohorts;
+mod external_ids;
+mod health;
+mod logging;
+mod matching;
+mod metrics;
+mod openapi;
+mod privacy;
+mod pseudonyms;
+mod sampling;
+mod stats;
+mod validation;
+
+pub use pseudonyms::PseudonymPolicy;
+use pseudonyms::{PseudonymiseQuery, SubjectPseudonymiser, SubjectRef};
+use validation::{Validate, Validator};
+
 /// A service for running a REST API.
 pub struct RestService {
-    db_service: Box<dyn silo_db::service::Service>,
+    db_service: Arc<dyn silo_db::service::Service>,
+    pseudonym_policy: PseudonymPolicy,
+    admin_token: Option<String>,
+    metrics: Arc<metrics::Metrics>,
 }
 
 impl RestService {
-    pub fn new(db_service: Box<dyn silo_db::service::Service>) -> Self {
-        Self { db_service }
+    /// Creates and returns a RestService whose calls to the database service
+    /// are timed.
+    pub fn new(db_service: Arc<dyn silo_db::service::Service>) -> Self {
+        let metrics = Arc::new(metrics::Metrics::new());
+        Self {
+            db_service: Arc::new(metrics::TimedService::new(db_service, metrics.clone())),
+            pseudonym_policy: PseudonymPolicy::default(),
+            admin_token: None,
+            metrics,
+        }
+    }
+
+    /// Sets the server-wide policy on pseudonymising subject identifiers in
+    /// exports.
+    pub fn with_pseudonym_policy(mut self, policy: PseudonymPolicy) -> Self {
+        self.pseudonym_policy = policy;
+        self
+    }
+
+    /// Sets the bearer token administrators re-identify subjects and set
+    /// privacy budgets with. Both are disabled without one.
+    pub fn with_admin_token(mut self, token: Option<String>) -> Self {
+        self.admin_token = token;
+        self
+    }
+
+    /// Returns whether or not a request carries the admin token.
+    fn is_admin(&self, request: &HttpRequest) -> bool {
+        let admin_token = match &self.admin_token {
+            Some(token) => token,
+            None => return false,
+        };
+        let given = request
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        match given {
+            // Compare every byte so the time taken doesn't reveal the token.
+            Some(given) => {
+                given.len() == admin_token.len()
+                    && given
+                        .bytes()
+                        .zip(admin_token.bytes())
+                        .fold(0, |acc, (a, b)| acc | (a ^ b))
+                        == 0
+            }
+            None => false,
+        }
     }
 }
 
@@ -31,17 +112,11 @@ async fn groups_post(service: web::Data<Arc<RestService>>) -> impl Responder {
         .await
     {
         Ok(id) => HttpResponse::Ok().json(models::Group { id }),
-        Err(e) => {
-            println!("{:?}", e);
-            HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
-            })
-        }
+        Err(e) => db_error(e),
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct GroupsResponse {
     pub groups: Vec<models::Group>,
@@ -51,21 +126,54 @@ pub struct GroupsResponse {
 async fn groups_get(service: web::Data<Arc<RestService>>) -> impl Responder {
     match service.db_service.get_groups().await {
         Ok(groups) => HttpResponse::Ok().json(GroupsResponse { groups }),
-        Err(e) => {
-            println!("{:?}", e);
-            HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
-            })
-        }
+        Err(e) => db_error(e),
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertExternalId {
+    pub namespace: String,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct InsertSubject {
     pub age: i16,
     pub length_of_stay: i16,
+    #[serde(default)]
+    pub admitted_at: Option<DateTime<Utc>>,
+    /// Identifiers of the subject outside of silo. A subject of the group with any
+    /// of them is updated instead of inserting a new one.
+    #[serde(default)]
+    pub external_ids: Vec<InsertExternalId>,
+}
+
+impl InsertSubject {
+    /// Returns the Subject to insert into a group and its external IDs.
+    fn to_models(&self, group_id: i32) -> (models::Subject, Vec<models::ExternalId>) {
+        let subject = models::Subject {
+            id: 0,
+            group_id,
+            age: self.age,
+            length_of_stay: self.length_of_stay,
+            admitted_at: self.admitted_at,
+        };
+        let external_ids = self
+            .external_ids
+            .iter()
+            .map(|e| models::ExternalId {
+                id: 0,
+                subject_id: 0,
+                group_id,
+                namespace: e.namespace.clone(),
+                value: e.value.clone(),
+            })
+            .collect();
+
+        (subject, external_ids)
+    }
 }
 
 #[post("/groups/{id}/subjects")]
@@ -74,59 +182,204 @@ async fn groups_subjects_post(
     web::Path(id): web::Path<i32>,
     subject: web::Json<InsertSubject>,
 ) -> impl Responder {
-    let s = models::Subject {
-        id: 0,
-        group_id: id,
-        age: subject.age,
-        length_of_stay: subject.length_of_stay,
+    match service.db_service.find_group_by_id(id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return group_not_found(id),
+        Err(e) => return db_error(e),
     };
 
-    match service.db_service.insert_subject(&s).await {
-        Ok(id) => HttpResponse::Ok().json(models::Subject { id, ..s }),
-        Err(e) => {
-            println!("{:?}", e);
-            HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
-            })
-        }
+    let mut v = Validator::new();
+    subject.validate(&mut v);
+    if let Err(response) = v.finish() {
+        return response;
+    }
+
+    let (s, external_ids) = subject.to_models(id);
+
+    match service.db_service.upsert_subject(&s, &external_ids).await {
+        Ok((id, _)) => HttpResponse::Ok().json(models::Subject { id, ..s }),
+        Err(e) => db_error(e),
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupSubject {
+    /// The ID of the subject, or its token if the response is pseudonymised.
+    pub id: SubjectRef,
+    pub group_id: i32,
+    pub age: i16,
+    pub length_of_stay: i16,
+    pub admitted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SubjectsResponse {
-    pub subjects: Vec<models::Subject>,
+    pub subjects: Vec<GroupSubject>,
 }
 
 #[get("/groups/{id}/subjects")]
 async fn groups_subjects_get(
     service: web::Data<Arc<RestService>>,
     web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<PseudonymiseQuery>,
 ) -> impl Responder {
+    let mut pseudonymiser = match SubjectPseudonymiser::new(&service, query.pseudonymise.as_deref())
+    {
+        Ok(pseudonymiser) => pseudonymiser,
+        Err(response) => return response,
+    };
+
     match service.db_service.find_subjects_by_group_id(id).await {
-        Ok(subjects) => HttpResponse::Ok().json(SubjectsResponse { subjects }),
+        Ok(subjects) => {
+            let subjects = subjects
+                .into_iter()
+                .map(|s| GroupSubject {
+                    id: pseudonymiser.subject(s.id),
+                    group_id: s.group_id,
+                    age: s.age,
+                    length_of_stay: s.length_of_stay,
+                    admitted_at: s.admitted_at,
+                })
+                .collect();
+            pseudonymiser
+                .respond(&service, id, SubjectsResponse { subjects })
+                .await
+        }
         Err(e) => {
-            println!("{:?}", e);
+            error!("{}", e);
             HttpResponse::BadRequest().json(models::Group { id: -1 })
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct ApiError {
     pub error: String,
     pub message: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// Logs a database error and returns it as a response, with 503 while the
+/// database is unreachable and 409 when the operation conflicts with existing
+/// data.
+fn db_error(e: silo_db::errors::DatabaseError) -> HttpResponse {
+    error!("{}", e);
+    if e.is_unavailable() {
+        return HttpResponse::ServiceUnavailable().json(ApiError {
+            error: "error.db.unavailable".into(),
+            message: format!("{}", e),
+        });
+    }
+
+    match e.is_conflict() {
+        true => HttpResponse::Conflict().json(ApiError {
+            error: "error.db.conflict".into(),
+            message: format!("{}", e),
+        }),
+        false => HttpResponse::BadRequest().json(ApiError {
+            error: "error.db.generic".into(),
+            message: format!("{:?}", e),
+        }),
+    }
+}
+
+/// Returns the response to a matrix that could not be generated.
+fn transform_error(e: TransformError) -> HttpResponse {
+    let error = match &e {
+        TransformError::NoSuchGroup(id) => return group_not_found(*id),
+        TransformError::Database(e) => return db_error(e.clone()),
+        TransformError::Rollup(_) => "error.matrix.rollup",
+        TransformError::NoSuchTrait(_) => "error.sampling.trait",
+        TransformError::Generate(_) => "error.matrix.generate",
+        TransformError::Bundle(_) => "error.matrix.bundle",
+        TransformError::NoSuchFile => "error.matrix.file",
+        TransformError::Canceled => return transform_unavailable(e),
+    };
+
+    error!("{}", e);
+    HttpResponse::BadRequest().json(ApiError {
+        error: error.into(),
+        message: format!("{}", e),
+    })
+}
+
+/// Returns the response to a request the transform actor could not handle.
+fn transform_unavailable<E: std::fmt::Display>(e: E) -> HttpResponse {
+    error!("{}", e);
+    HttpResponse::ServiceUnavailable().json(ApiError {
+        error: "error.transform.unavailable".into(),
+        message: format!("{}", e),
+    })
+}
+
+/// Creates a new group with the given subjects as members, returning its ID.
+async fn new_group(service: &RestService, subject_ids: &[i32]) -> Result<i32, HttpResponse> {
+    let group_ids = service
+        .db_service
+        .insert_groups_with_members(&[subject_ids.to_vec()])
+        .await
+        .map_err(db_error)?;
+
+    group_ids.into_iter().next().ok_or_else(|| {
+        HttpResponse::InternalServerError().json(ApiError {
+            error: "error.group.insert".into(),
+            message: "the group was not inserted".into(),
+        })
+    })
+}
+
+/// Returns the response to a request for a group that doesn't exist.
+fn group_not_found(id: i32) -> HttpResponse {
+    HttpResponse::NotFound().json(ApiError {
+        error: "error.group.not_found".into(),
+        message: format!("group {} does not exist", id),
+    })
+}
+
+/// The subjects of a group with their trait links, and every trait.
+struct GroupData {
+    subjects: Vec<models::Subject>,
+    links: Vec<models::SubjectTraitLink>,
+    traits: Vec<models::SubjectTrait>,
+}
+
+/// Fetches a group's subjects, their trait links and every trait. Returns None if
+/// the group doesn't exist.
+async fn group_data(service: &RestService, id: i32) -> Result<Option<GroupData>, HttpResponse> {
+    match service.db_service.find_group_by_id(id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(db_error(e)),
+    };
+
+    let subjects = service
+        .db_service
+        .find_subjects_by_group_id(id)
+        .await
+        .map_err(db_error)?;
+    let links = service
+        .db_service
+        .find_subject_trait_links_by_group_id(id)
+        .await
+        .map_err(db_error)?;
+    let traits = service.db_service.get_traits().await.map_err(db_error)?;
+
+    Ok(Some(GroupData {
+        subjects,
+        links,
+        traits,
+    }))
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct InsertTrait {
     pub parent_id: i32,
     pub trait_name: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct TraitsResponse {
     pub traits: Vec<models::SubjectTrait>,
 }
@@ -135,13 +388,7 @@ pub struct TraitsResponse {
 async fn traits_get(service: web::Data<Arc<RestService>>) -> impl Responder {
     match service.db_service.get_traits().await {
         Ok(traits) => HttpResponse::Ok().json(TraitsResponse { traits }),
-        Err(e) => {
-            println!("{:?}", e);
-            HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
-            })
-        }
+        Err(e) => db_error(e),
     }
 }
 
@@ -150,6 +397,19 @@ async fn traits_post(
     service: web::Data<Arc<RestService>>,
     _trait: web::Json<InsertTrait>,
 ) -> impl Responder {
+    let mut v = Validator::new();
+    _trait.validate(&mut v);
+    if _trait.parent_id > 0 {
+        if let Err(response) =
+            validation::check_trait_exists(&service, &mut v, "parentId", _trait.parent_id).await
+        {
+            return response;
+        }
+    }
+    if let Err(response) = v.finish() {
+        return response;
+    }
+
     let tr = models::SubjectTrait {
         id: 0, // Auto-generate ID
         parent_id: _trait.parent_id,
@@ -158,23 +418,29 @@ async fn traits_post(
 
     match service.db_service.insert_subject_trait(&tr).await {
         Ok(id) => HttpResponse::Ok().json(models::SubjectTrait { id, ..tr }),
-        Err(e) => {
-            println!("{:?}", e);
-            HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
-            })
-        }
+        Err(e) => db_error(e),
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct InsertSubjectSubjectTrait {
     pub trait_id: i32,
+    /// The time the trait started to apply, if known.
+    #[serde(default)]
+    pub onset_at: Option<DateTime<Utc>>,
+    /// The time the trait stopped applying, if it has.
+    #[serde(default)]
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// The value of the trait, such as a lab result, if it has one.
+    #[serde(default)]
+    pub value: Option<f64>,
+    /// The qualifier of the trait, such as mild, moderate or severe, if it has one.
+    #[serde(default)]
+    pub qualifier: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiCreationSuccess {
     pub id: i32,
@@ -183,26 +449,43 @@ pub struct ApiCreationSuccess {
 #[post("/groups/{group_id}/subjects/{subject_id}/traits")]
 async fn groups_subjects_traits_post(
     service: web::Data<Arc<RestService>>,
-    web::Path((_, subject_id)): web::Path<(i32, i32)>,
+    web::Path((group_id, subject_id)): web::Path<(i32, i32)>,
     subject_subject_trait: web::Json<InsertSubjectSubjectTrait>,
 ) -> impl Responder {
-    match service
-        .db_service
-        .insert_subject_subject_trait(subject_id, subject_subject_trait.trait_id)
-        .await
+    let mut v = Validator::new();
+    subject_subject_trait.validate(&mut v);
+    if let Err(response) =
+        validation::check_subject_in_group(&service, &mut v, group_id, subject_id).await
+    {
+        return response;
+    }
+    if let Err(response) =
+        validation::check_trait_exists(&service, &mut v, "traitId", subject_subject_trait.trait_id)
+            .await
     {
+        return response;
+    }
+    if let Err(response) = v.finish() {
+        return response;
+    }
+
+    let link = models::SubjectTraitLink {
+        id: 0,
+        subject_id,
+        subject_trait_id: subject_subject_trait.trait_id,
+        onset_at: subject_subject_trait.onset_at,
+        resolved_at: subject_subject_trait.resolved_at,
+        value: subject_subject_trait.value,
+        qualifier: subject_subject_trait.qualifier.clone(),
+    };
+
+    match service.db_service.insert_subject_subject_trait(&link).await {
         Ok(id) => HttpResponse::Ok().json(ApiCreationSuccess { id }),
-        Err(e) => {
-            println!("{:?}", e);
-            HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
-            })
-        }
+        Err(e) => db_error(e),
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SubjectTraitsResponse {
     pub traits: Vec<models::SubjectTrait>,
@@ -219,112 +502,386 @@ async fn groups_subjects_traits_get(
         .await
     {
         Ok(traits) => HttpResponse::Ok().json(SubjectTraitsResponse { traits }),
-        Err(e) => {
-            println!("{:?}", e);
-            HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
-            })
-        }
+        Err(e) => db_error(e),
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct MatrixGenQuery {
     pub attributes: String,
+    /// The trait columns, each a trait name optionally followed by how it is
+    /// output: :presence (default), :value or :severity. Value and severity
+    /// columns are named after the trait with a _value or _severity suffix.
+    #[serde(default)]
     pub traits: String,
     pub fields: bool,
+    /// Rolls traits up to their ancestors at this depth of the trait tree. Cannot
+    /// be combined with rollup_to.
+    pub rollup_depth: Option<usize>,
+    /// Rolls traits up to the depth of the named trait.
+    pub rollup_to: Option<String>,
+    /// The output format: tsv (default), csv, json, mtx, libsvm, coo, arrow, parquet
+    /// or arff.
+    pub format: Option<String>,
+    /// The column used as the label of libsvm output.
+    pub label: Option<String>,
+    /// The file of the export to return: matrix (default), rows, columns, codebook
+    /// or codebook_table.
+    pub file: Option<String>,
+    /// The format of the codebook table: markdown (default) or csv.
+    pub codebook_format: Option<String>,
+    /// Returns every file of the export as a single zip archive.
+    #[serde(default)]
+    pub bundle: bool,
+    /// Adds a split column labelling the train, validation or test set of each
+    /// subject, split with this seed.
+    pub split_seed: Option<u64>,
+    /// The proportion of subjects in the training set of the split.
+    pub split_train: Option<f64>,
+    /// The proportion of subjects in the validation set of the split.
+    pub split_validation: Option<f64>,
+    /// The proportion of subjects in the test set of the split.
+    pub split_test: Option<f64>,
+    /// The name of the trait the split is stratified by.
+    pub split_stratify_trait: Option<String>,
+    /// The width of the age bands the split is stratified by, in years.
+    pub split_age_band: Option<i16>,
+    /// Only counts traits that applied in a window of time: at_admission,
+    /// within_days (with window_days) or between (with window_from and/or
+    /// window_to).
+    pub window: Option<String>,
+    /// The number of days after admission of a within_days window.
+    pub window_days: Option<i64>,
+    /// The start of a between window.
+    pub window_from: Option<DateTime<Utc>>,
+    /// The end of a between window.
+    pub window_to: Option<DateTime<Utc>>,
+    /// Replaces subject IDs with tokens: hmac, random or none. Exports are
+    /// pseudonymised with the server's default method if its policy requires it.
+    pub pseudonymise: Option<String>,
+}
+
+/// Parses the output type of a matrix of a group from its query.
+fn matrix_output_type(group_id: i32, query: &MatrixGenQuery) -> Result<MatrixOutputType, String> {
+    match query.format.as_deref().unwrap_or("tsv") {
+        "tsv" => Ok(MatrixOutputType::Tsv),
+        "csv" => Ok(MatrixOutputType::Csv),
+        "json" => Ok(MatrixOutputType::Json),
+        "mtx" => Ok(MatrixOutputType::MatrixMarket),
+        "coo" => Ok(MatrixOutputType::Coo),
+        "arrow" | "feather" => Ok(MatrixOutputType::ArrowIpc),
+        "parquet" => Ok(MatrixOutputType::Parquet),
+        "arff" => Ok(MatrixOutputType::Arff {
+            relation: format!("group_{}", group_id),
+        }),
+        "libsvm" => match &query.label {
+            Some(label) => Ok(MatrixOutputType::Libsvm {
+                label: label.clone(),
+            }),
+            None => Err("libsvm output requires a label".into()),
+        },
+        format => Err(format!("unknown matrix format {}", format)),
+    }
+}
+
+/// Parses the kind of file to return from a matrix export from its query.
+fn matrix_file_kind(query: &MatrixGenQuery) -> Result<MatrixFileKind, String> {
+    match query.file.as_deref().unwrap_or("matrix") {
+        "matrix" => Ok(MatrixFileKind::Matrix),
+        "rows" => Ok(MatrixFileKind::RowIds),
+        "columns" => Ok(MatrixFileKind::ColumnNames),
+        "codebook" => Ok(MatrixFileKind::Codebook),
+        "codebook_table" => Ok(MatrixFileKind::CodebookTable),
+        file => Err(format!("unknown matrix file {}", file)),
+    }
+}
+
+/// Parses the format of the codebook table from a matrix query.
+fn codebook_format(query: &MatrixGenQuery) -> Result<CodebookFormat, String> {
+    match query.codebook_format.as_deref().unwrap_or("markdown") {
+        "markdown" | "md" => Ok(CodebookFormat::Markdown),
+        "csv" => Ok(CodebookFormat::Csv),
+        format => Err(format!("unknown codebook format {}", format)),
+    }
+}
+
+/// Parses the trait columns of a matrix query into trait names and how they are
+/// output. The name of each column must differ from those of the others and of
+/// the attributes.
+fn trait_columns(query: &MatrixGenQuery) -> Result<Vec<(&str, TraitOutput)>, String> {
+    let mut columns: Vec<(&str, TraitOutput)> = vec![];
+    let mut names: Vec<String> = query.attributes.split(',').map(String::from).collect();
+
+    for column in query.traits.split(',').filter(|column| !column.is_empty()) {
+        let (name, output) = match column.rfind(':') {
+            Some(i) => (&column[..i], &column[i + 1..]),
+            None => (column, "presence"),
+        };
+
+        let output = match output {
+            "presence" => TraitOutput::Presence,
+            "value" => TraitOutput::Value,
+            "severity" => TraitOutput::Severity,
+            output => return Err(format!("unknown output {} of trait {}", output, name)),
+        };
+
+        let column_name = output.column_name(name);
+        if names.contains(&column_name) {
+            return Err(format!("more than one column is named {}", column_name));
+        }
+        names.push(column_name);
+        columns.push((name, output));
+    }
+
+    Ok(columns)
+}
+
+/// Parses the time window traits must apply in from a matrix query, if any.
+fn time_window(query: &MatrixGenQuery) -> Result<Option<TimeWindow>, String> {
+    let window = match query.window.as_deref() {
+        None => return Ok(None),
+        Some("at_admission") => TimeWindow::AtAdmission,
+        Some("within_days") => match query.window_days {
+            Some(days) => TimeWindow::WithinDays(days),
+            None => return Err("a within_days window requires window_days".into()),
+        },
+        Some("between") => TimeWindow::Between {
+            from: query.window_from,
+            to: query.window_to,
+        },
+        Some(window) => return Err(format!("unknown time window {}", window)),
+    };
+
+    window.validate().map(|_| Some(window))
 }
 
 #[get("/groups/{id}/generate/matrix")]
 async fn groups_generate_matrix(
     service: web::Data<Arc<RestService>>,
+    transform: web::Data<Addr<TransformActor>>,
     web::Path(id): web::Path<i32>,
     web::Query(query): web::Query<MatrixGenQuery>,
 ) -> impl Responder {
-    let trait_names = query.traits.split(",");
-    let attribute_names = query.attributes.split(",");
-
-    // TODO: convert to a Stream
-    let mut trait_ids = vec![];
-    for name in trait_names.clone() {
-        match service.db_service.find_subject_trait_by_name(name).await {
-            Ok(Some(s)) => {
-                trait_ids.push(s.id);
-            }
-            _ => (),
+    let started = Instant::now();
+    let pseudonymiser = match service
+        .pseudonym_policy
+        .pseudonymiser(query.pseudonymise.as_deref())
+    {
+        Ok(pseudonymiser) => pseudonymiser,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.matrix.pseudonymise".into(),
+                message,
+            });
         }
-    }
+    };
 
-    let subjects = match service.db_service.find_subjects_by_group_id(id).await {
-        Ok(s) => s,
-        Err(e) => {
-            println!("{:?}", e);
+    let window = match time_window(&query) {
+        Ok(window) => window,
+        Err(message) => {
             return HttpResponse::BadRequest().json(ApiError {
-                error: "error.db.generic".into(),
-                message: format!("{:?}", e),
+                error: "error.matrix.window".into(),
+                message,
             });
         }
     };
 
-    let mut matrix_rows = vec![];
-
-    for subject in subjects {
-        let mut int_fields: HashMap<String, i32> = HashMap::new();
-        let mut binary_fields: HashMap<String, bool> = HashMap::new();
-
-        if attribute_names.clone().into_iter().any(|i| i == "id") {
-            int_fields.insert("id".into(), subject.id.into());
+    let formats = (
+        matrix_output_type(id, &query),
+        matrix_file_kind(&query),
+        codebook_format(&query),
+    );
+    let (output_type, file_kind, codebook_format) = match formats {
+        (Ok(output_type), Ok(file_kind), Ok(codebook_format)) => {
+            (output_type, file_kind, codebook_format)
         }
-
-        if attribute_names.clone().into_iter().any(|i| i == "age") {
-            int_fields.insert("age".into(), subject.age.into());
+        (Err(message), _, _) | (_, Err(message), _) | (_, _, Err(message)) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.matrix.format".into(),
+                message,
+            });
         }
+    };
 
-        if attribute_names
-            .clone()
-            .into_iter()
-            .any(|i| i == "length_of_stay")
-        {
-            int_fields.insert("length_of_stay".into(), subject.length_of_stay.into());
+    let trait_columns = match trait_columns(&query) {
+        Ok(columns) => columns,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.matrix.traits".into(),
+                message,
+            });
         }
+    };
+    let attribute_names = query.attributes.split(",");
 
-        let traits = service
-            .db_service
-            .find_subject_trats_by_subject_id(subject.id)
-            .await
-            .unwrap();
+    // Sparse outputs hold numbers only. The tokens are in their row ID file.
+    if output_type.is_sparse()
+        && pseudonymiser.is_some()
+        && attribute_names.clone().any(|i| i == "id")
+    {
+        return HttpResponse::BadRequest().json(ApiError {
+            error: "error.matrix.format".into(),
+            message: "pseudonymised IDs cannot be exported as a column of a sparse matrix; \
+                      they are in its row ID file"
+                .into(),
+        });
+    }
 
-        let trait_iter = traits.iter();
+    let rollup = match (query.rollup_depth, &query.rollup_to) {
+        (Some(_), Some(_)) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.matrix.rollup".into(),
+                message: "roll traits up to either a depth or a trait".into(),
+            });
+        }
+        (Some(depth), None) => Some(RollupLevel::Depth(depth)),
+        (None, Some(name)) => Some(RollupLevel::To(name.clone())),
+        (None, None) => None,
+    };
 
-        for name in trait_names.clone() {
-            if trait_iter.clone().any(|x| x.trait_name == name) {
-                binary_fields.insert(name.clone().into(), true);
-            } else {
-                binary_fields.insert(name.clone().into(), false);
+    // The trait a split is stratified by is looked up with the data of the
+    // group, in the transform actor.
+    let split = match query.split_seed {
+        Some(seed) => {
+            let options = match sampling::split_options(
+                [
+                    "split_train",
+                    "split_validation",
+                    "split_test",
+                    "split_age_band",
+                ],
+                query.split_train,
+                query.split_validation,
+                query.split_test,
+                None,
+                query.split_age_band,
+                &[],
+            ) {
+                Ok(options) => options,
+                Err(response) => return response,
+            };
+
+            if query.split_stratify_trait.is_some() && options.stratify.is_some() {
+                return sampling::stratify_conflict();
             }
-        }
 
-        matrix_rows.push(MatrixTransformerRow::new(binary_fields, int_fields));
-    }
+            Some(MatrixSplit {
+                seed,
+                options,
+                stratify_trait: query.split_stratify_trait.clone(),
+            })
+        }
+        None => None,
+    };
 
-    let mut transformer = MatrixTransformerBuilder::new()
-        .output_as(MatrixOutputType::Tsv)
-        .with_header(query.fields);
+    // Building and formatting the matrix is CPU heavy, so it runs on the worker
+    // pool of the transform actor rather than on this executor.
+    let generated = transform
+        .send(GenerateMatrix {
+            group_id: id,
+            attributes: attribute_names.map(String::from).collect(),
+            traits: trait_columns
+                .into_iter()
+                .map(|(name, output)| (name.to_string(), output))
+                .collect(),
+            window,
+            rollup,
+            split,
+            pseudonymiser,
+            output_type,
+            header: query.fields,
+            codebook_format,
+            selection: match query.bundle {
+                true => MatrixSelection::Bundle,
+                false => MatrixSelection::File(file_kind),
+            },
+        })
+        .await;
+
+    let GroupMatrix {
+        file,
+        pseudonymiser,
+    } = match generated {
+        Ok(Ok(matrix)) => matrix,
+        Ok(Err(e)) => return transform_error(e),
+        Err(e) => return transform_unavailable(e),
+    };
 
-    for name in trait_names.clone() {
-        transformer = transformer.with_binary_field(name);
-    }
+    let export_id = match &pseudonymiser {
+        Some(p) => match pseudonyms::record_export(&service, id, p).await {
+            Ok(export_id) => Some(export_id),
+            Err(response) => return response,
+        },
+        None => None,
+    };
 
-    for name in attribute_names.clone() {
-        transformer = transformer.with_int_field(name);
+    let mut response = HttpResponse::Ok();
+    if let Some(export_id) = export_id {
+        response.header("X-Silo-Export-Id", export_id.to_string());
     }
 
-    let t = transformer.build();
+    let format = match query.bundle {
+        true => "bundle",
+        false => query.format.as_deref().unwrap_or("tsv"),
+    };
+    service
+        .metrics
+        .observe_export(format, file.contents.len(), started.elapsed());
+
+    response
+        .content_type(file.content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file.name),
+        )
+        .body(file.contents)
+}
 
-    let matrix = t.generate(matrix_rows).unwrap();
+/// Registers every route of the API. Routes are relative to /api/v1.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(traits_get)
+        .service(traits_post)
+        .service(groups_post)
+        .service(groups_get)
+        .service(groups_generate_matrix)
+        .service(stats::groups_stats_get)
+        .service(analysis::groups_analysis_cooccurrence_get)
+        .service(analysis::groups_analysis_comparison_get)
+        .service(cohorts::cohorts_post)
+        .service(cohorts::cohorts_get)
+        .service(cohorts::cohorts_id_get)
+        .service(cohorts::cohorts_id_put)
+        .service(cohorts::cohorts_id_delete)
+        .service(cohorts::cohorts_id_materialise_post)
+        .service(cohorts::cohorts_id_materialisations_get)
+        .service(matching::groups_match_post)
+        .service(sampling::groups_sample_post)
+        .service(sampling::groups_split_post)
+        .service(groups_subjects_post)
+        .service(pseudonyms::admin_reidentify_post)
+        .service(privacy::groups_private_count_get)
+        .service(privacy::groups_private_histogram_get)
+        .service(privacy::cohorts_private_count_get)
+        .service(privacy::privacy_budget_get)
+        .service(privacy::admin_privacy_budgets_put)
+        .service(external_ids::groups_subjects_import_post)
+        .service(external_ids::groups_subjects_external_get)
+        .service(external_ids::groups_subjects_external_ids_get)
+        .service(external_ids::subjects_duplicates_get)
+        .service(groups_subjects_get)
+        .service(groups_subjects_traits_post)
+        .service(groups_subjects_traits_get)
+        .service(openapi::openapi_get)
+        .service(openapi::docs_get);
+}
 
-    HttpResponse::Ok().body(matrix)
+/// Registers the routes served at the root of the server rather than under
+/// /api/v1, where orchestration and monitoring expect them.
+fn root_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(health::healthz_get)
+        .service(health::readyz_get)
+        .service(health::version_get)
+        .service(metrics::metrics_get);
 }
 
 pub async fn build_and_serve_http(service: RestService) -> Result<(), Box<dyn std::error::Error>> {
@@ -334,22 +891,21 @@ pub async fn build_and_serve_http(service: RestService) -> Result<(), Box<dyn st
     // TODO: use config or env for port.
     let address = format!("127.0.0.1:3030");
 
-    println!("Server running at {}", address);
+    info!("Server running at {}", address);
 
     let service_arc = Arc::new(service);
     let server_res = HttpServer::new(move || {
-        App::new().data(service_arc.clone()).service(
-            web::scope("/api/v1")
-                .service(traits_get)
-                .service(traits_post)
-                .service(groups_post)
-                .service(groups_get)
-                .service(groups_generate_matrix)
-                .service(groups_subjects_post)
-                .service(groups_subjects_get)
-                .service(groups_subjects_traits_post)
-                .service(groups_subjects_traits_get),
-        )
+        let db = DbActor::new(service_arc.db_service.clone()).start();
+
+        App::new()
+            .wrap(metrics::RequestMetrics::new(service_arc.metrics.clone()))
+            .wrap(logging::RequestLogging)
+            .data(service_arc.clone())
+            // Each worker has its own database and transform actors.
+            .data(db.clone())
+            .data(TransformActor::new(db).start())
+            .configure(root_routes)
+            .service(web::scope("/api/v1").configure(routes))
     })
     .bind(address)?
     .run()
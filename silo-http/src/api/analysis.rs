@@ -0,0 +1,204 @@
+use super::{group_data, group_not_found, ApiError, RestService};
+use silo_core::analysis::{
+    AssociationTest, AttributeTest, ComparisonOptions, Cooccurrence, GroupComparison, Sample,
+};
+use silo_core::models::SubjectTrait;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CooccurrenceQuery {
+    /// The comma-separated names of the traits to analyse. Defaults to every trait
+    /// held by a subject of the group.
+    #[serde(default)]
+    pub traits: String,
+    /// The test of association: auto (default), chi_square or fisher.
+    pub test: Option<String>,
+    /// The output format: json (default) or tsv.
+    pub format: Option<String>,
+    /// The table returned as TSV: pairs (default) or counts.
+    pub table: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ComparisonQuery {
+    /// The ID of the control group.
+    pub control: i32,
+    /// The comma-separated names of the traits to compare. Defaults to every trait
+    /// held by a subject of either group.
+    #[serde(default)]
+    pub traits: String,
+    /// The test of association of traits: auto (default), chi_square or fisher.
+    pub trait_test: Option<String>,
+    /// The test of attributes: t_test (default) or mann_whitney.
+    pub attribute_test: Option<String>,
+}
+
+impl ComparisonQuery {
+    /// Returns the ComparisonOptions of the query, falling back to the defaults.
+    fn options(&self) -> Result<ComparisonOptions, String> {
+        let defaults = ComparisonOptions::default();
+
+        Ok(ComparisonOptions {
+            trait_test: match &self.trait_test {
+                Some(test) => test.parse()?,
+                None => defaults.trait_test,
+            },
+            attribute_test: match &self.attribute_test {
+                Some(test) => test.parse::<AttributeTest>()?,
+                None => defaults.attribute_test,
+            },
+        })
+    }
+}
+
+/// Selects the comma-separated traits named in a query, or every held trait if
+/// none are named.
+fn selected_traits(
+    names: &str,
+    traits: &[SubjectTrait],
+    held: &HashSet<i32>,
+) -> Result<Vec<SubjectTrait>, String> {
+    let names: Vec<&str> = names.split(",").filter(|n| !n.is_empty()).collect();
+
+    if names.is_empty() {
+        return Ok(traits
+            .iter()
+            .filter(|t| held.contains(&t.id))
+            .cloned()
+            .collect());
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            traits
+                .iter()
+                .find(|t| t.trait_name == name)
+                .cloned()
+                .ok_or_else(|| format!("unknown trait {}", name))
+        })
+        .collect()
+}
+
+#[get("/groups/{id}/analysis/cooccurrence")]
+pub(super) async fn groups_analysis_cooccurrence_get(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<CooccurrenceQuery>,
+) -> impl Responder {
+    let test = match query
+        .test
+        .as_deref()
+        .unwrap_or("auto")
+        .parse::<AssociationTest>()
+    {
+        Ok(test) => test,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.analysis.test".into(),
+                message,
+            });
+        }
+    };
+
+    let data = match group_data(&service, id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(id),
+        Err(response) => return response,
+    };
+
+    let held: HashSet<i32> = data.links.iter().map(|l| l.subject_trait_id).collect();
+    let traits = match selected_traits(&query.traits, &data.traits, &held) {
+        Ok(traits) => traits,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.analysis.trait".into(),
+                message,
+            });
+        }
+    };
+
+    let cooccurrence = Cooccurrence::new(id, &data.subjects, &data.links, &traits, test);
+
+    match (query.format.as_deref(), query.table.as_deref()) {
+        (None, _) | (Some("json"), _) => HttpResponse::Ok().json(cooccurrence),
+        (Some("tsv"), None) | (Some("tsv"), Some("pairs")) => HttpResponse::Ok()
+            .content_type("text/tab-separated-values")
+            .body(cooccurrence.pairs_tsv()),
+        (Some("tsv"), Some("counts")) => HttpResponse::Ok()
+            .content_type("text/tab-separated-values")
+            .body(cooccurrence.counts_tsv()),
+        (Some("tsv"), Some(table)) => HttpResponse::BadRequest().json(ApiError {
+            error: "error.analysis.format".into(),
+            message: format!("unknown table {}", table),
+        }),
+        (Some(format), _) => HttpResponse::BadRequest().json(ApiError {
+            error: "error.analysis.format".into(),
+            message: format!("unknown format {}", format),
+        }),
+    }
+}
+
+#[get("/groups/{id}/analysis/comparison")]
+pub(super) async fn groups_analysis_comparison_get(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<ComparisonQuery>,
+) -> impl Responder {
+    let options = match query.options() {
+        Ok(options) => options,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.analysis.test".into(),
+                message,
+            });
+        }
+    };
+
+    let case = match group_data(&service, id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(id),
+        Err(response) => return response,
+    };
+    let control = match group_data(&service, query.control).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(query.control),
+        Err(response) => return response,
+    };
+
+    let held: HashSet<i32> = case
+        .links
+        .iter()
+        .chain(control.links.iter())
+        .map(|l| l.subject_trait_id)
+        .collect();
+    let traits = match selected_traits(&query.traits, &case.traits, &held) {
+        Ok(traits) => traits,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.analysis.trait".into(),
+                message,
+            });
+        }
+    };
+
+    HttpResponse::Ok().json(GroupComparison::new(
+        Sample {
+            group_id: id,
+            subjects: &case.subjects,
+            links: &case.links,
+        },
+        Sample {
+            group_id: query.control,
+            subjects: &control.subjects,
+            links: &control.links,
+        },
+        &traits,
+        &options,
+    ))
+}
@@ -0,0 +1,297 @@
+use super::{db_error, ApiCreationSuccess, ApiError, RestService};
+use silo_core::cohort::CohortDefinition;
+use silo_core::models;
+
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CohortBody {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub definition: CohortDefinition,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CohortsResponse {
+    pub cohorts: Vec<models::Cohort>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CohortMaterialisationsResponse {
+    pub materialisations: Vec<models::CohortMaterialisation>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MaterialiseQuery {
+    /// Writes the cohort into a new group instead of refreshing the group it was
+    /// last materialised into.
+    #[serde(default)]
+    pub new_group: bool,
+}
+
+/// Returns the response to a request for a cohort that doesn't exist.
+fn cohort_not_found(id: i32) -> HttpResponse {
+    HttpResponse::NotFound().json(ApiError {
+        error: "error.cohort.not_found".into(),
+        message: format!("cohort {} does not exist", id),
+    })
+}
+
+/// Checks that a definition only references existing traits and groups.
+pub(super) async fn validate_definition(
+    service: &RestService,
+    definition: &CohortDefinition,
+) -> Result<(), HttpResponse> {
+    let invalid = |message| {
+        HttpResponse::BadRequest().json(ApiError {
+            error: "error.cohort.definition".into(),
+            message,
+        })
+    };
+
+    let traits = service.db_service.get_traits().await.map_err(db_error)?;
+    definition.validate(&traits).map_err(invalid)?;
+
+    for id in &definition.source_group_ids {
+        match service.db_service.find_group_by_id(*id).await {
+            Ok(Some(_)) => (),
+            Ok(None) => return Err(invalid(format!("group {} does not exist", id))),
+            Err(e) => return Err(db_error(e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches a cohort by ID, responding with 404 if it doesn't exist.
+pub(super) async fn find_cohort(
+    service: &RestService,
+    id: i32,
+) -> Result<models::Cohort, HttpResponse> {
+    match service.db_service.find_cohort_by_id(id).await {
+        Ok(Some(cohort)) => Ok(cohort),
+        Ok(None) => Err(cohort_not_found(id)),
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+/// Returns the IDs of the subjects of a cohort's source groups matching its
+/// definition.
+pub(super) async fn select_subjects(
+    service: &RestService,
+    cohort: &models::Cohort,
+) -> Result<Vec<i32>, HttpResponse> {
+    let mut subjects = vec![];
+    let mut links = vec![];
+    for group_id in &cohort.definition.source_group_ids {
+        match service
+            .db_service
+            .find_subjects_by_group_id(*group_id)
+            .await
+        {
+            Ok(s) => subjects.extend(s),
+            Err(e) => return Err(db_error(e)),
+        };
+        match service
+            .db_service
+            .find_subject_trait_links_by_group_id(*group_id)
+            .await
+        {
+            Ok(l) => links.extend(l),
+            Err(e) => return Err(db_error(e)),
+        };
+    }
+    let traits = match service.db_service.get_traits().await {
+        Ok(traits) => traits,
+        Err(e) => return Err(db_error(e)),
+    };
+
+    Ok(cohort.definition.select(&subjects, &links, &traits))
+}
+
+#[post("/cohorts")]
+pub(super) async fn cohorts_post(
+    service: web::Data<Arc<RestService>>,
+    body: web::Json<CohortBody>,
+) -> impl Responder {
+    let body = body.into_inner();
+    if let Err(response) = validate_definition(&service, &body.definition).await {
+        return response;
+    }
+
+    let now = Utc::now();
+    let cohort = models::Cohort {
+        id: 0,
+        name: body.name,
+        description: body.description,
+        definition: body.definition,
+        version: 1,
+        group_id: None,
+        created_at: now,
+        updated_at: now,
+    };
+
+    match service.db_service.insert_cohort(&cohort).await {
+        Ok(id) => HttpResponse::Ok().json(models::Cohort { id, ..cohort }),
+        Err(e) => db_error(e),
+    }
+}
+
+#[get("/cohorts")]
+pub(super) async fn cohorts_get(service: web::Data<Arc<RestService>>) -> impl Responder {
+    match service.db_service.get_cohorts().await {
+        Ok(cohorts) => HttpResponse::Ok().json(CohortsResponse { cohorts }),
+        Err(e) => db_error(e),
+    }
+}
+
+#[get("/cohorts/{id}")]
+pub(super) async fn cohorts_id_get(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+) -> impl Responder {
+    match find_cohort(&service, id).await {
+        Ok(cohort) => HttpResponse::Ok().json(cohort),
+        Err(response) => response,
+    }
+}
+
+#[put("/cohorts/{id}")]
+pub(super) async fn cohorts_id_put(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    body: web::Json<CohortBody>,
+) -> impl Responder {
+    let body = body.into_inner();
+    let cohort = match find_cohort(&service, id).await {
+        Ok(cohort) => cohort,
+        Err(response) => return response,
+    };
+    if let Err(response) = validate_definition(&service, &body.definition).await {
+        return response;
+    }
+
+    // Only changes to the definition produce a new version.
+    let version = match body.definition == cohort.definition {
+        true => cohort.version,
+        false => cohort.version + 1,
+    };
+    let cohort = models::Cohort {
+        name: body.name,
+        description: body.description,
+        definition: body.definition,
+        version,
+        updated_at: Utc::now(),
+        ..cohort
+    };
+
+    match service.db_service.update_cohort(&cohort).await {
+        Ok(_) => HttpResponse::Ok().json(cohort),
+        Err(e) => db_error(e),
+    }
+}
+
+#[delete("/cohorts/{id}")]
+pub(super) async fn cohorts_id_delete(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+) -> impl Responder {
+    match service.db_service.delete_cohort(id).await {
+        Ok(true) => HttpResponse::Ok().json(ApiCreationSuccess { id }),
+        Ok(false) => cohort_not_found(id),
+        Err(e) => db_error(e),
+    }
+}
+
+#[post("/cohorts/{id}/materialise")]
+pub(super) async fn cohorts_id_materialise_post(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<MaterialiseQuery>,
+) -> impl Responder {
+    let cohort = match find_cohort(&service, id).await {
+        Ok(cohort) => cohort,
+        Err(response) => return response,
+    };
+    // Source groups may have been removed since the cohort was saved.
+    if let Err(response) = validate_definition(&service, &cohort.definition).await {
+        return response;
+    }
+
+    let subject_ids = match select_subjects(&service, &cohort).await {
+        Ok(subject_ids) => subject_ids,
+        Err(response) => return response,
+    };
+
+    let group_id = match cohort.group_id {
+        Some(group_id) if !query.new_group => group_id,
+        _ => match service
+            .db_service
+            .insert_group(&models::Group { id: 0 })
+            .await
+        {
+            Ok(group_id) => group_id,
+            Err(e) => return db_error(e),
+        },
+    };
+
+    let materialisation = models::CohortMaterialisation {
+        id: 0,
+        cohort_id: cohort.id,
+        version: cohort.version,
+        definition: Some(cohort.definition.clone()),
+        group_id,
+        subject_count: subject_ids.len() as i32,
+        materialised_at: Utc::now(),
+    };
+    let materialisation = match service
+        .db_service
+        .insert_cohort_materialisation(&materialisation, &subject_ids)
+        .await
+    {
+        Ok(id) => models::CohortMaterialisation {
+            id,
+            ..materialisation
+        },
+        Err(e) => return db_error(e),
+    };
+
+    if cohort.group_id != Some(group_id) {
+        let cohort = models::Cohort {
+            group_id: Some(group_id),
+            ..cohort
+        };
+        if let Err(e) = service.db_service.update_cohort(&cohort).await {
+            return db_error(e);
+        }
+    }
+
+    HttpResponse::Ok().json(materialisation)
+}
+
+#[get("/cohorts/{id}/materialisations")]
+pub(super) async fn cohorts_id_materialisations_get(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+) -> impl Responder {
+    if let Err(response) = find_cohort(&service, id).await {
+        return response;
+    }
+
+    match service
+        .db_service
+        .find_cohort_materialisations_by_cohort_id(id)
+        .await
+    {
+        Ok(materialisations) => {
+            HttpResponse::Ok().json(CohortMaterialisationsResponse { materialisations })
+        }
+        Err(e) => db_error(e),
+    }
+}
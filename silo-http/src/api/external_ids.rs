@@ -0,0 +1,188 @@
+use super::validation::Validator;
+use super::{db_error, group_not_found, ApiError, InsertSubject, RestService};
+use silo_core::duplicates::{find_duplicates, DuplicateSet};
+use silo_core::models;
+
+use actix_web::{get, post, web, HttpResponse, Responder};
+use log::error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportBody {
+    /// The subjects to insert, or update if the group has a subject with any of
+    /// their external IDs.
+    pub subjects: Vec<InsertSubject>,
+}
+
+/// What became of a subject of an import.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportOutcome {
+    /// A new subject was inserted.
+    Inserted,
+    /// An existing subject with one of its external IDs was updated.
+    Updated,
+    /// Its external IDs belong to more than one subject of the group, so
+    /// nothing was changed.
+    Conflict,
+    /// The subject could not be imported, and nothing was changed.
+    Failed,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub outcome: ImportOutcome,
+    /// The ID of the subject, if it was inserted or updated.
+    pub subject_id: Option<i32>,
+    /// Why the subject was not imported, if it wasn't.
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResponse {
+    pub group_id: i32,
+    /// The number of subjects inserted.
+    pub inserted: usize,
+    /// The number of existing subjects updated.
+    pub updated: usize,
+    /// The number of subjects that could not be imported.
+    pub failed: usize,
+    /// The result of each subject, in the order they were given. Each subject
+    /// is imported or not on its own, so a failed subject doesn't undo the
+    /// others.
+    pub results: Vec<ImportResult>,
+}
+
+#[post("/groups/{id}/subjects/import")]
+pub(super) async fn groups_subjects_import_post(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    body: web::Json<ImportBody>,
+) -> impl Responder {
+    match service.db_service.find_group_by_id(id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return group_not_found(id),
+        Err(e) => return db_error(e),
+    };
+
+    let mut v = Validator::new();
+    for (i, subject) in body.subjects.iter().enumerate() {
+        v.nested(&format!("subjects[{}]", i), subject);
+    }
+    if let Err(response) = v.finish() {
+        return response;
+    }
+
+    let mut response = ImportResponse {
+        group_id: id,
+        inserted: 0,
+        updated: 0,
+        failed: 0,
+        results: vec![],
+    };
+
+    for subject in &body.subjects {
+        let (s, external_ids) = subject.to_models(id);
+
+        let result = match service.db_service.upsert_subject(&s, &external_ids).await {
+            Ok((subject_id, inserted)) => ImportResult {
+                outcome: match inserted {
+                    true => ImportOutcome::Inserted,
+                    false => ImportOutcome::Updated,
+                },
+                subject_id: Some(subject_id),
+                message: None,
+            },
+            Err(e) => {
+                error!("{}", e);
+                ImportResult {
+                    outcome: match e.is_conflict() {
+                        true => ImportOutcome::Conflict,
+                        false => ImportOutcome::Failed,
+                    },
+                    subject_id: None,
+                    message: Some(format!("{}", e)),
+                }
+            }
+        };
+
+        match result.outcome {
+            ImportOutcome::Inserted => response.inserted += 1,
+            ImportOutcome::Updated => response.updated += 1,
+            ImportOutcome::Conflict | ImportOutcome::Failed => response.failed += 1,
+        }
+        response.results.push(result);
+    }
+
+    HttpResponse::Ok().json(response)
+}
+
+#[get("/groups/{id}/subjects/external/{namespace}/{value}")]
+pub(super) async fn groups_subjects_external_get(
+    service: web::Data<Arc<RestService>>,
+    web::Path((id, namespace, value)): web::Path<(i32, String, String)>,
+) -> impl Responder {
+    match service
+        .db_service
+        .find_subject_by_external_id(id, &namespace, &value)
+        .await
+    {
+        Ok(Some(subject)) => HttpResponse::Ok().json(subject),
+        Ok(None) => HttpResponse::NotFound().json(ApiError {
+            error: "error.subject.not_found".into(),
+            message: format!("group {} has no subject with {} {}", id, namespace, value),
+        }),
+        Err(e) => db_error(e),
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalIdsResponse {
+    pub external_ids: Vec<models::ExternalId>,
+}
+
+#[get("/groups/{group_id}/subjects/{subject_id}/external_ids")]
+pub(super) async fn groups_subjects_external_ids_get(
+    service: web::Data<Arc<RestService>>,
+    web::Path((_, subject_id)): web::Path<(i32, i32)>,
+) -> impl Responder {
+    match service
+        .db_service
+        .find_external_ids_by_subject_id(subject_id)
+        .await
+    {
+        Ok(external_ids) => HttpResponse::Ok().json(ExternalIdsResponse { external_ids }),
+        Err(e) => db_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DuplicatesQuery {
+    /// Only compares external IDs of this namespace.
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicatesResponse {
+    pub duplicates: Vec<DuplicateSet>,
+}
+
+#[get("/subjects/duplicates")]
+pub(super) async fn subjects_duplicates_get(
+    service: web::Data<Arc<RestService>>,
+    web::Query(query): web::Query<DuplicatesQuery>,
+) -> impl Responder {
+    match service.db_service.get_external_ids().await {
+        Ok(external_ids) => HttpResponse::Ok().json(DuplicatesResponse {
+            duplicates: find_duplicates(&external_ids, query.namespace.as_deref()),
+        }),
+        Err(e) => db_error(e),
+    }
+}
@@ -0,0 +1,108 @@
+use super::RestService;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// The git commit the server was built from, or unknown outside a repository.
+const GIT_COMMIT: &str = env!("SILO_GIT_COMMIT");
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthResponse {
+    pub status: String,
+}
+
+/// A check made before the server is ready to handle requests.
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessCheck {
+    /// The name of the check: database or migrations.
+    pub name: String,
+    pub ok: bool,
+    /// Why the check failed, if it did.
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionResponse {
+    /// The version of the server.
+    pub version: String,
+    /// The git commit the server was built from.
+    pub git_commit: String,
+    /// The version of the last migration applied to the database, if it could
+    /// be read.
+    pub schema_version: Option<i64>,
+}
+
+impl ReadinessCheck {
+    fn new(name: &str, result: Result<(), String>) -> Self {
+        Self {
+            name: name.into(),
+            ok: result.is_ok(),
+            message: result.err(),
+        }
+    }
+}
+
+/// Responds while the server is running, whether or not its database is.
+#[get("/healthz")]
+pub(super) async fn healthz_get() -> impl Responder {
+    HttpResponse::Ok().json(HealthResponse {
+        status: "ok".into(),
+    })
+}
+
+/// Responds with 503 unless the database is reachable and fully migrated.
+#[get("/readyz")]
+pub(super) async fn readyz_get(service: web::Data<Arc<RestService>>) -> impl Responder {
+    let database = service.db_service.ping().await.map_err(|e| e.to_string());
+
+    let migrations = match &database {
+        Ok(_) => match service.db_service.migration_status().await {
+            Ok(status) if status.is_current() => Ok(()),
+            Ok(status) => Err(format!(
+                "the database is at migration {} of {}",
+                status.applied.unwrap_or(0),
+                status.latest
+            )),
+            Err(e) => Err(format!("the migrations could not be read: {}", e)),
+        },
+        Err(_) => Err("the database is unreachable".into()),
+    };
+
+    let checks = vec![
+        ReadinessCheck::new("database", database),
+        ReadinessCheck::new("migrations", migrations),
+    ];
+    let ready = checks.iter().all(|check| check.ok);
+    let body = ReadinessResponse { ready, checks };
+
+    match ready {
+        true => HttpResponse::Ok().json(body),
+        false => HttpResponse::ServiceUnavailable().json(body),
+    }
+}
+
+#[get("/version")]
+pub(super) async fn version_get(service: web::Data<Arc<RestService>>) -> impl Responder {
+    let schema_version = match service.db_service.migration_status().await {
+        Ok(status) => status.applied,
+        Err(_) => None,
+    };
+
+    HttpResponse::Ok().json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION").into(),
+        git_commit: GIT_COMMIT.into(),
+        schema_version,
+    })
+}
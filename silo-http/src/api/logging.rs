@@ -0,0 +1,156 @@
+use silo_core::logging::{self, Span};
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use log::{info, warn};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// The header carrying the ID of a request, which clients may set.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest request ID accepted from a client.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Returns the ID of a request from its header, if it is usable, or a new one.
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map_or_else(|| format!("{:016x}", rand::random::<u64>()), String::from)
+}
+
+/// Middleware running every request within a span carrying its ID, which is
+/// returned in the X-Request-Id header, and logging each request handled.
+pub struct RequestLogging;
+
+impl<S, B> Transform<S> for RequestLogging
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestLoggingMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestLoggingMiddleware { service })
+    }
+}
+
+pub struct RequestLoggingMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for RequestLoggingMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let id = request_id(&req);
+        let span = Span::new("http")
+            .with_field("method", req.method())
+            .with_field("path", req.path());
+        let response = self.service.call(req);
+
+        let header_id = id.clone();
+        let handled = async move {
+            let mut res = match response.await {
+                Ok(res) => res,
+                Err(e) => {
+                    warn!("request failed: {}", e);
+                    return Err(e);
+                }
+            };
+
+            info!(
+                "{} {} responded {} in {:?}",
+                res.request().method(),
+                res.request()
+                    .match_pattern()
+                    .unwrap_or_else(|| res.request().path().into()),
+                res.status().as_u16(),
+                start.elapsed()
+            );
+            if let Ok(value) = HeaderValue::from_str(&header_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        };
+
+        Box::pin(logging::in_request(&id, span, handled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    /// Responds with the ID of the request being handled.
+    async fn echo_request_id() -> HttpResponse {
+        HttpResponse::Ok().body(logging::request_id().unwrap_or_default())
+    }
+
+    #[actix_rt::test]
+    async fn request_ids() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RequestLogging)
+                .route("/id", web::get().to(echo_request_id)),
+        )
+        .await;
+
+        let request = test::TestRequest::with_uri("/id")
+            .header(REQUEST_ID_HEADER, "client-id_1")
+            .to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(
+            response.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "client-id_1"
+        );
+        assert_eq!(test::read_body(response).await, "client-id_1");
+
+        // IDs that could forge log lines are replaced.
+        let request = test::TestRequest::with_uri("/id")
+            .header(REQUEST_ID_HEADER, "a\"b")
+            .to_request();
+        let response = test::call_service(&mut app, request).await;
+        let id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(id.len(), 16);
+        assert_eq!(test::read_body(response).await, id.as_str());
+    }
+}
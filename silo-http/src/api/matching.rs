@@ -0,0 +1,138 @@
+use super::pseudonyms::SubjectPseudonymiser;
+use super::validation::{Validate, Validator};
+use super::{group_data, group_not_found, new_group, ApiError, RestService};
+use silo_core::analysis::Sample;
+use silo_core::matching::{match_controls, MatchingOptions};
+
+use actix_web::{post, web, HttpResponse, Responder};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Returns true, as the default of boolean fields.
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchBody {
+    /// The ID of the group controls are picked from.
+    pub pool_group_id: i32,
+    /// Matches controls on age.
+    #[serde(default = "default_true")]
+    pub match_age: bool,
+    /// The largest allowed difference in age, in years.
+    pub age_tolerance: Option<i16>,
+    /// Matches controls on length of stay.
+    #[serde(default = "default_true")]
+    pub match_length_of_stay: bool,
+    /// The largest allowed difference in length of stay, in days.
+    pub length_of_stay_tolerance: Option<i16>,
+    /// The names of the traits controls must share with their case.
+    #[serde(default)]
+    pub traits: Vec<String>,
+    /// The number of controls matched to every case.
+    pub ratio: Option<usize>,
+    /// Allows a control to be matched to more than one case.
+    #[serde(default)]
+    pub with_replacement: bool,
+    /// The seed of the matching. A random seed is picked and reported if none is
+    /// given.
+    pub seed: Option<u64>,
+    /// Replaces subject IDs with tokens: hmac, random or none. Responses are
+    /// pseudonymised with the server's default method if its policy requires it.
+    pub pseudonymise: Option<String>,
+}
+
+#[post("/groups/{id}/match")]
+pub(super) async fn groups_match_post(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    body: web::Json<MatchBody>,
+) -> impl Responder {
+    let mut v = Validator::new();
+    body.validate(&mut v);
+    if let Err(response) = v.finish() {
+        return response;
+    }
+    let defaults = MatchingOptions::default();
+    let ratio = body.ratio.unwrap_or(defaults.ratio);
+
+    let mut pseudonymiser = match SubjectPseudonymiser::new(&service, body.pseudonymise.as_deref())
+    {
+        Ok(pseudonymiser) => pseudonymiser,
+        Err(response) => return response,
+    };
+
+    let cases = match group_data(&service, id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(id),
+        Err(response) => return response,
+    };
+    let pool = match group_data(&service, body.pool_group_id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(body.pool_group_id),
+        Err(response) => return response,
+    };
+
+    let mut trait_ids = vec![];
+    for name in &body.traits {
+        match cases.traits.iter().find(|t| &t.trait_name == name) {
+            Some(t) => trait_ids.push(t.id),
+            None => {
+                return HttpResponse::BadRequest().json(ApiError {
+                    error: "error.matching.trait".into(),
+                    message: format!("unknown trait {}", name),
+                });
+            }
+        }
+    }
+    let trait_names: HashMap<i32, String> = cases
+        .traits
+        .iter()
+        .map(|t| (t.id, t.trait_name.clone()))
+        .collect();
+
+    let options = MatchingOptions {
+        age_tolerance: match body.match_age {
+            true => body.age_tolerance.or(defaults.age_tolerance),
+            false => None,
+        },
+        length_of_stay_tolerance: match body.match_length_of_stay {
+            true => body
+                .length_of_stay_tolerance
+                .or(defaults.length_of_stay_tolerance),
+            false => None,
+        },
+        trait_ids,
+        ratio,
+        with_replacement: body.with_replacement,
+        seed: body.seed.unwrap_or_else(rand::random),
+    };
+
+    let mut report = match_controls(
+        Sample {
+            group_id: id,
+            subjects: &cases.subjects,
+            links: &cases.links,
+        },
+        Sample {
+            group_id: body.pool_group_id,
+            subjects: &pool.subjects,
+            links: &pool.links,
+        },
+        &trait_names,
+        &options,
+    );
+
+    let control_group_id = match new_group(&service, &report.control_ids()).await {
+        Ok(group_id) => group_id,
+        Err(response) => return response,
+    };
+    report.control_group_id = Some(control_group_id);
+
+    let report = report.map_ids(|id| pseudonymiser.subject(id));
+    pseudonymiser.respond(&service, id, report).await
+}
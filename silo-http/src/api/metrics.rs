@@ -0,0 +1,362 @@
+use super::RestService;
+use silo_core::logging::{self, Span};
+use silo_core::models;
+use silo_db::errors::DatabaseError;
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{get, web, Error, HttpResponse, Responder};
+use async_trait::async_trait;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use log::warn;
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The metrics of the server, exported in the Prometheus text format.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_query_duration: HistogramVec,
+    db_query_errors: IntCounterVec,
+    db_connections_in_use: IntGauge,
+    db_max_connections: IntGauge,
+    export_size: HistogramVec,
+    export_duration: HistogramVec,
+    subjects: IntGauge,
+    traits: IntGauge,
+    groups: IntGauge,
+}
+
+impl Metrics {
+    /// Creates and returns a set of metrics registered with a new registry.
+    pub fn new() -> Self {
+        let http_requests = IntCounterVec::new(
+            Opts::new("silo_http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "silo_http_request_duration_seconds",
+                "The time taken to handle HTTP requests",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "silo_db_query_duration_seconds",
+                "The time taken by each method of the database service",
+            ),
+            &["method"],
+        )
+        .unwrap();
+        let db_query_errors = IntCounterVec::new(
+            Opts::new(
+                "silo_db_query_errors_total",
+                "Calls to the database service that failed",
+            ),
+            &["method"],
+        )
+        .unwrap();
+        let db_connections_in_use = IntGauge::new(
+            "silo_db_pool_connections_in_use",
+            "Database connections taken from the pool",
+        )
+        .unwrap();
+        let db_max_connections = IntGauge::new(
+            "silo_db_pool_max_connections",
+            "The most connections the database pool opens",
+        )
+        .unwrap();
+        let export_size = HistogramVec::new(
+            HistogramOpts::new("silo_export_size_bytes", "The size of matrix exports")
+                // From 1 KiB to 1 GiB, in bytes.
+                .buckets(exponential_buckets(1024.0, 4.0, 11).unwrap()),
+            &["format"],
+        )
+        .unwrap();
+        let export_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "silo_export_duration_seconds",
+                "The time taken to generate matrix exports",
+            ),
+            &["format"],
+        )
+        .unwrap();
+        let subjects = IntGauge::new("silo_subjects_total", "Subjects stored").unwrap();
+        let traits = IntGauge::new("silo_traits_total", "Traits stored").unwrap();
+        let groups = IntGauge::new("silo_groups_total", "Groups stored").unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_query_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_query_errors.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_connections_in_use.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_max_connections.clone()))
+            .unwrap();
+        registry.register(Box::new(export_size.clone())).unwrap();
+        registry
+            .register(Box::new(export_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(subjects.clone())).unwrap();
+        registry.register(Box::new(traits.clone())).unwrap();
+        registry.register(Box::new(groups.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_query_duration,
+            db_query_errors,
+            db_connections_in_use,
+            db_max_connections,
+            export_size,
+            export_duration,
+            subjects,
+            traits,
+            groups,
+        }
+    }
+
+    /// Records the usage of the database connection pool.
+    fn set_pool_state(&self, state: &models::PoolState) {
+        self.db_connections_in_use.set(state.in_use);
+        self.db_max_connections.set(state.max_open);
+    }
+
+    /// Records a handled HTTP request.
+    fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a generated export of a format.
+    pub(super) fn observe_export(&self, format: &str, size: usize, elapsed: Duration) {
+        self.export_size
+            .with_label_values(&[format])
+            .observe(size as f64);
+        self.export_duration
+            .with_label_values(&[format])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records the number of subjects, traits and groups stored.
+    fn set_entity_counts(&self, counts: &models::EntityCounts) {
+        self.subjects.set(counts.subjects);
+        self.traits.set(counts.traits);
+        self.groups.set(counts.groups);
+    }
+
+    /// Returns every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Middleware counting and timing every request by the route it matched.
+pub struct RequestMetrics(Arc<Metrics>);
+
+impl RequestMetrics {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self(metrics)
+    }
+}
+
+impl<S, B> Transform<S> for RequestMetrics
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestMetricsMiddleware {
+            service,
+            metrics: self.0.clone(),
+        })
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+    metrics: Arc<Metrics>,
+}
+
+impl<S, B> Service for RequestMetricsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let method = req.method().to_string();
+        let metrics = self.metrics.clone();
+        let response = self.service.call(req);
+
+        Box::pin(async move {
+            let res = response.await?;
+            // Routes are labelled by their patterns to keep the number of series bounded.
+            let route = res
+                .request()
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".into());
+            metrics.observe_request(&method, &route, res.status().as_u16(), start.elapsed());
+            Ok(res)
+        })
+    }
+}
+
+/// A database service which times every call to the service it wraps and
+/// runs it within a span.
+pub(super) struct TimedService {
+    inner: Arc<dyn silo_db::service::Service>,
+    metrics: Arc<Metrics>,
+}
+
+impl TimedService {
+    pub(super) fn new(inner: Arc<dyn silo_db::service::Service>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    /// Awaits a call to the wrapped service, recording its duration and
+    /// whether it failed.
+    async fn time<T>(
+        &self,
+        method: &str,
+        call: impl Future<Output = Result<T, DatabaseError>>,
+    ) -> Result<T, DatabaseError> {
+        let timer = self
+            .metrics
+            .db_query_duration
+            .with_label_values(&[method])
+            .start_timer();
+
+        let span = Span::new("service").with_field("call", method);
+        let result = logging::in_span(span, call).await;
+        timer.observe_duration();
+        if result.is_err() {
+            self.metrics
+                .db_query_errors
+                .with_label_values(&[method])
+                .inc();
+        }
+        result
+    }
+}
+
+/// Implements every method of the Service for TimedService by timing the
+/// call to the wrapped service.
+macro_rules! timed_service {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $result:ty;)*) => {
+        #[async_trait]
+        impl silo_db::service::Service for TimedService {
+            $(
+                async fn $method(&self, $($arg: $ty),*) -> Result<$result, DatabaseError> {
+                    self.time(stringify!($method), self.inner.$method($($arg),*))
+                        .await
+                }
+            )*
+        }
+    };
+}
+
+silo_db::service_methods!(timed_service);
+
+#[get("/metrics")]
+pub(super) async fn metrics_get(service: web::Data<Arc<RestService>>) -> impl Responder {
+    // The totals and the pool are refreshed on each scrape; a failure leaves
+    // the last ones.
+    match service.db_service.count_entities().await {
+        Ok(counts) => service.metrics.set_entity_counts(&counts),
+        Err(e) => warn!("could not count entities: {}", e),
+    }
+    match service.db_service.pool_state().await {
+        Ok(state) => service.metrics.set_pool_state(&state),
+        Err(e) => warn!("could not read the connection pool: {}", e),
+    }
+
+    HttpResponse::Ok()
+        .content_type(TextEncoder::new().format_type())
+        .body(service.metrics.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = Metrics::new();
+        metrics.set_pool_state(&models::PoolState {
+            in_use: 3,
+            max_open: 50,
+        });
+        metrics.observe_request("GET", "/api/v1/groups/{id}", 200, Duration::from_millis(3));
+        metrics.observe_export("csv", 2048, Duration::from_millis(40));
+        metrics.set_entity_counts(&models::EntityCounts {
+            subjects: 12,
+            traits: 4,
+            groups: 2,
+        });
+
+        let text = metrics.render();
+        assert!(text.contains(
+            "silo_http_requests_total{method=\"GET\",route=\"/api/v1/groups/{id}\",status=\"200\"} 1"
+        ));
+        assert!(text.contains("silo_export_size_bytes_count{format=\"csv\"} 1"));
+        assert!(text.contains("silo_export_size_bytes_bucket{format=\"csv\",le=\"1024\"} 0"));
+        assert!(text.contains("silo_export_size_bytes_bucket{format=\"csv\",le=\"4096\"} 1"));
+        assert!(text.contains("le=\"1073741824\""));
+        assert!(text.contains("silo_db_pool_connections_in_use 3"));
+        assert!(text.contains("silo_db_pool_max_connections 50"));
+        assert!(text.contains("silo_subjects_total 12"));
+    }
+}
@@ -0,0 +1,739 @@
+use super::analysis::{ComparisonQuery, CooccurrenceQuery};
+use super::cohorts::{
+    CohortBody, CohortMaterialisationsResponse, CohortsResponse, MaterialiseQuery,
+};
+use super::external_ids::{
+    DuplicatesQuery, DuplicatesResponse, ExternalIdsResponse, ImportBody, ImportResponse,
+};
+use super::health::{HealthResponse, ReadinessResponse, VersionResponse};
+use super::matching::MatchBody;
+use super::privacy::{
+    PrivacyBudgetBody, PrivateCountQuery, PrivateCountResponse, PrivateHistogramQuery,
+    PrivateHistogramResponse, PrivateNoiseQuery,
+};
+use super::pseudonyms::{PseudonymiseQuery, ReidentifyBody, ReidentifyResponse, SubjectRef};
+use super::sampling::{SampleBody, SampleResponse, SplitBody, SplitResponse};
+use super::stats::StatsQuery;
+use super::validation::ValidationErrors;
+use super::{
+    ApiCreationSuccess, ApiError, GroupsResponse, InsertSubject, InsertSubjectSubjectTrait,
+    InsertTrait, MatrixGenQuery, SubjectTraitsResponse, SubjectsResponse, TraitsResponse,
+};
+use silo_core::analysis::{Cooccurrence, GroupComparison};
+use silo_core::matching::MatchingReport;
+use silo_core::models;
+use silo_core::stats::GroupStats;
+
+use actix_web::{get, HttpResponse, Responder};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+/// The interactive documentation page, which renders the specification served
+/// next to it.
+const DOCS_PAGE: &str = include_str!("docs.html");
+
+/// Builds the OpenAPI document of the API, generating the schemas of requests and
+/// responses from their types.
+struct SpecBuilder {
+    generator: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl SpecBuilder {
+    fn new() -> Self {
+        Self {
+            generator: SchemaGenerator::new(SchemaSettings::openapi3()),
+            paths: Map::new(),
+        }
+    }
+
+    /// Starts an operation on a path relative to /api/v1, unless the operation
+    /// is served at the root.
+    fn operation(
+        &mut self,
+        method: &'static str,
+        path: &'static str,
+        tag: &str,
+        summary: &str,
+    ) -> OperationBuilder<'_> {
+        // Path parameters are IDs unless an operation says otherwise.
+        let parameters = path
+            .split('/')
+            .filter_map(|s| s.strip_prefix('{')?.strip_suffix('}'))
+            .map(|name| path_parameter(name, "integer"))
+            .collect();
+
+        OperationBuilder {
+            spec: self,
+            method,
+            path,
+            operation: json!({
+                "tags": [tag],
+                "summary": summary,
+                "operationId": operation_id(method, path),
+            }),
+            parameters,
+            response: json!({
+                "description": "Success",
+                "content": {},
+            }),
+        }
+    }
+
+    /// Returns the schema of a type, referencing the components of the document.
+    fn schema<T: JsonSchema>(&mut self) -> Value {
+        serde_json::to_value(self.generator.subschema_for::<T>()).unwrap()
+    }
+
+    /// Returns the OpenAPI document of every operation added.
+    fn build(mut self) -> Value {
+        let error = self.schema::<ApiError>();
+        let schemas: Map<String, Value> = self
+            .generator
+            .definitions()
+            .iter()
+            .map(|(name, schema)| (name.clone(), serde_json::to_value(schema).unwrap()))
+            .collect();
+
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "silo",
+                "description": "The REST API of silo.",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "servers": [{ "url": "/api/v1" }],
+            "paths": self.paths,
+            "components": {
+                "schemas": schemas,
+                "responses": {
+                    "Error": {
+                        "description": "The request failed",
+                        "content": { "application/json": { "schema": error } },
+                    },
+                },
+                "securitySchemes": {
+                    "adminToken": { "type": "http", "scheme": "bearer" },
+                    "apiKey": { "type": "apiKey", "in": "header", "name": "X-Api-Key" },
+                },
+            },
+        })
+    }
+}
+
+/// An operation being added to a SpecBuilder.
+struct OperationBuilder<'a> {
+    spec: &'a mut SpecBuilder,
+    method: &'static str,
+    path: &'static str,
+    operation: Value,
+    parameters: Vec<Value>,
+    response: Value,
+}
+
+impl<'a> OperationBuilder<'a> {
+    /// Documents a path parameter as a string rather than an ID.
+    fn with_string_path(mut self, name: &str) -> Self {
+        for parameter in self.parameters.iter_mut() {
+            if parameter["name"] == name {
+                *parameter = path_parameter(name, "string");
+            }
+        }
+        self
+    }
+
+    /// Adds a query parameter for every field of a query type.
+    fn with_query<T: JsonSchema>(mut self) -> Self {
+        let schema = match T::json_schema(&mut self.spec.generator) {
+            Schema::Object(schema) => schema,
+            Schema::Bool(_) => return self,
+        };
+        let object = match schema.object {
+            Some(object) => object,
+            None => return self,
+        };
+
+        for (name, property) in object.properties {
+            let mut property = serde_json::to_value(property).unwrap();
+            let description = property
+                .as_object_mut()
+                .and_then(|p| p.remove("description"));
+
+            let mut parameter = json!({
+                "name": name,
+                "in": "query",
+                "required": object.required.contains(&name),
+                "schema": property,
+            });
+            if let Some(description) = description {
+                parameter["description"] = description;
+            }
+            self.parameters.push(parameter);
+        }
+        self
+    }
+
+    /// Sets the JSON body of the request.
+    fn with_body<T: JsonSchema>(mut self) -> Self {
+        self.operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": self.spec.schema::<T>() } },
+        });
+        self
+    }
+
+    /// Adds a JSON body to the successful response.
+    fn with_response<T: JsonSchema>(mut self) -> Self {
+        self.response["content"]["application/json"] = json!({
+            "schema": self.spec.schema::<T>(),
+        });
+        self
+    }
+
+    /// Adds a body of another content type to the successful response.
+    fn with_content(mut self, content_type: &str, format: &str) -> Self {
+        self.response["content"][content_type] = json!({
+            "schema": { "type": "string", "format": format },
+        });
+        self
+    }
+
+    /// Adds a response header to the successful response.
+    fn with_response_header(mut self, name: &str, description: &str) -> Self {
+        self.response["headers"][name] = json!({
+            "description": description,
+            "schema": { "type": "string" },
+        });
+        self
+    }
+
+    /// Documents the 422 response of a request that fails validation.
+    fn with_validation(mut self) -> Self {
+        self.operation["responses"]["422"] = json!({
+            "description": "The request has invalid fields",
+            "content": {
+                "application/json": { "schema": self.spec.schema::<ValidationErrors>() },
+            },
+        });
+        self
+    }
+
+    /// Documents the 503 response of a server that isn't ready.
+    fn with_unavailable<T: JsonSchema>(mut self) -> Self {
+        self.operation["responses"]["503"] = json!({
+            "description": "The server is not ready to handle requests",
+            "content": { "application/json": { "schema": self.spec.schema::<T>() } },
+        });
+        self
+    }
+
+    /// Documents the operation as served at the root of the server rather than
+    /// under /api/v1.
+    fn at_root(mut self) -> Self {
+        self.operation["servers"] = json!([{ "url": "/" }]);
+        self
+    }
+
+    /// Requires a security scheme of the document: adminToken or apiKey.
+    fn with_security(mut self, scheme: &str) -> Self {
+        let mut requirement = Map::new();
+        requirement.insert(scheme.into(), json!([]));
+        self.operation["security"] = json!([requirement]);
+        self
+    }
+
+    /// Adds the operation to the document.
+    fn add(mut self) {
+        if !self.parameters.is_empty() {
+            self.operation["parameters"] = Value::Array(self.parameters);
+        }
+        self.operation["responses"]["200"] = self.response;
+        self.operation["responses"]["default"] = json!({ "$ref": "#/components/responses/Error" });
+
+        self.spec
+            .paths
+            .entry(self.path)
+            .or_insert_with(|| json!({}))[self.method] = self.operation;
+    }
+}
+
+/// Returns a required path parameter of a type.
+fn path_parameter(name: &str, instance_type: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "schema": { "type": instance_type },
+    })
+}
+
+/// Returns the ID of an operation from its method and path, such as
+/// get_groups_id_subjects for GET /groups/{id}/subjects.
+fn operation_id(method: &str, path: &str) -> String {
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.trim_matches(|c| c == '{' || c == '}').replace('.', "_"));
+
+    std::iter::once(method.to_string())
+        .chain(segments)
+        .collect::<Vec<String>>()
+        .join("_")
+}
+
+/// Returns the OpenAPI 3 specification of the API.
+pub fn openapi() -> Value {
+    let mut spec = SpecBuilder::new();
+
+    spec.operation("get", "/groups", "groups", "List every group")
+        .with_response::<GroupsResponse>()
+        .add();
+    spec.operation("post", "/groups", "groups", "Create an empty group")
+        .with_response::<models::Group>()
+        .add();
+    spec.operation(
+        "get",
+        "/groups/{id}/subjects",
+        "subjects",
+        "List the subjects of a group",
+    )
+    .with_query::<PseudonymiseQuery>()
+    .with_response::<SubjectsResponse>()
+    .with_response_header(
+        "X-Silo-Export-Id",
+        "The ID of the export, when it is pseudonymised",
+    )
+    .add();
+    spec.operation(
+        "post",
+        "/groups/{id}/subjects",
+        "subjects",
+        "Insert a subject, or update the subject with any of its external IDs",
+    )
+    .with_body::<InsertSubject>()
+    .with_validation()
+    .with_response::<models::Subject>()
+    .add();
+    spec.operation(
+        "post",
+        "/groups/{id}/subjects/import",
+        "subjects",
+        "Insert or update many subjects by their external IDs",
+    )
+    .with_body::<ImportBody>()
+    .with_validation()
+    .with_response::<ImportResponse>()
+    .add();
+    spec.operation(
+        "get",
+        "/groups/{id}/subjects/external/{namespace}/{value}",
+        "subjects",
+        "Find the subject of a group with an external ID",
+    )
+    .with_string_path("namespace")
+    .with_string_path("value")
+    .with_response::<models::Subject>()
+    .add();
+    spec.operation(
+        "get",
+        "/groups/{group_id}/subjects/{subject_id}/external_ids",
+        "subjects",
+        "List the external IDs of a subject",
+    )
+    .with_response::<ExternalIdsResponse>()
+    .add();
+    spec.operation(
+        "get",
+        "/subjects/duplicates",
+        "subjects",
+        "Find subjects sharing an external ID",
+    )
+    .with_query::<DuplicatesQuery>()
+    .with_response::<DuplicatesResponse>()
+    .add();
+
+    spec.operation("get", "/traits", "traits", "List every trait")
+        .with_response::<TraitsResponse>()
+        .add();
+    spec.operation("post", "/traits", "traits", "Create a trait")
+        .with_body::<InsertTrait>()
+        .with_validation()
+        .with_response::<models::SubjectTrait>()
+        .add();
+    spec.operation(
+        "get",
+        "/groups/{group_id}/subjects/{subject_id}/traits",
+        "traits",
+        "List the traits of a subject",
+    )
+    .with_response::<SubjectTraitsResponse>()
+    .add();
+    spec.operation(
+        "post",
+        "/groups/{group_id}/subjects/{subject_id}/traits",
+        "traits",
+        "Link a trait to a subject",
+    )
+    .with_body::<InsertSubjectSubjectTrait>()
+    .with_validation()
+    .with_response::<ApiCreationSuccess>()
+    .add();
+
+    spec.operation(
+        "get",
+        "/groups/{id}/generate/matrix",
+        "matrix",
+        "Export a group as a matrix of subjects by attributes and traits",
+    )
+    .with_query::<MatrixGenQuery>()
+    .with_validation()
+    .with_content("text/tab-separated-values", "binary")
+    .with_content("application/octet-stream", "binary")
+    .with_response_header(
+        "X-Silo-Export-Id",
+        "The ID of the export, when it is pseudonymised",
+    )
+    .add();
+
+    spec.operation(
+        "get",
+        "/groups/{id}/stats",
+        "stats",
+        "Describe the subjects of a group",
+    )
+    .with_query::<StatsQuery>()
+    .with_response::<GroupStats>()
+    .add();
+    spec.operation(
+        "get",
+        "/groups/{id}/analysis/cooccurrence",
+        "analysis",
+        "Test the association of every pair of traits of a group",
+    )
+    .with_query::<CooccurrenceQuery>()
+    .with_response::<Cooccurrence>()
+    .with_content("text/tab-separated-values", "binary")
+    .add();
+    spec.operation(
+        "get",
+        "/groups/{id}/analysis/comparison",
+        "analysis",
+        "Compare the traits and attributes of a group with a control group",
+    )
+    .with_query::<ComparisonQuery>()
+    .with_response::<GroupComparison>()
+    .add();
+
+    spec.operation("get", "/cohorts", "cohorts", "List every cohort")
+        .with_response::<CohortsResponse>()
+        .add();
+    spec.operation("post", "/cohorts", "cohorts", "Save a cohort definition")
+        .with_body::<CohortBody>()
+        .with_response::<models::Cohort>()
+        .add();
+    spec.operation("get", "/cohorts/{id}", "cohorts", "Get a cohort")
+        .with_response::<models::Cohort>()
+        .add();
+    spec.operation("put", "/cohorts/{id}", "cohorts", "Update a cohort")
+        .with_body::<CohortBody>()
+        .with_response::<models::Cohort>()
+        .add();
+    spec.operation("delete", "/cohorts/{id}", "cohorts", "Delete a cohort")
+        .with_response::<ApiCreationSuccess>()
+        .add();
+    spec.operation(
+        "post",
+        "/cohorts/{id}/materialise",
+        "cohorts",
+        "Write the subjects of a cohort into a group",
+    )
+    .with_query::<MaterialiseQuery>()
+    .with_response::<models::CohortMaterialisation>()
+    .add();
+    spec.operation(
+        "get",
+        "/cohorts/{id}/materialisations",
+        "cohorts",
+        "List the materialisations of a cohort",
+    )
+    .with_response::<CohortMaterialisationsResponse>()
+    .add();
+
+    spec.operation(
+        "post",
+        "/groups/{id}/match",
+        "matching",
+        "Match controls to the subjects of a group into a new group",
+    )
+    .with_body::<MatchBody>()
+    .with_validation()
+    .with_response::<MatchingReport<SubjectRef>>()
+    .with_response_header(
+        "X-Silo-Export-Id",
+        "The ID of the export, when it is pseudonymised",
+    )
+    .add();
+    spec.operation(
+        "post",
+        "/groups/{id}/sample",
+        "sampling",
+        "Draw a random sample of a group into a new group",
+    )
+    .with_body::<SampleBody>()
+    .with_validation()
+    .with_response::<SampleResponse>()
+    .with_response_header(
+        "X-Silo-Export-Id",
+        "The ID of the export, when it is pseudonymised",
+    )
+    .add();
+    spec.operation(
+        "post",
+        "/groups/{id}/split",
+        "sampling",
+        "Split a group into train, validation and test sets",
+    )
+    .with_body::<SplitBody>()
+    .with_validation()
+    .with_response::<SplitResponse>()
+    .with_response_header(
+        "X-Silo-Export-Id",
+        "The ID of the export, when it is pseudonymised",
+    )
+    .add();
+
+    spec.operation(
+        "post",
+        "/admin/reidentify",
+        "pseudonyms",
+        "Map the tokens of pseudonymised exports back to subjects",
+    )
+    .with_security("adminToken")
+    .with_body::<ReidentifyBody>()
+    .with_response::<ReidentifyResponse>()
+    .add();
+
+    spec.operation(
+        "get",
+        "/groups/{id}/private/count",
+        "privacy",
+        "Count the subjects of a group with differential privacy",
+    )
+    .with_security("apiKey")
+    .with_query::<PrivateCountQuery>()
+    .with_validation()
+    .with_response::<PrivateCountResponse>()
+    .add();
+    spec.operation(
+        "get",
+        "/groups/{id}/private/histogram",
+        "privacy",
+        "Bin an attribute of the subjects of a group with differential privacy",
+    )
+    .with_security("apiKey")
+    .with_query::<PrivateHistogramQuery>()
+    .with_validation()
+    .with_response::<PrivateHistogramResponse>()
+    .add();
+    spec.operation(
+        "get",
+        "/cohorts/{id}/private/count",
+        "privacy",
+        "Count the subjects of a cohort with differential privacy",
+    )
+    .with_security("apiKey")
+    .with_query::<PrivateNoiseQuery>()
+    .with_validation()
+    .with_response::<PrivateCountResponse>()
+    .add();
+    spec.operation(
+        "get",
+        "/privacy/budget",
+        "privacy",
+        "Get the privacy budget of the API key",
+    )
+    .with_security("apiKey")
+    .with_response::<models::PrivacyBudget>()
+    .add();
+    spec.operation(
+        "put",
+        "/admin/privacy/budgets/{api_key}",
+        "privacy",
+        "Set the privacy budget of an API key",
+    )
+    .with_security("adminToken")
+    .with_string_path("api_key")
+    .with_body::<PrivacyBudgetBody>()
+    .with_validation()
+    .with_response::<models::PrivacyBudget>()
+    .add();
+
+    spec.operation(
+        "get",
+        "/healthz",
+        "health",
+        "Check that the server is running",
+    )
+    .with_response::<HealthResponse>()
+    .at_root()
+    .add();
+    spec.operation(
+        "get",
+        "/readyz",
+        "health",
+        "Check that the database is reachable and migrated",
+    )
+    .with_response::<ReadinessResponse>()
+    .with_unavailable::<ReadinessResponse>()
+    .at_root()
+    .add();
+    spec.operation(
+        "get",
+        "/version",
+        "health",
+        "Get the version, git commit and schema version of the server",
+    )
+    .with_response::<VersionResponse>()
+    .at_root()
+    .add();
+
+    spec.operation(
+        "get",
+        "/metrics",
+        "metrics",
+        "Get the metrics of the server in the Prometheus text format",
+    )
+    .with_content("text/plain", "prometheus")
+    .at_root()
+    .add();
+
+    spec.operation("get", "/openapi.json", "docs", "Get this specification")
+        .with_response::<Value>()
+        .add();
+    spec.operation("get", "/docs", "docs", "Browse and try the API")
+        .with_content("text/html", "html")
+        .add();
+
+    spec.build()
+}
+
+#[get("/openapi.json")]
+pub(super) async fn openapi_get() -> impl Responder {
+    HttpResponse::Ok().json(openapi())
+}
+
+#[get("/docs")]
+pub(super) async fn docs_get() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(DOCS_PAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{root_routes, routes};
+    use super::*;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{test, web, App};
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::path::Path;
+
+    /// Returns the sources of every file under a directory.
+    fn sources(dir: &Path) -> Vec<String> {
+        let mut sources = vec![];
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                sources.extend(self::sources(&path));
+            } else if path.extension().and_then(|e| e.to_str()) == Some("rs") {
+                sources.push(fs::read_to_string(&path).unwrap());
+            }
+        }
+        sources
+    }
+
+    /// Returns the method and path of every route handler of the crate, from
+    /// the attributes of the handlers. An attribute may span several lines.
+    fn handler_routes() -> BTreeSet<(String, String)> {
+        let mut handlers = BTreeSet::new();
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for source in sources(&src) {
+            for method in &["get", "post", "put", "patch", "delete"] {
+                let attribute = format!("#[{}(", method);
+                for (i, _) in source.match_indices(attribute.as_str()) {
+                    let rest = source[i + attribute.len()..].trim_start();
+                    if let Some(path) = rest.strip_prefix('"') {
+                        let path = &path[..path.find('"').unwrap()];
+                        handlers.insert((method.to_string(), path.to_string()));
+                    }
+                }
+            }
+        }
+        handlers
+    }
+
+    /// Returns the method and path of every operation of the specification.
+    fn spec_routes() -> BTreeSet<(String, String)> {
+        let spec = openapi();
+
+        spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spec_matches_handlers() {
+        assert_eq!(spec_routes(), handler_routes());
+    }
+
+    #[actix_rt::test]
+    async fn spec_routes_are_registered() {
+        let mut app = test::init_service(
+            App::new()
+                .configure(root_routes)
+                .service(web::scope("/api/v1").configure(routes)),
+        )
+        .await;
+
+        let spec = openapi();
+        for (method, path) in spec_routes() {
+            let base = match spec["paths"][&path][&method].get("servers") {
+                Some(_) => "",
+                None => "/api/v1",
+            };
+            let uri = path
+                .split('/')
+                .map(|s| match s.starts_with('{') {
+                    true => "1",
+                    false => s,
+                })
+                .collect::<Vec<&str>>()
+                .join("/");
+            let request = test::TestRequest::with_uri(&format!("{}{}", base, uri))
+                .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+                .to_request();
+
+            // Handlers without a database respond with errors, but never with 404.
+            let response = test::call_service(&mut app, request).await;
+            assert_ne!(
+                response.status(),
+                StatusCode::NOT_FOUND,
+                "{} {} is not registered",
+                method,
+                path
+            );
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use super::cohorts::{find_cohort, select_subjects, validate_definition};
+use super::validation::{Validate, Validator};
+use super::{db_error, group_data, group_not_found, ApiError, RestService};
+use silo_core::cohort::{Attribute, CohortDefinition, TraitExpression};
+use silo_core::models;
+use silo_core::privacy::{fixed_histogram, Mechanism, NoiseParams};
+use silo_core::stats::{check_bins, HistogramBin};
+
+use actix_web::{get, put, web, HttpRequest, HttpResponse, Responder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Returns a 400 response for invalid privacy parameters.
+fn invalid(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(ApiError {
+        error: "error.privacy.params".into(),
+        message,
+    })
+}
+
+/// Returns the NoiseParams of a query, recording the errors of its fields. The
+/// mechanism defaults to Laplace and the Gaussian mechanism's delta to 1e-5.
+fn noise_params(
+    v: &mut Validator,
+    mechanism: Option<&str>,
+    epsilon: Option<f64>,
+    delta: Option<f64>,
+) -> NoiseParams {
+    let mechanism = match mechanism.map(str::parse) {
+        Some(Ok(mechanism)) => mechanism,
+        Some(Err(message)) => {
+            v.error("mechanism", message);
+            Mechanism::Laplace
+        }
+        None => Mechanism::Laplace,
+    };
+    let delta = match mechanism {
+        Mechanism::Laplace => None,
+        Mechanism::Gaussian => Some(delta.unwrap_or(1e-5)),
+    };
+
+    let params = NoiseParams {
+        mechanism,
+        epsilon: epsilon.unwrap_or(0.0),
+        delta,
+    };
+    match (epsilon, params.validate()) {
+        (None, _) => v.error("epsilon", "a private query needs an epsilon"),
+        (Some(_), Err((field, message))) => v.error(field, message),
+        (Some(_), Ok(())) => (),
+    }
+    params
+}
+
+/// Returns the API key a request was made with.
+fn api_key(request: &HttpRequest) -> Result<String, HttpResponse> {
+    request
+        .headers()
+        .get("X-Api-Key")
+        .and_then(|h| h.to_str().ok())
+        .map(String::from)
+        .ok_or_else(|| {
+            HttpResponse::Unauthorized().json(ApiError {
+                error: "error.privacy.api_key".into(),
+                message: "private queries need an X-Api-Key header".into(),
+            })
+        })
+}
+
+/// Fetches the PrivacyBudget of an API key, responding with 401 if the key has
+/// none.
+async fn find_budget(
+    service: &RestService,
+    api_key: &str,
+) -> Result<models::PrivacyBudget, HttpResponse> {
+    match service
+        .db_service
+        .find_privacy_budget_by_api_key(api_key)
+        .await
+    {
+        Ok(Some(budget)) => Ok(budget),
+        Ok(None) => Err(HttpResponse::Unauthorized().json(ApiError {
+            error: "error.privacy.api_key".into(),
+            message: "the API key has no privacy budget".into(),
+        })),
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+/// Spends the epsilon of a query from an API key's budget, returning what is left
+/// of the budget. Responds with 403 once the budget is used up.
+async fn spend(
+    service: &RestService,
+    api_key: &str,
+    params: &NoiseParams,
+) -> Result<models::PrivacyBudget, HttpResponse> {
+    let spent = service
+        .db_service
+        .spend_privacy_budget(api_key, params.epsilon)
+        .await
+        .map_err(db_error)?;
+
+    let budget = find_budget(service, api_key).await?;
+    match spent {
+        true => Ok(budget),
+        false => Err(HttpResponse::Forbidden().json(ApiError {
+            error: "error.privacy.budget_exhausted".into(),
+            message: format!(
+                "the query needs an epsilon of {} but the API key only has {} left",
+                params.epsilon,
+                budget.remaining()
+            ),
+        })),
+    }
+}
+
+/// Describes the noise added to a private result.
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseReport {
+    pub mechanism: Mechanism,
+    pub epsilon: f64,
+    pub delta: Option<f64>,
+    /// The scale of the Laplace noise, or the standard deviation of the Gaussian
+    /// noise, added to each count.
+    pub scale: f64,
+    /// The epsilon the API key has left after the query.
+    pub remaining_epsilon: f64,
+}
+
+impl NoiseReport {
+    fn new(params: &NoiseParams, budget: &models::PrivacyBudget) -> Self {
+        Self {
+            mechanism: params.mechanism,
+            epsilon: params.epsilon,
+            delta: params.delta,
+            scale: params.scale(1.0),
+            remaining_epsilon: budget.remaining(),
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivateCountResponse {
+    /// Always true: the count has noise added and isn't exact.
+    pub noisy: bool,
+    pub noise: NoiseReport,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivateHistogramResponse {
+    /// Always true: the counts have noise added and aren't exact.
+    pub noisy: bool,
+    pub noise: NoiseReport,
+    pub attribute: Attribute,
+    pub histogram: Vec<HistogramBin>,
+}
+
+/// Responds with a count with noise added, spending the query's epsilon first.
+async fn private_count(
+    service: &RestService,
+    api_key: &str,
+    params: &NoiseParams,
+    count: usize,
+) -> HttpResponse {
+    let budget = match spend(service, api_key, params).await {
+        Ok(budget) => budget,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(PrivateCountResponse {
+        noisy: true,
+        noise: NoiseReport::new(params, &budget),
+        count: params.noisy_count(&mut rand::thread_rng(), count),
+    })
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PrivateCountQuery {
+    /// The noise mechanism: laplace (default) or gaussian.
+    pub mechanism: Option<String>,
+    /// The epsilon spent on the query.
+    pub epsilon: Option<f64>,
+    /// The delta of the gaussian mechanism.
+    pub delta: Option<f64>,
+    /// Only counts subjects with this trait or any of its descendants.
+    #[serde(rename = "trait")]
+    pub trait_name: Option<String>,
+}
+
+#[get("/groups/{id}/private/count")]
+pub(super) async fn groups_private_count_get(
+    service: web::Data<Arc<RestService>>,
+    request: HttpRequest,
+    web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<PrivateCountQuery>,
+) -> impl Responder {
+    let mut v = Validator::new();
+    let params = noise_params(
+        &mut v,
+        query.mechanism.as_deref(),
+        query.epsilon,
+        query.delta,
+    );
+    if let Err(response) = v.finish() {
+        return response;
+    }
+    let api_key = match api_key(&request) {
+        Ok(api_key) => api_key,
+        Err(response) => return response,
+    };
+    if let Err(response) = find_budget(&service, &api_key).await {
+        return response;
+    }
+
+    let data = match group_data(&service, id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(id),
+        Err(response) => return response,
+    };
+
+    let definition = CohortDefinition {
+        source_group_ids: vec![id],
+        traits: query.trait_name.map(TraitExpression::Has),
+        filters: vec![],
+        window: None,
+    };
+    if let Err(message) = definition.validate(&data.traits) {
+        return invalid(message);
+    }
+    let count = definition
+        .select(&data.subjects, &data.links, &data.traits)
+        .len();
+
+    private_count(&service, &api_key, &params, count).await
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PrivateHistogramQuery {
+    /// The noise mechanism: laplace (default) or gaussian.
+    pub mechanism: Option<String>,
+    /// The epsilon spent on the query.
+    pub epsilon: Option<f64>,
+    /// The delta of the gaussian mechanism.
+    pub delta: Option<f64>,
+    /// The attribute to bin: age or length_of_stay.
+    pub attribute: Attribute,
+    /// The width of each bin. Defaults to 10 years of age or 7 days of stay.
+    pub bin_width: Option<f64>,
+    /// The lower bound of the first bin. Defaults to 0.
+    pub lower: Option<f64>,
+    /// The upper bound of the last bin. Defaults to 120 years of age or 365 days
+    /// of stay.
+    pub upper: Option<f64>,
+}
+
+#[get("/groups/{id}/private/histogram")]
+pub(super) async fn groups_private_histogram_get(
+    service: web::Data<Arc<RestService>>,
+    request: HttpRequest,
+    web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<PrivateHistogramQuery>,
+) -> impl Responder {
+    let mut v = Validator::new();
+    let params = noise_params(
+        &mut v,
+        query.mechanism.as_deref(),
+        query.epsilon,
+        query.delta,
+    );
+    // The bins are fixed by the query rather than fitted to the data, as the
+    // range of the data would otherwise be released without noise.
+    let (default_width, default_upper) = match query.attribute {
+        Attribute::Age => (10.0, 120.0),
+        Attribute::LengthOfStay => (7.0, 365.0),
+    };
+    let bin_width = query.bin_width.unwrap_or(default_width);
+    let lower = query.lower.unwrap_or(0.0);
+    let upper = query.upper.unwrap_or(default_upper);
+    if upper <= lower {
+        v.error("upper", "must be above the lower bound");
+    } else if let Err(message) = check_bins(lower, upper, bin_width) {
+        v.error("bin_width", message);
+    }
+    if let Err(response) = v.finish() {
+        return response;
+    }
+
+    let api_key = match api_key(&request) {
+        Ok(api_key) => api_key,
+        Err(response) => return response,
+    };
+    if let Err(response) = find_budget(&service, &api_key).await {
+        return response;
+    }
+
+    let data = match group_data(&service, id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(id),
+        Err(response) => return response,
+    };
+    let values: Vec<f64> = data
+        .subjects
+        .iter()
+        .map(|s| query.attribute.value(s).into())
+        .collect();
+    let bins = fixed_histogram(&values, lower, upper, bin_width);
+
+    let budget = match spend(&service, &api_key, &params).await {
+        Ok(budget) => budget,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(PrivateHistogramResponse {
+        noisy: true,
+        noise: NoiseReport::new(&params, &budget),
+        attribute: query.attribute,
+        histogram: params.noisy_histogram(&mut rand::thread_rng(), &bins),
+    })
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PrivateNoiseQuery {
+    /// The noise mechanism: laplace (default) or gaussian.
+    pub mechanism: Option<String>,
+    /// The epsilon spent on the query.
+    pub epsilon: Option<f64>,
+    /// The delta of the gaussian mechanism.
+    pub delta: Option<f64>,
+}
+
+#[get("/cohorts/{id}/private/count")]
+pub(super) async fn cohorts_private_count_get(
+    service: web::Data<Arc<RestService>>,
+    request: HttpRequest,
+    web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<PrivateNoiseQuery>,
+) -> impl Responder {
+    let mut v = Validator::new();
+    let params = noise_params(
+        &mut v,
+        query.mechanism.as_deref(),
+        query.epsilon,
+        query.delta,
+    );
+    if let Err(response) = v.finish() {
+        return response;
+    }
+    let api_key = match api_key(&request) {
+        Ok(api_key) => api_key,
+        Err(response) => return response,
+    };
+    if let Err(response) = find_budget(&service, &api_key).await {
+        return response;
+    }
+
+    let cohort = match find_cohort(&service, id).await {
+        Ok(cohort) => cohort,
+        Err(response) => return response,
+    };
+    if let Err(response) = validate_definition(&service, &cohort.definition).await {
+        return response;
+    }
+    let count = match select_subjects(&service, &cohort).await {
+        Ok(subject_ids) => subject_ids.len(),
+        Err(response) => return response,
+    };
+
+    private_count(&service, &api_key, &params, count).await
+}
+
+#[get("/privacy/budget")]
+pub(super) async fn privacy_budget_get(
+    service: web::Data<Arc<RestService>>,
+    request: HttpRequest,
+) -> impl Responder {
+    let api_key = match api_key(&request) {
+        Ok(api_key) => api_key,
+        Err(response) => return response,
+    };
+
+    match find_budget(&service, &api_key).await {
+        Ok(budget) => HttpResponse::Ok().json(budget),
+        Err(response) => response,
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivacyBudgetBody {
+    /// The total epsilon the API key may spend, including what it has spent.
+    pub epsilon_total: f64,
+}
+
+#[put("/admin/privacy/budgets/{api_key}")]
+pub(super) async fn admin_privacy_budgets_put(
+    service: web::Data<Arc<RestService>>,
+    request: HttpRequest,
+    web::Path(api_key): web::Path<String>,
+    body: web::Json<PrivacyBudgetBody>,
+) -> impl Responder {
+    if !service.is_admin(&request) {
+        return HttpResponse::Forbidden().json(ApiError {
+            error: "error.admin.forbidden".into(),
+            message: "setting privacy budgets requires the admin token".into(),
+        });
+    }
+    let mut v = Validator::new();
+    body.validate(&mut v);
+    if let Err(response) = v.finish() {
+        return response;
+    }
+
+    match service
+        .db_service
+        .set_privacy_budget(&api_key, body.epsilon_total)
+        .await
+    {
+        Ok(budget) => HttpResponse::Ok().json(budget),
+        Err(e) => db_error(e),
+    }
+}
@@ -0,0 +1,434 @@
+use super::{db_error, ApiError, RestService};
+use silo_core::models;
+use silo_transform::pseudonym::{PseudonymMethod, Pseudonymiser};
+
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The server-wide policy on replacing subject identifiers in exports with
+/// tokens. Exports are matrices and every other response listing the subjects
+/// of a group: its subjects, samples, splits and matched controls.
+#[derive(Debug, Clone)]
+pub struct PseudonymPolicy {
+    /// Pseudonymises every export, whether or not it was requested.
+    pub required: bool,
+    /// The method used when an export doesn't request one.
+    pub default_method: PseudonymMethod,
+    /// The secret key HMAC tokens are derived from. HMAC tokens are unavailable
+    /// without one.
+    pub hmac_key: Option<Vec<u8>>,
+}
+
+impl Default for PseudonymPolicy {
+    fn default() -> Self {
+        Self {
+            required: false,
+            default_method: PseudonymMethod::Random,
+            hmac_key: None,
+        }
+    }
+}
+
+impl PseudonymPolicy {
+    /// Returns the Pseudonymiser of an export from the method it requested: hmac,
+    /// random or none. Returns None if the export isn't pseudonymised.
+    pub(super) fn pseudonymiser(
+        &self,
+        requested: Option<&str>,
+    ) -> Result<Option<Pseudonymiser>, String> {
+        let method = match requested {
+            Some("none") if self.required => {
+                return Err("this server only allows pseudonymised exports".into());
+            }
+            Some("none") => return Ok(None),
+            Some(method) => method.parse()?,
+            None if self.required => self.default_method,
+            None => return Ok(None),
+        };
+
+        match (method, &self.hmac_key) {
+            (PseudonymMethod::Hmac, Some(key)) => Ok(Some(Pseudonymiser::hmac(key))),
+            (PseudonymMethod::Hmac, None) => {
+                Err("this server has no key for hmac pseudonyms".into())
+            }
+            (PseudonymMethod::Random, _) => Ok(Some(Pseudonymiser::random())),
+        }
+    }
+}
+
+/// Identifies a subject in a response: by its ID, or by its token when the
+/// response is pseudonymised.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum SubjectRef {
+    /// The ID of the subject.
+    Id(i32),
+    /// The token of the subject.
+    Token(String),
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PseudonymiseQuery {
+    /// Replaces subject IDs with tokens: hmac, random or none. Responses are
+    /// pseudonymised with the server's default method if its policy requires it.
+    pub pseudonymise: Option<String>,
+}
+
+/// Replaces the subject IDs of a response listing subjects with tokens, when
+/// requested or required by the pseudonym policy.
+pub(super) struct SubjectPseudonymiser(Option<Pseudonymiser>);
+
+impl SubjectPseudonymiser {
+    /// Returns the SubjectPseudonymiser of a response from the method its
+    /// request asked for, or the response to a request the policy refuses.
+    pub(super) fn new(
+        service: &RestService,
+        requested: Option<&str>,
+    ) -> Result<Self, HttpResponse> {
+        match service.pseudonym_policy.pseudonymiser(requested) {
+            Ok(pseudonymiser) => Ok(Self(pseudonymiser)),
+            Err(message) => Err(HttpResponse::BadRequest().json(ApiError {
+                error: "error.pseudonymise".into(),
+                message,
+            })),
+        }
+    }
+
+    /// Returns how a subject is identified in the response.
+    pub(super) fn subject(&mut self, id: i32) -> SubjectRef {
+        match &mut self.0 {
+            Some(p) => SubjectRef::Token(p.token(&id.to_string())),
+            None => SubjectRef::Id(id),
+        }
+    }
+
+    /// Responds with a body, recording the tokens given out in it as an export
+    /// of a group.
+    pub(super) async fn respond<T: Serialize>(
+        self,
+        service: &RestService,
+        group_id: i32,
+        body: T,
+    ) -> HttpResponse {
+        let mut response = HttpResponse::Ok();
+        if let Some(p) = self.0.filter(|p| !p.mapping().is_empty()) {
+            match record_export(service, group_id, &p).await {
+                Ok(export_id) => response.header("X-Silo-Export-Id", export_id.to_string()),
+                Err(response) => return response,
+            };
+        }
+
+        response.json(body)
+    }
+}
+
+/// Records the tokens of a pseudonymised export of a group, returning the ID of
+/// the export.
+pub(super) async fn record_export(
+    service: &RestService,
+    group_id: i32,
+    pseudonymiser: &Pseudonymiser,
+) -> Result<i32, HttpResponse> {
+    let pseudonyms: Vec<models::Pseudonym> = pseudonymiser
+        .mapping()
+        .iter()
+        .filter_map(|(id, token)| {
+            id.parse().ok().map(|subject_id| models::Pseudonym {
+                id: 0,
+                export_id: 0,
+                subject_id,
+                token: token.clone(),
+            })
+        })
+        .collect();
+    let export = models::PseudonymExport {
+        id: 0,
+        group_id,
+        method: pseudonymiser.method().name().into(),
+        created_at: Utc::now(),
+    };
+
+    service
+        .db_service
+        .insert_pseudonym_export(&export, &pseudonyms)
+        .await
+        .map_err(db_error)
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReidentifyBody {
+    /// Re-identifies every token of this export.
+    pub export_id: Option<i32>,
+    /// Re-identifies these tokens, from any export.
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReidentifyResponse {
+    pub pseudonyms: Vec<models::Pseudonym>,
+}
+
+#[post("/admin/reidentify")]
+pub(super) async fn admin_reidentify_post(
+    service: web::Data<Arc<RestService>>,
+    request: HttpRequest,
+    body: web::Json<ReidentifyBody>,
+) -> impl Responder {
+    if !service.is_admin(&request) {
+        return HttpResponse::Forbidden().json(ApiError {
+            error: "error.admin.forbidden".into(),
+            message: "re-identifying subjects requires the admin token".into(),
+        });
+    }
+
+    let mut pseudonyms = vec![];
+    if let Some(export_id) = body.export_id {
+        match service
+            .db_service
+            .find_pseudonyms_by_export_id(export_id)
+            .await
+        {
+            Ok(found) => pseudonyms.extend(found),
+            Err(e) => return db_error(e),
+        }
+    }
+    if !body.tokens.is_empty() {
+        match service
+            .db_service
+            .find_pseudonyms_by_tokens(&body.tokens)
+            .await
+        {
+            Ok(found) => pseudonyms.extend(found),
+            Err(e) => return db_error(e),
+        }
+    }
+
+    pseudonyms.sort_by_key(|p| (p.export_id, p.id));
+    pseudonyms.dedup_by_key(|p| p.id);
+
+    HttpResponse::Ok().json(ReidentifyResponse { pseudonyms })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::routes;
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use silo_db::errors::DatabaseError;
+    use silo_db::service::Service;
+
+    /// A database of two groups of two subjects each.
+    struct Fixture;
+
+    macro_rules! fixture_service {
+        ($($method:ident($($arg:ident: $ty:ty),*) -> $result:ty;)*) => {
+            /// The responses of the Fixture. Those it doesn't override fail.
+            trait Responses {
+                $(
+                    fn $method(&self, $($arg: $ty),*) -> Result<$result, DatabaseError> {
+                        let _ = ($($arg,)*);
+                        Err(DatabaseError::Query(stringify!($method).into()))
+                    }
+                )*
+            }
+
+            #[async_trait]
+            impl Service for Fixture {
+                $(
+                    async fn $method(&self, $($arg: $ty),*) -> Result<$result, DatabaseError> {
+                        Responses::$method(self, $($arg),*)
+                    }
+                )*
+            }
+        };
+    }
+
+    silo_db::service_methods!(fixture_service);
+
+    impl Responses for Fixture {
+        fn find_group_by_id(&self, id: i32) -> Result<Option<models::Group>, DatabaseError> {
+            Ok(Some(models::Group { id }))
+        }
+
+        fn find_subjects_by_group_id(
+            &self,
+            id: i32,
+        ) -> Result<Vec<models::Subject>, DatabaseError> {
+            Ok((1..=2)
+                .map(|n| models::Subject {
+                    id: id * 10 + n,
+                    group_id: id,
+                    age: 40,
+                    length_of_stay: 3,
+                    admitted_at: None,
+                })
+                .collect())
+        }
+
+        fn find_subject_trait_links_by_group_id(
+            &self,
+            _: i32,
+        ) -> Result<Vec<models::SubjectTraitLink>, DatabaseError> {
+            Ok(vec![])
+        }
+
+        fn get_traits(&self) -> Result<Vec<models::SubjectTrait>, DatabaseError> {
+            Ok(vec![])
+        }
+
+        fn insert_groups_with_members(
+            &self,
+            members: &[Vec<i32>],
+        ) -> Result<Vec<i32>, DatabaseError> {
+            Ok((1..=members.len() as i32).map(|n| 100 + n).collect())
+        }
+
+        fn insert_pseudonym_export(
+            &self,
+            _: &models::PseudonymExport,
+            pseudonyms: &[models::Pseudonym],
+        ) -> Result<i32, DatabaseError> {
+            assert!(!pseudonyms.is_empty());
+            Ok(7)
+        }
+    }
+
+    /// Sends a request to a server that requires pseudonyms, returning the
+    /// status, the export ID and the body of its response.
+    async fn call(request: test::TestRequest) -> (StatusCode, Option<String>, Value) {
+        let policy = PseudonymPolicy {
+            required: true,
+            ..PseudonymPolicy::default()
+        };
+        let service = RestService::new(Arc::new(Fixture)).with_pseudonym_policy(policy);
+        let mut app = test::init_service(
+            App::new()
+                .data(Arc::new(service))
+                .service(web::scope("/api/v1").configure(routes)),
+        )
+        .await;
+
+        let response = test::call_service(&mut app, request.to_request()).await;
+        let status = response.status();
+        let export_id = response
+            .headers()
+            .get("X-Silo-Export-Id")
+            .map(|h| h.to_str().unwrap().to_string());
+        let body = test::read_body_json(response).await;
+
+        (status, export_id, body)
+    }
+
+    /// Asserts that every subject of a response is identified by a token.
+    fn assert_tokens<'a>(ids: impl IntoIterator<Item = &'a Value>) {
+        let ids: Vec<&Value> = ids.into_iter().collect();
+        assert!(!ids.is_empty());
+        for id in ids {
+            assert_eq!(id.as_str().map(str::len), Some(TOKEN_LENGTH), "{}", id);
+        }
+    }
+
+    /// The length of a token in hexadecimal.
+    const TOKEN_LENGTH: usize = 32;
+
+    #[actix_rt::test]
+    async fn group_subjects_are_pseudonymised() {
+        let (status, export_id, body) =
+            call(test::TestRequest::get().uri("/api/v1/groups/1/subjects")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(export_id.as_deref(), Some("7"));
+        assert_tokens(
+            body["subjects"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|s| &s["id"]),
+        );
+
+        let (status, _, body) =
+            call(test::TestRequest::get().uri("/api/v1/groups/1/subjects?pseudonymise=none")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "error.pseudonymise");
+    }
+
+    #[actix_rt::test]
+    async fn samples_are_pseudonymised() {
+        let (status, export_id, body) = call(
+            test::TestRequest::post()
+                .uri("/api/v1/groups/1/sample")
+                .set_json(&json!({ "size": 2 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(export_id.as_deref(), Some("7"));
+        assert_tokens(body["subjectIds"].as_array().unwrap());
+
+        let (status, _, _) = call(
+            test::TestRequest::post()
+                .uri("/api/v1/groups/1/sample")
+                .set_json(&json!({ "size": 2, "pseudonymise": "none" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn splits_are_pseudonymised() {
+        let (status, export_id, body) = call(
+            test::TestRequest::post()
+                .uri("/api/v1/groups/1/split")
+                .set_json(&json!({ "output": "column" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(export_id.as_deref(), Some("7"));
+        let assignments = body["assignments"].as_object().unwrap();
+        assert_eq!(assignments.len(), 2);
+        for token in assignments.keys() {
+            assert_eq!(token.len(), TOKEN_LENGTH);
+        }
+
+        let (status, _, _) = call(
+            test::TestRequest::post()
+                .uri("/api/v1/groups/1/split")
+                .set_json(&json!({ "output": "column", "pseudonymise": "none" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn matching_reports_are_pseudonymised() {
+        let (status, export_id, body) = call(
+            test::TestRequest::post()
+                .uri("/api/v1/groups/1/match")
+                .set_json(&json!({ "poolGroupId": 2 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(export_id.as_deref(), Some("7"));
+        let sets = body["sets"].as_array().unwrap();
+        assert_tokens(sets.iter().map(|s| &s["caseId"]));
+        assert_tokens(
+            sets.iter()
+                .flat_map(|s| s["controlIds"].as_array().unwrap()),
+        );
+
+        let (status, _, _) = call(
+            test::TestRequest::post()
+                .uri("/api/v1/groups/1/match")
+                .set_json(&json!({ "poolGroupId": 2, "pseudonymise": "none" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
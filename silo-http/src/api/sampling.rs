@@ -0,0 +1,275 @@
+use super::pseudonyms::{SubjectPseudonymiser, SubjectRef};
+use super::validation::{check_split, Validate, Validator};
+use super::{db_error, group_data, group_not_found, new_group, ApiError, RestService};
+use silo_core::models;
+use silo_core::sampling::{SampleSize, Sampler, SplitOptions, SplitSet, Stratification};
+
+use actix_web::{post, web, HttpResponse, Responder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleBody {
+    /// The number of subjects to draw.
+    pub size: Option<usize>,
+    /// The proportion of subjects to draw, between 0 and 1.
+    pub fraction: Option<f64>,
+    /// The seed of the sample. A random seed is picked and reported if none is
+    /// given.
+    pub seed: Option<u64>,
+    /// Replaces subject IDs with tokens: hmac, random or none. Responses are
+    /// pseudonymised with the server's default method if its policy requires it.
+    pub pseudonymise: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleResponse {
+    pub group_id: i32,
+    pub sample_group_id: i32,
+    pub seed: u64,
+    /// The IDs of the sampled subjects, or their tokens if the response is
+    /// pseudonymised.
+    pub subject_ids: Vec<SubjectRef>,
+}
+
+#[post("/groups/{id}/sample")]
+pub(super) async fn groups_sample_post(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    body: web::Json<SampleBody>,
+) -> impl Responder {
+    let mut v = Validator::new();
+    body.validate(&mut v);
+    if let Err(response) = v.finish() {
+        return response;
+    }
+    let size = match (body.size, body.fraction) {
+        (Some(size), _) => SampleSize::Count(size),
+        (None, fraction) => SampleSize::Fraction(fraction.unwrap_or_default()),
+    };
+
+    let mut pseudonymiser = match SubjectPseudonymiser::new(&service, body.pseudonymise.as_deref())
+    {
+        Ok(pseudonymiser) => pseudonymiser,
+        Err(response) => return response,
+    };
+
+    let data = match group_data(&service, id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(id),
+        Err(response) => return response,
+    };
+
+    let sampler = Sampler::new(body.seed.unwrap_or_else(rand::random));
+    let subject_ids = sampler.sample(&data.subjects, size);
+
+    let sample_group_id = match new_group(&service, &subject_ids).await {
+        Ok(group_id) => group_id,
+        Err(response) => return response,
+    };
+
+    let response = SampleResponse {
+        group_id: id,
+        sample_group_id,
+        seed: sampler.seed(),
+        subject_ids: subject_ids
+            .into_iter()
+            .map(|id| pseudonymiser.subject(id))
+            .collect(),
+    };
+    pseudonymiser.respond(&service, id, response).await
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitBody {
+    /// The proportion of subjects in the training set.
+    pub train: Option<f64>,
+    /// The proportion of subjects in the validation set.
+    pub validation: Option<f64>,
+    /// The proportion of subjects in the test set.
+    pub test: Option<f64>,
+    /// The name of the trait the split is stratified by.
+    pub stratify_trait: Option<String>,
+    /// The width of the age bands the split is stratified by, in years.
+    pub age_band: Option<i16>,
+    /// The seed of the split. A random seed is picked and reported if none is
+    /// given.
+    pub seed: Option<u64>,
+    /// The output of the split: groups (default), which writes each set into a
+    /// new group, or column, which only returns the set of each subject.
+    pub output: Option<String>,
+    /// Replaces subject IDs with tokens: hmac, random or none. Responses are
+    /// pseudonymised with the server's default method if its policy requires it.
+    pub pseudonymise: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitGroup {
+    pub set: SplitSet,
+    pub group_id: i32,
+    pub subject_count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitResponse {
+    pub group_id: i32,
+    pub seed: u64,
+    /// The group of each set, when the output is groups.
+    pub groups: Vec<SplitGroup>,
+    /// The set of each subject by ID, or by token if the response is
+    /// pseudonymised, when the output is column.
+    pub assignments: BTreeMap<SubjectRef, SplitSet>,
+}
+
+/// Returns the response to a split stratified by both a trait and an age band.
+pub(super) fn stratify_conflict() -> HttpResponse {
+    HttpResponse::BadRequest().json(ApiError {
+        error: "error.sampling.stratify".into(),
+        message: "stratify by either a trait or an age band".into(),
+    })
+}
+
+/// Parses the options of a split, looking up the trait it is stratified by.
+/// Invalid options are reported on the given fields, in the order of
+/// check_split.
+pub(super) fn split_options(
+    fields: [&str; 4],
+    train: Option<f64>,
+    validation: Option<f64>,
+    test: Option<f64>,
+    stratify_trait: Option<&str>,
+    age_band: Option<i16>,
+    traits: &[models::SubjectTrait],
+) -> Result<SplitOptions, HttpResponse> {
+    let defaults = SplitOptions::default();
+
+    let stratify = match (stratify_trait, age_band) {
+        (Some(_), Some(_)) => return Err(stratify_conflict()),
+        (Some(name), None) => match traits.iter().find(|t| t.trait_name == name) {
+            Some(t) => Some(Stratification::Trait(t.id)),
+            None => {
+                return Err(HttpResponse::BadRequest().json(ApiError {
+                    error: "error.sampling.trait".into(),
+                    message: format!("unknown trait {}", name),
+                }));
+            }
+        },
+        (None, Some(width)) => Some(Stratification::AgeBand(width)),
+        (None, None) => None,
+    };
+
+    let options = SplitOptions {
+        train: train.unwrap_or(defaults.train),
+        validation: validation.unwrap_or(defaults.validation),
+        test: test.unwrap_or(defaults.test),
+        stratify,
+    };
+
+    let mut v = Validator::new();
+    check_split(&mut v, &options, fields);
+    v.finish().map(|_| options)
+}
+
+#[post("/groups/{id}/split")]
+pub(super) async fn groups_split_post(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    body: web::Json<SplitBody>,
+) -> impl Responder {
+    let as_groups = match body.output.as_deref().unwrap_or("groups") {
+        "groups" => true,
+        "column" => false,
+        output => {
+            return HttpResponse::BadRequest().json(ApiError {
+                error: "error.sampling.output".into(),
+                message: format!("unknown split output {}", output),
+            });
+        }
+    };
+
+    let mut pseudonymiser = match SubjectPseudonymiser::new(&service, body.pseudonymise.as_deref())
+    {
+        Ok(pseudonymiser) => pseudonymiser,
+        Err(response) => return response,
+    };
+
+    let data = match group_data(&service, id).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return group_not_found(id),
+        Err(response) => return response,
+    };
+
+    let options = match split_options(
+        ["train", "validation", "test", "ageBand"],
+        body.train,
+        body.validation,
+        body.test,
+        body.stratify_trait.as_deref(),
+        body.age_band,
+        &data.traits,
+    ) {
+        Ok(options) => options,
+        Err(response) => return response,
+    };
+
+    let sampler = Sampler::new(body.seed.unwrap_or_else(rand::random));
+    let assignments = sampler.split(&data.subjects, &data.links, &options);
+
+    if !as_groups {
+        let response = SplitResponse {
+            group_id: id,
+            seed: sampler.seed(),
+            groups: vec![],
+            assignments: assignments
+                .into_iter()
+                .map(|(id, set)| (pseudonymiser.subject(id), set))
+                .collect(),
+        };
+        return pseudonymiser.respond(&service, id, response).await;
+    }
+
+    let members: Vec<Vec<i32>> = SplitSet::ALL
+        .iter()
+        .map(|set| {
+            assignments
+                .iter()
+                .filter(|(_, s)| *s == set)
+                .map(|(id, _)| *id)
+                .collect()
+        })
+        .collect();
+
+    // The groups are inserted together, so a failure leaves none behind.
+    let group_ids = match service
+        .db_service
+        .insert_groups_with_members(&members)
+        .await
+    {
+        Ok(group_ids) => group_ids,
+        Err(e) => return db_error(e),
+    };
+    let groups = SplitSet::ALL
+        .iter()
+        .zip(group_ids)
+        .zip(&members)
+        .map(|((set, group_id), subject_ids)| SplitGroup {
+            set: *set,
+            group_id,
+            subject_count: subject_ids.len(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(SplitResponse {
+        group_id: id,
+        seed: sampler.seed(),
+        groups,
+        assignments: BTreeMap::new(),
+    })
+}
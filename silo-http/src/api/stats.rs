@@ -0,0 +1,56 @@
+use super::{group_data, group_not_found, ApiError, RestService};
+use silo_core::stats::{GroupStats, StatsOptions};
+
+use actix_web::{get, web, HttpResponse, Responder};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StatsQuery {
+    /// The width of each bin of the age histogram, in years.
+    pub age_bin_width: Option<f64>,
+    /// The width of each bin of the length of stay histogram, in days.
+    pub length_of_stay_bin_width: Option<f64>,
+}
+
+impl StatsQuery {
+    /// Returns the StatsOptions of the query, falling back to the defaults.
+    pub fn options(&self) -> StatsOptions {
+        let defaults = StatsOptions::default();
+
+        StatsOptions {
+            age_bin_width: self.age_bin_width.unwrap_or(defaults.age_bin_width),
+            length_of_stay_bin_width: self
+                .length_of_stay_bin_width
+                .unwrap_or(defaults.length_of_stay_bin_width),
+        }
+    }
+}
+
+#[get("/groups/{id}/stats")]
+pub(super) async fn groups_stats_get(
+    service: web::Data<Arc<RestService>>,
+    web::Path(id): web::Path<i32>,
+    web::Query(query): web::Query<StatsQuery>,
+) -> impl Responder {
+    let options = query.options();
+
+    match group_data(&service, id).await {
+        Ok(Some(data)) => match options.validate(&data.subjects) {
+            Ok(()) => HttpResponse::Ok().json(GroupStats::new(
+                id,
+                &data.subjects,
+                &data.links,
+                &data.traits,
+                &options,
+            )),
+            Err(message) => HttpResponse::BadRequest().json(ApiError {
+                error: "error.stats.bins".into(),
+                message,
+            }),
+        },
+        Ok(None) => group_not_found(id),
+        Err(response) => response,
+    }
+}
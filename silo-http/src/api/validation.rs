@@ -0,0 +1,310 @@
+use super::{db_error, RestService};
+use silo_core::sampling::{SplitOptions, Stratification};
+
+use actix_web::HttpResponse;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The largest age accepted for a subject, in years.
+const MAX_AGE: i16 = 150;
+
+/// An invalid field of a request.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    /// The path of the field, such as subjects[0].age, or the name of a path
+    /// parameter.
+    pub field: String,
+    pub message: String,
+}
+
+/// The response to a request that failed validation.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationErrors {
+    pub error: String,
+    pub message: String,
+    pub errors: Vec<FieldError>,
+}
+
+/// Collects the field errors of a request before it reaches the Service.
+#[derive(Debug, Default)]
+pub(super) struct Validator {
+    prefix: String,
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    /// Creates and returns a Validator without errors.
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an error of a field.
+    pub(super) fn error(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: format!("{}{}", self.prefix, field),
+            message: message.into(),
+        });
+    }
+
+    /// Records an error of a field unless a condition holds.
+    pub(super) fn check(&mut self, valid: bool, field: &str, message: &str) {
+        if !valid {
+            self.error(field, message);
+        }
+    }
+
+    /// Validates a nested payload, prefixing the fields of its errors with the
+    /// field it was found at.
+    pub(super) fn nested<T: Validate + ?Sized>(&mut self, field: &str, payload: &T) {
+        let prefix = format!("{}{}.", self.prefix, field);
+        let outer = std::mem::replace(&mut self.prefix, prefix);
+        payload.validate(self);
+        self.prefix = outer;
+    }
+
+    /// Responds with 422 and every recorded error, if there are any.
+    pub(super) fn finish(self) -> Result<(), HttpResponse> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(HttpResponse::UnprocessableEntity().json(ValidationErrors {
+                error: "error.validation".into(),
+                message: "the request has invalid fields".into(),
+                errors: self.errors,
+            })),
+        }
+    }
+}
+
+/// A request payload whose fields can be checked without the database.
+pub(super) trait Validate {
+    /// Records the errors of the payload's fields.
+    fn validate(&self, v: &mut Validator);
+}
+
+impl Validate for super::InsertExternalId {
+    fn validate(&self, v: &mut Validator) {
+        v.check(
+            !self.namespace.trim().is_empty(),
+            "namespace",
+            "must not be empty",
+        );
+        v.check(!self.value.trim().is_empty(), "value", "must not be empty");
+    }
+}
+
+impl Validate for super::InsertSubject {
+    fn validate(&self, v: &mut Validator) {
+        v.check(
+            (0..=MAX_AGE).contains(&self.age),
+            "age",
+            &format!("must be between 0 and {}", MAX_AGE),
+        );
+        v.check(
+            self.length_of_stay >= 0,
+            "lengthOfStay",
+            "must not be negative",
+        );
+        for (i, external_id) in self.external_ids.iter().enumerate() {
+            v.nested(&format!("externalIds[{}]", i), external_id);
+        }
+    }
+}
+
+impl Validate for super::InsertTrait {
+    fn validate(&self, v: &mut Validator) {
+        v.check(
+            !self.trait_name.trim().is_empty(),
+            "traitName",
+            "must not be empty",
+        );
+        v.check(
+            self.parent_id >= 0,
+            "parentId",
+            "must be 0 for a root trait or the ID of a trait",
+        );
+    }
+}
+
+impl Validate for super::InsertSubjectSubjectTrait {
+    fn validate(&self, v: &mut Validator) {
+        if let (Some(onset_at), Some(resolved_at)) = (self.onset_at, self.resolved_at) {
+            v.check(
+                resolved_at >= onset_at,
+                "resolvedAt",
+                "a trait cannot resolve before its onset",
+            );
+        }
+        if let Some(value) = self.value {
+            v.check(value.is_finite(), "value", "must be a finite number");
+        }
+        if let Some(qualifier) = &self.qualifier {
+            v.check(
+                !qualifier.trim().is_empty(),
+                "qualifier",
+                "must not be empty",
+            );
+        }
+    }
+}
+
+impl Validate for super::matching::MatchBody {
+    fn validate(&self, v: &mut Validator) {
+        v.check(self.ratio != Some(0), "ratio", "must be at least 1");
+        v.check(
+            self.age_tolerance.unwrap_or(0) >= 0,
+            "ageTolerance",
+            "must not be negative",
+        );
+        v.check(
+            self.length_of_stay_tolerance.unwrap_or(0) >= 0,
+            "lengthOfStayTolerance",
+            "must not be negative",
+        );
+    }
+}
+
+impl Validate for super::sampling::SampleBody {
+    fn validate(&self, v: &mut Validator) {
+        match (self.size, self.fraction) {
+            (Some(_), None) => {}
+            (None, Some(fraction)) => v.check(
+                (0.0..=1.0).contains(&fraction),
+                "fraction",
+                "must be between 0 and 1",
+            ),
+            _ => v.error("size", "give either a size or a fraction"),
+        }
+    }
+}
+
+impl Validate for super::privacy::PrivacyBudgetBody {
+    fn validate(&self, v: &mut Validator) {
+        v.check(
+            self.epsilon_total >= 0.0 && self.epsilon_total.is_finite(),
+            "epsilonTotal",
+            "must be a non-negative number",
+        );
+    }
+}
+
+/// Records the errors of the options of a split. The fields of its train,
+/// validation and test proportions and of its age band width are named in
+/// that order.
+pub(super) fn check_split(v: &mut Validator, options: &SplitOptions, fields: [&str; 4]) {
+    let proportions = [options.train, options.validation, options.test];
+    for (proportion, field) in proportions.iter().zip(&fields) {
+        v.check(
+            proportion.is_finite() && *proportion >= 0.0,
+            field,
+            "must not be negative",
+        );
+    }
+    v.check(
+        proportions.iter().sum::<f64>() > 0.0,
+        fields[0],
+        "at least one split proportion must be positive",
+    );
+    if let Some(Stratification::AgeBand(width)) = options.stratify {
+        v.check(width > 0, fields[3], "must be at least a year wide");
+    }
+}
+
+/// Records an error if a trait doesn't exist.
+pub(super) async fn check_trait_exists(
+    service: &RestService,
+    v: &mut Validator,
+    field: &str,
+    id: i32,
+) -> Result<(), HttpResponse> {
+    match service.db_service.find_subject_trait_by_id(id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => v.error(field, format!("trait {} does not exist", id)),
+        Err(e) => return Err(db_error(e)),
+    }
+    Ok(())
+}
+
+/// Records an error if a subject isn't in a group, either directly or as a
+/// member.
+pub(super) async fn check_subject_in_group(
+    service: &RestService,
+    v: &mut Validator,
+    group_id: i32,
+    subject_id: i32,
+) -> Result<(), HttpResponse> {
+    match service
+        .db_service
+        .find_subject_in_group(group_id, subject_id)
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => v.error(
+            "subjectId",
+            format!("subject {} is not in group {}", subject_id, group_id),
+        ),
+        Err(e) => return Err(db_error(e)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::external_ids::ImportBody;
+    use super::super::matching::MatchBody;
+    use super::super::InsertTrait;
+    use super::*;
+
+    /// Returns the fields of every error of a payload.
+    fn invalid_fields<T: Validate>(payload: &T) -> Vec<String> {
+        let mut v = Validator::new();
+        payload.validate(&mut v);
+        v.errors.into_iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn payload_errors() {
+        let import: ImportBody = serde_json::from_str(
+            r#"{"subjects": [
+                {"age": 40, "lengthOfStay": 3},
+                {"age": -1, "lengthOfStay": 3, "externalIds": [{"namespace": "mrn", "value": " "}]}
+            ]}"#,
+        )
+        .unwrap();
+        let mut v = Validator::new();
+        for (i, subject) in import.subjects.iter().enumerate() {
+            v.nested(&format!("subjects[{}]", i), subject);
+        }
+        let fields: Vec<String> = v.errors.iter().map(|e| e.field.clone()).collect();
+        assert_eq!(
+            fields,
+            vec!["subjects[1].age", "subjects[1].externalIds[0].value"]
+        );
+
+        let t = InsertTrait {
+            parent_id: -2,
+            trait_name: "".into(),
+        };
+        assert_eq!(invalid_fields(&t), vec!["traitName", "parentId"]);
+
+        let matching: MatchBody =
+            serde_json::from_str(r#"{"poolGroupId": 1, "ratio": 0, "lengthOfStayTolerance": -1}"#)
+                .unwrap();
+        assert_eq!(
+            invalid_fields(&matching),
+            vec!["ratio", "lengthOfStayTolerance"]
+        );
+
+        let mut v = Validator::new();
+        let options = SplitOptions {
+            train: -0.5,
+            validation: 0.0,
+            test: 0.0,
+            stratify: Some(Stratification::AgeBand(0)),
+        };
+        check_split(&mut v, &options, ["train", "validation", "test", "ageBand"]);
+        let fields: Vec<String> = v.errors.iter().map(|e| e.field.clone()).collect();
+        assert_eq!(fields, vec!["train", "train", "ageBand"]);
+    }
+}
@@ -1,5 +1,4 @@
 use silo_transform::matrix::*;
-use std::collections::HashMap;
 
 fn main() {
     let transformer = MatrixTransformerBuilder::new()
@@ -7,21 +6,19 @@ fn main() {
         .with_header(true)
         .with_int_field("age")
         .with_int_field("length_of_stay")
-        .with_binary_field("sex")
+        .with_string_field("sex")
+        .with_column(Column::float("bmi").with_precision(1))
         .with_binary_field("abdominal_pain")
         .with_binary_field("cough")
         .build();
 
-    let mut int_fields: HashMap<String, i32> = HashMap::new();
-    int_fields.insert("sex".into(), 0);
-    int_fields.insert("length_of_stay".into(), 123);
-    int_fields.insert("age".into(), 24);
-
-    let mut binary_fields: HashMap<String, bool> = HashMap::new();
-    binary_fields.insert("abdominal_pain".into(), true);
-    binary_fields.insert("cough".into(), true);
-
-    let patient = MatrixTransformerRow::new(binary_fields, int_fields);
+    let patient = MatrixTransformerRow::new()
+        .with_value("sex", "F")
+        .with_value("length_of_stay", 123)
+        .with_value("age", 24)
+        .with_value("bmi", 22.84)
+        .with_value("abdominal_pain", true)
+        .with_value("cough", true);
 
     let output = transformer.generate(vec![patient.clone(), patient]);
 
@@ -0,0 +1,612 @@
+use crate::codebook::CodebookFormat;
+use crate::matrix::*;
+use crate::pseudonym::Pseudonymiser;
+use crate::rollup::{RollupLevel, TraitTree};
+use actix::prelude::*;
+use actix_threadpool::BlockingError;
+use silo_core::models;
+use silo_core::sampling::{Sampler, SplitOptions, Stratification};
+use silo_core::window::TimeWindow;
+use silo_db::actor::{
+    DbActor, FindGroupById, FindSubjectTraitLinksByGroupId, FindSubjectsByGroupId, GetTraits,
+};
+use silo_db::errors::DatabaseError;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Actix actor generating matrices. Data is fetched through the database
+/// actor, and matrices are built and formatted on a worker pool rather than on
+/// the executor of the actor.
+pub struct TransformActor {
+    db: Addr<DbActor>,
+}
+
+impl Actor for TransformActor {
+    type Context = Context<Self>;
+}
+
+impl TransformActor {
+    /// Creates and returns a TransformActor fetching data through a database
+    /// actor.
+    pub fn new(db: Addr<DbActor>) -> Self {
+        Self { db }
+    }
+}
+
+/// The data of a group that its matrices are generated from.
+#[derive(Debug, Clone)]
+pub struct GroupData {
+    /// Every trait, which the trait columns of a matrix are found in.
+    pub traits: Vec<models::SubjectTrait>,
+    /// The subjects of the group, one per row.
+    pub subjects: Vec<models::Subject>,
+    /// The trait links of the subjects of the group.
+    pub links: Vec<models::SubjectTraitLink>,
+}
+
+/// Fetches the data of a group by its ID through the database actor. Returns
+/// None if the group doesn't exist.
+async fn fetch_group_data(db: &Addr<DbActor>, id: i32) -> Result<Option<GroupData>, DatabaseError> {
+    if delivered(db.send(FindGroupById(id)).await)?.is_none() {
+        return Ok(None);
+    }
+
+    let (traits, subjects, links) = futures::join!(
+        db.send(GetTraits),
+        db.send(FindSubjectsByGroupId(id)),
+        db.send(FindSubjectTraitLinksByGroupId(id)),
+    );
+
+    Ok(Some(GroupData {
+        traits: delivered(traits)?,
+        subjects: delivered(subjects)?,
+        links: delivered(links)?,
+    }))
+}
+
+/// Returns the result of a message to the database actor, which is unavailable
+/// if the actor has stopped.
+fn delivered<T>(
+    result: Result<Result<T, DatabaseError>, MailboxError>,
+) -> Result<T, DatabaseError> {
+    result.unwrap_or_else(|e| Err(DatabaseError::Unavailable(format!("{}", e))))
+}
+
+/// Which file of a matrix export is returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatrixSelection {
+    /// A single file of the export.
+    File(MatrixFileKind),
+    /// Every file of the export in a zip archive.
+    Bundle,
+}
+
+/// How a trait column of a matrix is output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraitOutput {
+    /// Whether or not the subject has the trait.
+    Presence,
+    /// The value of the trait.
+    Value,
+    /// The severity of the trait as an ordinal.
+    Severity,
+}
+
+impl TraitOutput {
+    /// Returns the name of the column of a trait output this way. Values and
+    /// severities are suffixed, so each output of a trait has its own column.
+    pub fn column_name(&self, name: &str) -> String {
+        match self {
+            TraitOutput::Presence => name.to_string(),
+            TraitOutput::Value => format!("{}_value", name),
+            TraitOutput::Severity => format!("{}_severity", name),
+        }
+    }
+
+    /// Returns the matrix column of a trait output this way.
+    fn column(&self, name: &str) -> Column {
+        let column_name = self.column_name(name);
+        match self {
+            TraitOutput::Presence => Column::binary(&column_name),
+            TraitOutput::Value => Column::float(&column_name),
+            TraitOutput::Severity => Column::int(&column_name),
+        }
+    }
+
+    /// Returns the value of the trait for a subject from its links to the trait.
+    /// Values come from the link with the latest onset, and severities are the
+    /// highest of any link.
+    fn value(&self, links: &[&models::SubjectTraitLink]) -> Value {
+        match self {
+            TraitOutput::Presence => (!links.is_empty()).into(),
+            TraitOutput::Value => links
+                .iter()
+                .filter(|l| l.value.is_some())
+                .max_by_key(|l| (l.onset_at, l.id))
+                .and_then(|l| l.value)
+                .into(),
+            TraitOutput::Severity => links.iter().filter_map(|l| l.severity()).max().into(),
+        }
+    }
+}
+
+/// A split column of a matrix, labelling the train, validation or test set of
+/// each subject.
+#[derive(Debug, Clone)]
+pub struct MatrixSplit {
+    /// The seed of the split.
+    pub seed: u64,
+    /// The proportions of the sets, and the age bands the split is stratified
+    /// by, if any.
+    pub options: SplitOptions,
+    /// The name of the trait the split is stratified by, if any. It is looked
+    /// up with the data of the group.
+    pub stratify_trait: Option<String>,
+}
+
+/// Generates a matrix of the subjects of a group. The data of the group is
+/// fetched through the database actor, and its rows are built and formatted on
+/// the worker pool.
+#[derive(Message)]
+#[rtype(result = "Result<GroupMatrix, TransformError>")]
+pub struct GenerateMatrix {
+    /// The ID of the group.
+    pub group_id: i32,
+    /// The subject attributes output as columns: id, age or length_of_stay.
+    pub attributes: Vec<String>,
+    /// The trait columns, by trait name, and how each is output. A trait may be
+    /// output more than one way.
+    pub traits: Vec<(String, TraitOutput)>,
+    /// The window of time traits must have applied in, if any.
+    pub window: Option<TimeWindow>,
+    /// The level traits are rolled up to, if any.
+    pub rollup: Option<RollupLevel>,
+    /// The split column of the matrix, if any.
+    pub split: Option<MatrixSplit>,
+    /// Replaces subject IDs with tokens, if given.
+    pub pseudonymiser: Option<Pseudonymiser>,
+    /// The output type of the matrix.
+    pub output_type: MatrixOutputType,
+    /// Whether or not the matrix has a header.
+    pub header: bool,
+    /// The format of the codebook table.
+    pub codebook_format: CodebookFormat,
+    /// The file of the export to return.
+    pub selection: MatrixSelection,
+}
+
+/// A generated matrix of a group.
+pub struct GroupMatrix {
+    /// The selected file of the export.
+    pub file: MatrixFile,
+    /// The Pseudonymiser of the export, with the token of every subject, if it
+    /// was pseudonymised.
+    pub pseudonymiser: Option<Pseudonymiser>,
+}
+
+/// TransformError is returned when a matrix cannot be generated.
+#[derive(Debug, Clone)]
+pub enum TransformError {
+    /// The group does not exist.
+    NoSuchGroup(i32),
+    /// The data of the group could not be fetched.
+    Database(DatabaseError),
+    /// Traits cannot be rolled up to the requested level.
+    Rollup(String),
+    /// The trait the split is stratified by does not exist.
+    NoSuchTrait(String),
+    /// The matrix could not be formatted.
+    Generate(String),
+    /// The files of the matrix could not be bundled.
+    Bundle(String),
+    /// The output type of the matrix does not produce the selected file.
+    NoSuchFile,
+    /// The worker pool stopped before the matrix was generated.
+    Canceled,
+}
+
+impl Display for TransformError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            TransformError::NoSuchGroup(id) => write!(f, "group {} does not exist", id),
+            TransformError::Database(e) => write!(f, "{}", e),
+            TransformError::Rollup(e) => write!(f, "{}", e),
+            TransformError::NoSuchTrait(name) => write!(f, "unknown trait {}", name),
+            TransformError::Generate(e) => write!(f, "{}", e),
+            TransformError::Bundle(e) => write!(f, "{}", e),
+            TransformError::NoSuchFile => write!(
+                f,
+                "the requested file could not be produced for this format"
+            ),
+            TransformError::Canceled => write!(f, "the matrix generation was canceled"),
+        }
+    }
+}
+
+impl Handler<GenerateMatrix> for TransformActor {
+    type Result = ResponseFuture<Result<GroupMatrix, TransformError>>;
+
+    fn handle(&mut self, msg: GenerateMatrix, _: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+
+        Box::pin(async move {
+            let data = match fetch_group_data(&db, msg.group_id).await {
+                Ok(Some(data)) => data,
+                Ok(None) => return Err(TransformError::NoSuchGroup(msg.group_id)),
+                Err(e) => return Err(TransformError::Database(e)),
+            };
+
+            match actix_threadpool::run(move || generate(msg, data)).await {
+                Ok(matrix) => Ok(matrix),
+                Err(BlockingError::Error(e)) => Err(e),
+                Err(BlockingError::Canceled) => Err(TransformError::Canceled),
+            }
+        })
+    }
+}
+
+/// Returns the matrix column of a subject attribute.
+fn attribute_column(name: &str) -> Column {
+    match name {
+        "age" => Column::int(name).with_units("years"),
+        "length_of_stay" => Column::int(name).with_units("days"),
+        _ => Column::int(name),
+    }
+}
+
+/// Builds a TraitTree from every trait in the database.
+fn trait_tree(traits: &[models::SubjectTrait]) -> TraitTree {
+    let names: HashMap<i32, &str> = traits
+        .iter()
+        .map(|t| (t.id, t.trait_name.as_str()))
+        .collect();
+
+    let mut tree = TraitTree::new();
+    for t in traits {
+        tree.insert(&t.trait_name, names.get(&t.parent_id).copied());
+        tree.set_id(&t.trait_name, t.id);
+    }
+
+    tree
+}
+
+/// Returns the split label of every subject of a group by ID.
+fn split_labels(
+    split: MatrixSplit,
+    traits: &[models::SubjectTrait],
+    subjects: &[models::Subject],
+    links: &[models::SubjectTraitLink],
+) -> Result<HashMap<String, String>, TransformError> {
+    let mut options = split.options;
+    if let Some(name) = split.stratify_trait {
+        match traits.iter().find(|t| t.trait_name == name) {
+            Some(t) => options.stratify = Some(Stratification::Trait(t.id)),
+            None => return Err(TransformError::NoSuchTrait(name)),
+        }
+    }
+
+    Ok(Sampler::new(split.seed)
+        .split(subjects, links, &options)
+        .into_iter()
+        .map(|(id, set)| (id.to_string(), set.name().to_string()))
+        .collect())
+}
+
+/// Builds the rows of a matrix of a group and generates its selected file.
+fn generate(msg: GenerateMatrix, data: GroupData) -> Result<GroupMatrix, TransformError> {
+    let GroupData {
+        traits,
+        subjects,
+        links,
+    } = data;
+
+    let tree = trait_tree(&traits);
+    if let Some(level) = &msg.rollup {
+        let depth = match level.depth(&tree) {
+            Some(depth) => depth,
+            None => {
+                return Err(TransformError::Rollup(format!(
+                    "cannot roll traits up to {:?}",
+                    level
+                )));
+            }
+        };
+
+        // Rolled up columns are named after the traits they roll up to, so
+        // they mustn't share a name with another column.
+        let mut names: Vec<String> = msg.attributes.clone();
+        names.extend(
+            msg.traits
+                .iter()
+                .map(|(name, output)| output.column_name(name)),
+        );
+        if msg.split.is_some() {
+            names.push("split".into());
+        }
+        if let Some(name) = tree
+            .traits_at_depth(depth)
+            .into_iter()
+            .find(|name| names.contains(name))
+        {
+            return Err(TransformError::Rollup(format!(
+                "the rolled up column {} clashes with another column",
+                name
+            )));
+        }
+    }
+
+    let links = match &msg.window {
+        Some(window) => window.filter(&subjects, &links),
+        None => links,
+    };
+
+    let split = match msg.split {
+        Some(split) => Some(split_labels(split, &traits, &subjects, &links)?),
+        None => None,
+    };
+
+    let trait_names_by_id: HashMap<i32, &str> = traits
+        .iter()
+        .map(|t| (t.id, t.trait_name.as_str()))
+        .collect();
+    let mut held: HashMap<i32, Vec<(&str, &models::SubjectTraitLink)>> = HashMap::new();
+    for link in &links {
+        if let Some(name) = trait_names_by_id.get(&link.subject_trait_id) {
+            held.entry(link.subject_id).or_default().push((name, link));
+        }
+    }
+
+    let attributes = &msg.attributes;
+    let has_attribute = |name: &str| attributes.iter().any(|a| a == name);
+    let mut pseudonymiser = msg.pseudonymiser;
+    let mut rows = vec![];
+
+    for subject in &subjects {
+        let mut row = match &mut pseudonymiser {
+            Some(p) => MatrixTransformerRow::new().with_id(p.token(&subject.id.to_string())),
+            None => MatrixTransformerRow::new().with_id(subject.id),
+        };
+
+        if has_attribute("id") {
+            match row.id().map(String::from) {
+                Some(token) if pseudonymiser.is_some() => row.set("id", token),
+                _ => row.set("id", subject.id),
+            }
+        }
+
+        if has_attribute("age") {
+            row.set("age", subject.age);
+        }
+
+        if has_attribute("length_of_stay") {
+            row.set("length_of_stay", subject.length_of_stay);
+        }
+
+        let subject_traits = held.get(&subject.id).map_or(&[][..], |t| t.as_slice());
+
+        // Rolled up fields are computed from every trait the subject holds,
+        // separately from the cells of its trait columns.
+        if msg.rollup.is_some() {
+            for (t, _) in subject_traits {
+                row.hold(t);
+            }
+        }
+
+        for (name, output) in &msg.traits {
+            let trait_links: Vec<&models::SubjectTraitLink> = subject_traits
+                .iter()
+                .filter(|(t, _)| t == name)
+                .map(|(_, link)| *link)
+                .collect();
+            row.set(&output.column_name(name), output.value(&trait_links));
+        }
+
+        rows.push(row);
+    }
+
+    let mut transformer = MatrixTransformerBuilder::new()
+        .output_as(msg.output_type)
+        .with_header(msg.header)
+        .with_codebook_format(msg.codebook_format);
+
+    for name in attributes {
+        transformer = match (name.as_str(), &pseudonymiser) {
+            ("id", Some(_)) => transformer.with_column(Column::string(name)),
+            _ => transformer.with_column(attribute_column(name)),
+        };
+    }
+
+    for (name, output) in &msg.traits {
+        let column = output.column(name).with_trait_path(tree.path(name));
+        transformer = match tree.id(name) {
+            Some(trait_id) => transformer.with_column(column.with_trait(trait_id, name)),
+            None => transformer.with_column(column),
+        };
+    }
+
+    if let Some(level) = msg.rollup {
+        transformer = transformer.with_rollup(level, tree);
+    }
+
+    if let Some(labels) = split {
+        // Rows are keyed by their tokens once pseudonymised.
+        let labels = match &pseudonymiser {
+            Some(p) => labels
+                .into_iter()
+                .filter_map(|(id, label)| p.mapping().get(&id).map(|t| (t.clone(), label)))
+                .collect(),
+            None => labels,
+        };
+        transformer = transformer.with_split_column("split", labels);
+    }
+
+    let file = select_file(transformer.build(), rows, msg.selection)?;
+
+    Ok(GroupMatrix {
+        file,
+        pseudonymiser,
+    })
+}
+
+/// Formats the rows of a matrix and returns the selected file.
+fn select_file(
+    transformer: MatrixTransformer,
+    rows: Vec<MatrixTransformerRow>,
+    selection: MatrixSelection,
+) -> Result<MatrixFile, TransformError> {
+    let files = transformer
+        .generate_files(rows)
+        .map_err(|e| TransformError::Generate(e.to_string()))?;
+
+    match selection {
+        MatrixSelection::Bundle => {
+            MatrixFile::bundle(&files).map_err(|e| TransformError::Bundle(e.to_string()))
+        }
+        MatrixSelection::File(kind) => files
+            .into_iter()
+            .find(|file| file.kind == kind)
+            .ok_or(TransformError::NoSuchFile),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use silo_core::models::{Subject, SubjectTrait, SubjectTraitLink};
+
+    /// Returns the data of a group of two subjects, the first of which has
+    /// asthma.
+    fn group_data() -> GroupData {
+        let subject = |id, age| Subject {
+            id,
+            group_id: 1,
+            age,
+            length_of_stay: 3,
+            admitted_at: None,
+        };
+
+        GroupData {
+            traits: vec![SubjectTrait {
+                id: 7,
+                parent_id: 0,
+                trait_name: "asthma".into(),
+            }],
+            subjects: vec![subject(1, 24), subject(2, 60)],
+            links: vec![SubjectTraitLink {
+                id: 1,
+                subject_id: 1,
+                subject_trait_id: 7,
+                onset_at: None,
+                resolved_at: None,
+                value: Some(2.5),
+                qualifier: None,
+            }],
+        }
+    }
+
+    /// Returns a request for a matrix of the ages and asthma of a group.
+    fn request(output_type: MatrixOutputType, selection: MatrixSelection) -> GenerateMatrix {
+        GenerateMatrix {
+            group_id: 1,
+            attributes: vec!["id".into(), "age".into()],
+            traits: vec![
+                ("asthma".into(), TraitOutput::Presence),
+                ("asthma".into(), TraitOutput::Value),
+            ],
+            window: None,
+            rollup: None,
+            split: None,
+            pseudonymiser: None,
+            output_type,
+            header: false,
+            codebook_format: CodebookFormat::Markdown,
+            selection,
+        }
+    }
+
+    #[test]
+    fn rows_from_group_data() {
+        let msg = request(
+            MatrixOutputType::Tsv,
+            MatrixSelection::File(MatrixFileKind::Matrix),
+        );
+        let matrix = generate(msg, group_data()).unwrap();
+        assert_eq!(matrix.file.contents, b"1\t24\t1\t2.5\t\n2\t60\t0\tNULL\t\n");
+        assert!(matrix.pseudonymiser.is_none());
+
+        let mut msg = request(
+            MatrixOutputType::Tsv,
+            MatrixSelection::File(MatrixFileKind::Matrix),
+        );
+        msg.pseudonymiser = Some(Pseudonymiser::hmac(b"secret"));
+        let matrix = generate(msg, group_data()).unwrap();
+        let mut p = matrix.pseudonymiser.unwrap();
+        assert_eq!(p.mapping().len(), 2);
+        let contents = String::from_utf8(matrix.file.contents).unwrap();
+        assert!(contents.starts_with(&format!("{}\t24\t", p.token("1"))));
+    }
+
+    #[test]
+    fn unknown_query_traits() {
+        let mut msg = request(
+            MatrixOutputType::Tsv,
+            MatrixSelection::File(MatrixFileKind::Matrix),
+        );
+        msg.split = Some(MatrixSplit {
+            seed: 1,
+            options: SplitOptions::default(),
+            stratify_trait: Some("copd".into()),
+        });
+        let unknown = generate(msg, group_data());
+        assert!(matches!(unknown, Err(TransformError::NoSuchTrait(name)) if name == "copd"));
+
+        let mut msg = request(
+            MatrixOutputType::Tsv,
+            MatrixSelection::File(MatrixFileKind::Matrix),
+        );
+        msg.rollup = Some(RollupLevel::To("copd".into()));
+        let rollup = generate(msg, group_data());
+        assert!(matches!(rollup, Err(TransformError::Rollup(_))));
+
+        // Rolling up to the root gives a second asthma column.
+        let mut msg = request(
+            MatrixOutputType::Tsv,
+            MatrixSelection::File(MatrixFileKind::Matrix),
+        );
+        msg.rollup = Some(RollupLevel::Depth(1));
+        let clash = generate(msg, group_data());
+        assert!(matches!(clash, Err(TransformError::Rollup(_))));
+    }
+
+    #[test]
+    fn selected_files() {
+        let file = generate(
+            request(
+                MatrixOutputType::Tsv,
+                MatrixSelection::File(MatrixFileKind::Matrix),
+            ),
+            group_data(),
+        )
+        .unwrap()
+        .file;
+        assert_eq!(file.kind, MatrixFileKind::Matrix);
+
+        let file = generate(
+            request(MatrixOutputType::Tsv, MatrixSelection::Bundle),
+            group_data(),
+        )
+        .unwrap()
+        .file;
+        assert_eq!(file.kind, MatrixFileKind::Bundle);
+
+        // Dense matrices have no row ID file.
+        let missing = generate(
+            request(
+                MatrixOutputType::Tsv,
+                MatrixSelection::File(MatrixFileKind::RowIds),
+            ),
+            group_data(),
+        );
+        assert!(matches!(missing, Err(TransformError::NoSuchFile)));
+    }
+}
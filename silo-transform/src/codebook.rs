@@ -0,0 +1,277 @@
+use crate::matrix::{Column, ColumnType, MatrixTransformerRow, Value};
+use serde::Serialize;
+
+/// Specifies the table format written alongside the JSON codebook.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodebookFormat {
+    /// Writes the codebook as a markdown table.
+    Markdown,
+    /// Writes the codebook as a CSV.
+    Csv,
+}
+
+impl CodebookFormat {
+    /// Returns the file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CodebookFormat::Markdown => "md",
+            CodebookFormat::Csv => "csv",
+        }
+    }
+
+    /// Returns the MIME type of the format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            CodebookFormat::Markdown => "text/markdown",
+            CodebookFormat::Csv => "text/csv",
+        }
+    }
+}
+
+/// A data dictionary describing every column of an exported matrix.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Codebook {
+    /// The number of rows in the matrix.
+    pub rows: usize,
+    /// The columns of the matrix in output order.
+    pub columns: Vec<CodebookEntry>,
+}
+
+/// Describes a single column of an exported matrix.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodebookEntry {
+    /// The name of the column.
+    pub name: String,
+    /// The type of the column's values.
+    pub column_type: String,
+    /// The ID of the trait the column was generated from, if any.
+    pub trait_id: Option<i32>,
+    /// The code of the trait the column was generated from, if any.
+    pub code: Option<String>,
+    /// The name of the trait the column was generated from, if any.
+    pub label: Option<String>,
+    /// The names of the column's trait and its ancestors, starting at the root.
+    pub path: Vec<String>,
+    /// The units of the column's values, if any.
+    pub units: Option<String>,
+    /// The number of rows with a missing value.
+    pub missing: usize,
+    /// The proportion of non-missing rows that are set, for binary columns.
+    pub prevalence: Option<f64>,
+    /// The smallest value, for numeric columns.
+    pub min: Option<f64>,
+    /// The largest value, for numeric columns.
+    pub max: Option<f64>,
+    /// The mean value, for numeric columns.
+    pub mean: Option<f64>,
+}
+
+impl CodebookEntry {
+    /// Creates and returns a CodebookEntry describing a column's values.
+    fn new(column: &Column, values: &[Value]) -> Self {
+        let present: Vec<f64> = values
+            .iter()
+            .filter_map(|value| value.as_f64())
+            .filter(|value| value.is_finite())
+            .collect();
+        let missing = values
+            .iter()
+            .filter(|value| match value {
+                Value::Missing => true,
+                Value::Float(f) => !f.is_finite(),
+                _ => false,
+            })
+            .count();
+
+        let mean = match present.len() {
+            0 => None,
+            len => Some(present.iter().sum::<f64>() / len as f64),
+        };
+
+        let (prevalence, min, max, mean) = match column.column_type() {
+            ColumnType::Binary => (mean, None, None, None),
+            ColumnType::Int | ColumnType::Float => (
+                None,
+                present.iter().cloned().fold(None, |min, v| match min {
+                    Some(min) if min <= v => Some(min),
+                    _ => Some(v),
+                }),
+                present.iter().cloned().fold(None, |max, v| match max {
+                    Some(max) if max >= v => Some(max),
+                    _ => Some(v),
+                }),
+                mean,
+            ),
+            _ => (None, None, None, None),
+        };
+
+        Self {
+            name: column.name().into(),
+            column_type: column.column_type().name().into(),
+            trait_id: column.trait_id(),
+            code: column.code().map(|code| code.into()),
+            label: column.label().map(|label| label.into()),
+            path: column.trait_path().to_vec(),
+            units: column.units().map(|units| units.into()),
+            missing,
+            prevalence,
+            min,
+            max,
+            mean,
+        }
+    }
+
+    /// Returns the cells of the entry as text, in the order of `HEADERS`.
+    fn cells(&self) -> Vec<String> {
+        let number = |n: Option<f64>| n.map(|n| format!("{:.4}", n)).unwrap_or_default();
+
+        vec![
+            self.name.clone(),
+            self.column_type.clone(),
+            self.trait_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.code.clone().unwrap_or_default(),
+            self.label.clone().unwrap_or_default(),
+            self.path.join(" > "),
+            self.units.clone().unwrap_or_default(),
+            self.missing.to_string(),
+            number(self.prevalence),
+            number(self.min),
+            number(self.max),
+            number(self.mean),
+        ]
+    }
+}
+
+/// The headers of the codebook's table formats.
+const HEADERS: [&str; 12] = [
+    "name",
+    "type",
+    "trait_id",
+    "code",
+    "label",
+    "path",
+    "units",
+    "missing",
+    "prevalence",
+    "min",
+    "max",
+    "mean",
+];
+
+impl Codebook {
+    /// Creates and returns a Codebook describing the columns of a set of rows.
+    pub fn new(columns: &[Column], rows: &[MatrixTransformerRow]) -> Self {
+        let columns = columns
+            .iter()
+            .map(|column| {
+                let values: Vec<Value> = rows
+                    .iter()
+                    .map(|row| {
+                        row.get(column.name())
+                            .cloned()
+                            .unwrap_or_else(|| column.default_value())
+                    })
+                    .collect();
+
+                CodebookEntry::new(column, &values)
+            })
+            .collect();
+
+        Self {
+            rows: rows.len(),
+            columns,
+        }
+    }
+
+    /// Returns the codebook as JSON.
+    pub fn to_json(&self) -> Result<String, std::io::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns the codebook as a table in the given format.
+    pub fn to_table(&self, format: CodebookFormat) -> String {
+        match format {
+            CodebookFormat::Markdown => self.to_markdown(),
+            CodebookFormat::Csv => self.to_csv(),
+        }
+    }
+
+    /// Returns the codebook as a markdown table.
+    pub fn to_markdown(&self) -> String {
+        let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+        let mut builder = line(HEADERS.iter().map(|h| h.to_string()).collect());
+        builder.push_str(&line(HEADERS.iter().map(|_| "---".to_string()).collect()));
+
+        for entry in &self.columns {
+            builder.push_str(&line(
+                entry
+                    .cells()
+                    .iter()
+                    .map(|cell| cell.replace('|', "\\|"))
+                    .collect(),
+            ));
+        }
+
+        builder
+    }
+
+    /// Returns the codebook as a CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut builder = format!("{}\n", HEADERS.join(","));
+
+        for entry in &self.columns {
+            let cells: Vec<String> = entry
+                .cells()
+                .iter()
+                .map(|cell| crate::matrix::escape_csv(cell, ','))
+                .collect();
+
+            builder.push_str(&format!("{}\n", cells.join(",")));
+        }
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codebook_statistics() {
+        let columns = vec![
+            Column::int("age").with_units("years"),
+            Column::binary("asthma")
+                .with_trait(3, "asthma")
+                .with_trait_path(vec!["respiratory_disease".into(), "asthma".into()]),
+        ];
+        let rows = vec![
+            MatrixTransformerRow::new()
+                .with_value("age", 20)
+                .with_value("asthma", true),
+            MatrixTransformerRow::new().with_value("age", 40),
+            MatrixTransformerRow::new().with_value("asthma", Value::Missing),
+        ];
+
+        let codebook = Codebook::new(&columns, &rows);
+
+        let age = &codebook.columns[0];
+        assert_eq!(age.missing, 1);
+        assert_eq!(
+            (age.min, age.max, age.mean),
+            (Some(20.0), Some(40.0), Some(30.0))
+        );
+
+        let asthma = &codebook.columns[1];
+        assert_eq!(asthma.code.as_deref(), Some("3"));
+        assert_eq!(asthma.label.as_deref(), Some("asthma"));
+        assert_eq!(asthma.missing, 1);
+        assert_eq!(asthma.prevalence, Some(0.5));
+        assert!(codebook
+            .to_csv()
+            .contains("asthma,binary,3,3,asthma,respiratory_disease > asthma,,1,0.5000,,,\n"));
+    }
+}
@@ -4,5 +4,17 @@
 //! sample and patient data. It exports an actor, `TransformActor`, as well as
 //! providing various functions and implementations for transforming data.
 
+/// Provides `TransformActor`, which generates matrices on a worker pool.
+pub mod actor;
+
 /// Provides a struct, `MatrixTransformer`, for transforming data into matrices.
 pub mod matrix;
+
+/// Provides a `TraitTree` for rolling traits up to their ancestors in matrices.
+pub mod rollup;
+
+/// Provides a `Codebook` describing the columns of an exported matrix.
+pub mod codebook;
+
+/// Provides a `Pseudonymiser` replacing subject identifiers in exports with tokens.
+pub mod pseudonym;
@@ -1,4 +1,19 @@
-use std::collections::HashMap;
+use crate::codebook::{Codebook, CodebookFormat};
+use crate::rollup::{RollupLevel, TraitTree};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+mod arff;
+
+mod column;
+pub use column::{Column, ColumnType, MissingValue};
+
+mod columnar;
+
+mod sparse;
+
+mod value;
+pub use value::Value;
 
 /// Specifies different output types for a matrix.
 pub enum MatrixOutputType {
@@ -8,53 +23,251 @@ pub enum MatrixOutputType {
     Csv,
     /// Outputs the matrix as a tab-separated file.
     Tsv,
+    /// Outputs the matrix in Matrix Market coordinate format.
+    MatrixMarket,
+    /// Outputs the matrix in LIBSVM/SVMlight format, using a column as the label.
+    Libsvm {
+        /// The name of the column used as the label of each row.
+        label: String,
+    },
+    /// Outputs the matrix as a list of (row, column, value) triples.
+    Coo,
+    /// Outputs the matrix as an Apache Arrow IPC file (Feather v2).
+    ArrowIpc,
+    /// Outputs the matrix as an Apache Parquet file.
+    Parquet,
+    /// Outputs the matrix as a Weka ARFF file.
+    Arff {
+        /// The name written to the `@RELATION` line.
+        relation: String,
+    },
 }
 
-/// Contains both int and binary field vectors.
-#[derive(Clone)]
+impl MatrixOutputType {
+    /// Returns the file extension of the output type.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MatrixOutputType::Json => "json",
+            MatrixOutputType::Csv => "csv",
+            MatrixOutputType::Tsv => "tsv",
+            MatrixOutputType::MatrixMarket => "mtx",
+            MatrixOutputType::Libsvm { .. } => "svm",
+            MatrixOutputType::Coo => "coo.tsv",
+            MatrixOutputType::ArrowIpc => "feather",
+            MatrixOutputType::Parquet => "parquet",
+            MatrixOutputType::Arff { .. } => "arff",
+        }
+    }
+
+    /// Returns the MIME type of the output type.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            MatrixOutputType::Json => "application/json",
+            MatrixOutputType::Csv => "text/csv",
+            MatrixOutputType::Tsv | MatrixOutputType::Coo => "text/tab-separated-values",
+            MatrixOutputType::MatrixMarket
+            | MatrixOutputType::Libsvm { .. }
+            | MatrixOutputType::Arff { .. } => "text/plain",
+            MatrixOutputType::ArrowIpc => "application/vnd.apache.arrow.file",
+            MatrixOutputType::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    /// Returns whether or not the output type is a binary format rather than text.
+    pub fn is_binary(&self) -> bool {
+        match self {
+            MatrixOutputType::ArrowIpc | MatrixOutputType::Parquet => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether or not the output type only writes non-zero values. Sparse
+    /// outputs come with separate row ID and column name files.
+    pub fn is_sparse(&self) -> bool {
+        match self {
+            MatrixOutputType::MatrixMarket
+            | MatrixOutputType::Libsvm { .. }
+            | MatrixOutputType::Coo => true,
+            _ => false,
+        }
+    }
+}
+
+/// Specifies what a file produced by a matrix export contains.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatrixFileKind {
+    /// The matrix itself.
+    Matrix,
+    /// The ID of each row, one per line, in matrix order.
+    RowIds,
+    /// The name of each column, one per line, in matrix order.
+    ColumnNames,
+    /// The codebook describing every column, as JSON.
+    Codebook,
+    /// The codebook describing every column, as a markdown or CSV table.
+    CodebookTable,
+    /// A zip archive of other files.
+    Bundle,
+}
+
+/// A single file produced by a matrix export.
+#[derive(Debug, Clone)]
+pub struct MatrixFile {
+    /// What the file contains.
+    pub kind: MatrixFileKind,
+    /// The name of the file, including its extension.
+    pub name: String,
+    /// The MIME type of the file.
+    pub content_type: String,
+    /// The contents of the file.
+    pub contents: Vec<u8>,
+}
+
+impl MatrixFile {
+    /// Creates and returns a new MatrixFile.
+    fn new(kind: MatrixFileKind, name: &str, content_type: &str, contents: Vec<u8>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            content_type: content_type.into(),
+            contents,
+        }
+    }
+}
+
+impl MatrixFile {
+    /// Bundles files into a single zip archive.
+    pub fn bundle(files: &[MatrixFile]) -> Result<MatrixFile, std::io::Error> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        for file in files {
+            zip.start_file(file.name.as_str(), options)?;
+            zip.write_all(&file.contents)?;
+        }
+
+        let contents = zip.finish()?.into_inner();
+
+        Ok(MatrixFile::new(
+            MatrixFileKind::Bundle,
+            "export.zip",
+            "application/zip",
+            contents,
+        ))
+    }
+}
+
+/// Contains the values of a single row, keyed by column name.
+#[derive(Debug, Clone, Default)]
 pub struct MatrixTransformerRow {
-    binary_fields: HashMap<String, bool>,
-    int_fields: HashMap<String, i32>,
+    id: Option<String>,
+    values: HashMap<String, Value>,
+    traits: HashSet<String>,
 }
 
 impl MatrixTransformerRow {
-    /// Creates and returns a new MatrixTransformerRow.
-    pub fn new(binary_fields: HashMap<String, bool>, int_fields: HashMap<String, i32>) -> Self {
+    /// Creates and returns a new, empty MatrixTransformerRow.
+    pub fn new() -> Self {
         Self {
-            binary_fields,
-            int_fields,
+            id: None,
+            values: HashMap::new(),
+            traits: HashSet::new(),
         }
     }
+
+    /// Sets the ID of the row, written to the row ID file of sparse outputs.
+    pub fn with_id<T: ToString>(mut self, id: T) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Returns the ID of the row, if it has been set.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Sets the value of a field and returns the row.
+    pub fn with_value<V: Into<Value>>(mut self, field_name: &str, value: V) -> Self {
+        self.set(field_name, value);
+        self
+    }
+
+    /// Sets the value of a field.
+    pub fn set<V: Into<Value>>(&mut self, field_name: &str, value: V) {
+        self.values.insert(field_name.into(), value.into());
+    }
+
+    /// Returns the value of a field, if it has been set.
+    pub fn get(&self, field_name: &str) -> Option<&Value> {
+        self.values.get(field_name)
+    }
+
+    /// Marks a trait as held by the row and returns the row.
+    pub fn with_trait(mut self, trait_name: &str) -> Self {
+        self.hold(trait_name);
+        self
+    }
+
+    /// Marks a trait as held by the row. Rolled up fields are computed from the
+    /// traits held, whatever the values of their own columns.
+    pub fn hold(&mut self, trait_name: &str) {
+        self.traits.insert(trait_name.into());
+    }
 }
+
 /// A builder for creating matrix transformers.
 pub struct MatrixTransformerBuilder {
-    __binary_fields: Vec<String>,
-    __int_fields: Vec<String>,
+    __columns: Vec<Column>,
     __output_type: MatrixOutputType,
     __with_header: bool,
+    __rollup: Option<(RollupLevel, TraitTree)>,
+    __codebook_format: CodebookFormat,
+    __split: Option<(String, HashMap<String, String>)>,
 }
 
 impl MatrixTransformerBuilder {
     /// Creates and returns a new MatrixTransformerBuilder.
     pub fn new() -> Self {
         Self {
-            __binary_fields: vec![],
-            __int_fields: vec![],
+            __columns: vec![],
             __output_type: MatrixOutputType::Tsv,
             __with_header: false,
+            __rollup: None,
+            __codebook_format: CodebookFormat::Markdown,
+            __split: None,
         }
     }
 
-    /// Adds an int field to the matrix.
-    pub fn with_int_field(mut self, field_name: &str) -> Self {
-        self.__int_fields.push(field_name.into());
+    /// Adds a column to the matrix. Columns are output in the order they are added.
+    pub fn with_column(mut self, column: Column) -> Self {
+        self.__columns.push(column);
         self
     }
 
+    /// Adds an int field to the matrix.
+    pub fn with_int_field(self, field_name: &str) -> Self {
+        self.with_column(Column::int(field_name))
+    }
+
     /// Adds a binary field to the matrix.
-    pub fn with_binary_field(mut self, field_name: &str) -> Self {
-        self.__binary_fields.push(field_name.into());
-        self
+    pub fn with_binary_field(self, field_name: &str) -> Self {
+        self.with_column(Column::binary(field_name))
+    }
+
+    /// Adds a float field to the matrix.
+    pub fn with_float_field(self, field_name: &str) -> Self {
+        self.with_column(Column::float(field_name))
+    }
+
+    /// Adds a string field to the matrix.
+    pub fn with_string_field(self, field_name: &str) -> Self {
+        self.with_column(Column::string(field_name))
+    }
+
+    /// Adds a date field to the matrix.
+    pub fn with_date_field(self, field_name: &str) -> Self {
+        self.with_column(Column::date(field_name))
     }
 
     /// Sets the output type of the matrix with a MatrixOutputType enum.
@@ -70,75 +283,293 @@ impl MatrixTransformerBuilder {
         self
     }
 
+    /// Rolls traits up to a level of the trait tree. Each trait at that level gets
+    /// a binary field, set when the row has any trait beneath it in the tree.
+    /// Rolled up fields are placed after all other columns.
+    pub fn with_rollup(mut self, level: RollupLevel, tree: TraitTree) -> Self {
+        self.__rollup = Some((level, tree));
+        self
+    }
+
+    /// Sets the table format of the codebook written alongside the JSON codebook.
+    pub fn with_codebook_format(mut self, format: CodebookFormat) -> Self {
+        self.__codebook_format = format;
+        self
+    }
+
+    /// Adds a string column labelling the set each row was split into, such as
+    /// train, validation or test, keyed by row ID. Rows without an ID or label
+    /// are missing. The column is placed after all other columns.
+    pub fn with_split_column(mut self, field_name: &str, labels: HashMap<String, String>) -> Self {
+        self.__split = Some((field_name.into(), labels));
+        self
+    }
+
     /// Builds the MatrixTransformer.
     pub fn build(self) -> MatrixTransformer {
+        let rollup = self
+            .__rollup
+            .and_then(|(level, tree)| level.depth(&tree).map(|depth| (depth, tree)));
+
+        let mut columns = self.__columns;
+        if let Some((depth, tree)) = &rollup {
+            columns.extend(tree.traits_at_depth(*depth).iter().map(|name| {
+                let column = Column::binary(name).with_trait_path(tree.path(name));
+                match tree.id(name) {
+                    Some(id) => column.with_trait(id, name),
+                    None => column,
+                }
+            }));
+        }
+        if let Some((name, _)) = &self.__split {
+            columns.push(Column::string(name));
+        }
+
         MatrixTransformer {
-            binary_fields: self.__binary_fields,
-            int_fields: self.__int_fields,
+            columns,
+            rollup,
+            split: self.__split,
             output_type: self.__output_type,
             with_header: self.__with_header,
+            codebook_format: self.__codebook_format,
         }
     }
 }
 
 /// Provides methods for transforming samples into matrices.
 pub struct MatrixTransformer {
-    binary_fields: Vec<String>,
-    int_fields: Vec<String>,
+    columns: Vec<Column>,
+    rollup: Option<(usize, TraitTree)>,
+    split: Option<(String, HashMap<String, String>)>,
     output_type: MatrixOutputType,
     with_header: bool,
+    codebook_format: CodebookFormat,
 }
 
 impl MatrixTransformer {
-    /// Generates and returns a matrix.
+    /// Returns the columns of the matrix in output order.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Returns the output type of the matrix.
+    pub fn output_type(&self) -> &MatrixOutputType {
+        &self.output_type
+    }
+
+    /// Generates and returns a matrix. Binary output types can only be generated
+    /// with `generate_files`.
     pub fn generate(&self, rows: Vec<MatrixTransformerRow>) -> Result<String, std::io::Error> {
+        if self.output_type.is_binary() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "binary matrices must be generated with generate_files",
+            ));
+        }
+
+        let rows: Vec<MatrixTransformerRow> =
+            rows.into_iter().map(|row| self.prepare(row)).collect();
+
+        let matrix = self.generate_matrix(&rows)?;
+        String::from_utf8(matrix)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Generates and returns the matrix along with its companion files. Sparse
+    /// outputs are followed by a row ID file and a column name file, and every
+    /// output ends with a JSON codebook and a codebook table.
+    pub fn generate_files(
+        &self,
+        rows: Vec<MatrixTransformerRow>,
+    ) -> Result<Vec<MatrixFile>, std::io::Error> {
+        let rows: Vec<MatrixTransformerRow> =
+            rows.into_iter().map(|row| self.prepare(row)).collect();
+
+        let mut files = vec![MatrixFile::new(
+            MatrixFileKind::Matrix,
+            &format!("matrix.{}", self.output_type.extension()),
+            self.output_type.content_type(),
+            self.generate_matrix(&rows)?,
+        )];
+
+        if self.output_type.is_sparse() {
+            files.push(MatrixFile::new(
+                MatrixFileKind::RowIds,
+                "rows.txt",
+                "text/plain",
+                sparse::row_ids(&rows).into_bytes(),
+            ));
+            files.push(MatrixFile::new(
+                MatrixFileKind::ColumnNames,
+                "columns.txt",
+                "text/plain",
+                sparse::column_names(&self.feature_columns()).into_bytes(),
+            ));
+        }
+
+        let codebook = Codebook::new(&self.columns, &rows);
+        files.push(MatrixFile::new(
+            MatrixFileKind::Codebook,
+            "codebook.json",
+            "application/json",
+            codebook.to_json()?.into_bytes(),
+        ));
+        files.push(MatrixFile::new(
+            MatrixFileKind::CodebookTable,
+            &format!("codebook.{}", self.codebook_format.extension()),
+            self.codebook_format.content_type(),
+            codebook.to_table(self.codebook_format).into_bytes(),
+        ));
+
+        Ok(files)
+    }
+
+    /// Generates the matrix itself from rows that have already been rolled up.
+    fn generate_matrix(&self, rows: &[MatrixTransformerRow]) -> Result<Vec<u8>, std::io::Error> {
         let mut matrix_output = String::from("");
 
-        if (self.with_header) {
-            match &self.output_type {
-                Tsv => self.append_tsv_header(&mut matrix_output),
+        match &self.output_type {
+            MatrixOutputType::Json => self.append_json(&mut matrix_output, rows)?,
+            MatrixOutputType::Csv => self.append_delimited(&mut matrix_output, rows, ','),
+            MatrixOutputType::Tsv => self.append_delimited(&mut matrix_output, rows, '\t'),
+            MatrixOutputType::MatrixMarket => {
+                self.append_matrix_market(&mut matrix_output, rows)?
+            }
+            MatrixOutputType::Libsvm { label } => {
+                self.append_libsvm(&mut matrix_output, rows, label)?
+            }
+            MatrixOutputType::Coo => self.append_coo(&mut matrix_output, rows)?,
+            MatrixOutputType::ArrowIpc => return self.write_arrow_ipc(rows),
+            MatrixOutputType::Parquet => return self.write_parquet(rows),
+            MatrixOutputType::Arff { relation } => {
+                self.append_arff(&mut matrix_output, rows, relation)
             }
         }
 
-        rows.iter().for_each(|row| match &self.output_type {
-            // No support yet for types other than TSV.
-            // TODO: implement those output types :)
-            _ => self.append_tsv_row(&mut matrix_output, &row),
-        });
+        Ok(matrix_output.into_bytes())
+    }
 
-        Ok(matrix_output)
+    /// Returns the value of a column in a row, or the column's default if unset.
+    fn value(&self, row: &MatrixTransformerRow, column: &Column) -> Value {
+        row.get(column.name())
+            .cloned()
+            .unwrap_or_else(|| column.default_value())
     }
 
-    /// Appends headers to a builder with a trailing newline.
-    fn append_tsv_header(&self, builder: &mut String) {
-        self.int_fields
+    /// Appends the rows as a JSON array of objects, keyed by column name.
+    fn append_json(
+        &self,
+        builder: &mut String,
+        rows: &[MatrixTransformerRow],
+    ) -> Result<(), std::io::Error> {
+        let objects: Vec<serde_json::Value> = rows
             .iter()
-            .for_each(|field_name| builder.push_str(&format!("{}\t", field_name)));
+            .map(|row| {
+                let object: serde_json::Map<String, serde_json::Value> = self
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        (
+                            column.name().into(),
+                            column.to_json(&self.value(row, column)),
+                        )
+                    })
+                    .collect();
 
-        self.binary_fields
-            .iter()
-            .for_each(|field_name| builder.push_str(&format!("{}\t", field_name)));
+                serde_json::Value::Object(object)
+            })
+            .collect();
+
+        builder.push_str(&serde_json::to_string(&objects)?);
 
-        builder.push_str("\n");
+        Ok(())
     }
 
-    /// Formats a row as TSV and appends it to the builder with a trailing newline.
-    fn append_tsv_row(&self, builder: &mut String, row: &MatrixTransformerRow) {
-        self.int_fields
-            .iter()
-            .for_each(|field_name| match row.int_fields.get(field_name) {
-                Some(value) => builder.push_str(&format!("{}\t", value)),
-                _ => builder.push_str("NULL\t"),
-            });
+    /// Appends the rows as delimited text. TSV keeps a trailing delimiter after
+    /// every cell, while CSV quotes cells that contain special characters.
+    fn append_delimited(
+        &self,
+        builder: &mut String,
+        rows: &[MatrixTransformerRow],
+        delimiter: char,
+    ) {
+        let cells = |row: Vec<String>| -> String {
+            match delimiter {
+                '\t' => row.iter().map(|cell| format!("{}\t", cell)).collect(),
+                _ => row
+                    .iter()
+                    .map(|cell| escape_csv(cell, delimiter))
+                    .collect::<Vec<String>>()
+                    .join(&delimiter.to_string()),
+            }
+        };
+
+        if self.with_header {
+            let header = self
+                .columns
+                .iter()
+                .map(|column| column.name().to_string())
+                .collect();
+
+            builder.push_str(&cells(header));
+            builder.push_str("\n");
+        }
 
-        self.binary_fields
+        for row in rows {
+            let values = self
+                .columns
+                .iter()
+                .map(|column| column.format(&self.value(row, column)))
+                .collect();
+
+            builder.push_str(&cells(values));
+            builder.push_str("\n");
+        }
+    }
+
+    /// Prepares a row for output by rolling up its traits and labelling its split.
+    fn prepare(&self, row: MatrixTransformerRow) -> MatrixTransformerRow {
+        self.label_split(self.roll_up(row))
+    }
+
+    /// Sets the split column of a row from its ID.
+    fn label_split(&self, mut row: MatrixTransformerRow) -> MatrixTransformerRow {
+        if let Some((name, labels)) = &self.split {
+            let label = row.id().and_then(|id| labels.get(id)).cloned();
+            row.set(name, label);
+        }
+
+        row
+    }
+
+    /// Sets the rolled up fields of a row from the traits it holds.
+    fn roll_up(&self, mut row: MatrixTransformerRow) -> MatrixTransformerRow {
+        let (depth, tree) = match &self.rollup {
+            Some(rollup) => rollup,
+            None => return row,
+        };
+
+        let ancestors: HashSet<String> = row
+            .traits
             .iter()
-            .for_each(|field_name| match row.binary_fields.get(field_name) {
-                Some(true) => builder.push_str("1\t".into()),
-                _ => builder.push_str("0\t"),
-            });
+            .filter_map(|name| tree.ancestor_at(name, *depth))
+            .collect();
+
+        for name in tree.traits_at_depth(*depth) {
+            let present = ancestors.contains(&name);
+            row.set(&name, present);
+        }
+
+        row
+    }
+}
 
-        builder.push_str("\n");
+/// Quotes a CSV cell if it contains the delimiter, quotes or newlines.
+pub(crate) fn escape_csv(cell: &str, delimiter: char) -> String {
+    if cell.contains(delimiter) || cell.contains('"') || cell.contains('\n') {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.into()
     }
 }
 
@@ -164,15 +595,112 @@ mod tests {
             .output_as(MatrixOutputType::Tsv)
             .build();
 
-        let mut int_fields: HashMap<String, i32> = HashMap::new();
-        int_fields.insert("age".into(), 24);
+        let row = MatrixTransformerRow::new()
+            .with_value("age", 24)
+            .with_value("migraine", true);
+
+        let output = transformer.generate(vec![row]).unwrap();
+        assert!(output == "24\t1\t\n");
+    }
+
+    #[test]
+    fn typed_csv_matrix() {
+        let transformer = MatrixTransformerBuilder::new()
+            .with_column(Column::float("bmi").with_precision(1))
+            .with_column(Column::string("ward").with_missing(MissingValue::Na))
+            .with_column(Column::int("age").with_missing(MissingValue::Empty))
+            .with_header(true)
+            .output_as(MatrixOutputType::Csv)
+            .build();
+
+        let row = MatrixTransformerRow::new()
+            .with_value("bmi", 31.26)
+            .with_value("age", Value::Missing);
 
-        let mut binary_fields: HashMap<String, bool> = HashMap::new();
-        binary_fields.insert("migraine".into(), true);
+        let output = transformer.generate(vec![row]).unwrap();
+        assert_eq!(output, "bmi,ward,age\n31.3,NA,\n");
+    }
 
-        let row = MatrixTransformerRow::new(binary_fields, int_fields);
+    #[test]
+    fn json_matrix() {
+        let transformer = MatrixTransformerBuilder::new()
+            .with_int_field("age")
+            .with_binary_field("migraine")
+            .with_string_field("sex")
+            .output_as(MatrixOutputType::Json)
+            .build();
+
+        let row = MatrixTransformerRow::new().with_value("age", 24);
 
         let output = transformer.generate(vec![row]).unwrap();
-        assert!(output == "24\t1\t\n");
+        assert_eq!(output, r#"[{"age":24,"migraine":false,"sex":null}]"#);
+    }
+
+    #[test]
+    fn rollup_matrix() {
+        let tree = TraitTree::new()
+            .with_trait("disease", None)
+            .with_trait("respiratory_disease", Some("disease"))
+            .with_trait("asthma", Some("respiratory_disease"))
+            .with_trait("cardiac_disease", Some("disease"));
+
+        let transformer = MatrixTransformerBuilder::new()
+            .with_header(true)
+            .with_rollup(RollupLevel::Depth(2), tree)
+            .build();
+
+        let row = MatrixTransformerRow::new().with_trait("asthma");
+
+        let output = transformer.generate(vec![row]).unwrap();
+        assert_eq!(output, "cardiac_disease\trespiratory_disease\t\n0\t1\t\n");
+    }
+
+    #[test]
+    fn rollup_matrix_with_value_column() {
+        let tree = TraitTree::new()
+            .with_trait("disease", None)
+            .with_trait("respiratory_disease", Some("disease"))
+            .with_trait("asthma", Some("respiratory_disease"))
+            .with_trait("cardiac_disease", Some("disease"));
+
+        let transformer = MatrixTransformerBuilder::new()
+            .with_float_field("asthma")
+            .with_header(true)
+            .with_rollup(RollupLevel::Depth(2), tree)
+            .build();
+
+        // The asthma cell holds its value rather than a marker, but the
+        // subject still holds the trait.
+        let row = MatrixTransformerRow::new()
+            .with_trait("asthma")
+            .with_value("asthma", 7.5);
+
+        let output = transformer.generate(vec![row]).unwrap();
+        assert_eq!(
+            output,
+            "asthma\tcardiac_disease\trespiratory_disease\t\n7.5\t0\t1\t\n"
+        );
+    }
+
+    #[test]
+    fn split_column_matrix() {
+        let labels: HashMap<String, String> = vec![("1".to_string(), "train".to_string())]
+            .into_iter()
+            .collect();
+
+        let transformer = MatrixTransformerBuilder::new()
+            .with_int_field("age")
+            .with_split_column("split", labels)
+            .output_as(MatrixOutputType::Csv)
+            .with_header(true)
+            .build();
+
+        let rows = vec![
+            MatrixTransformerRow::new().with_id(1).with_value("age", 30),
+            MatrixTransformerRow::new().with_id(2).with_value("age", 40),
+        ];
+
+        let output = transformer.generate(rows).unwrap();
+        assert_eq!(output, "age,split\n30,train\n40,NULL\n");
     }
 }
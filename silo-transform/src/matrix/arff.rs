@@ -0,0 +1,113 @@
+use super::{Column, ColumnType, MatrixTransformer, MatrixTransformerRow, Value};
+
+impl MatrixTransformer {
+    /// Appends the rows as a Weka ARFF file, declaring every column as an attribute
+    /// in output order. Missing values are written as `?`.
+    pub(super) fn append_arff(
+        &self,
+        builder: &mut String,
+        rows: &[MatrixTransformerRow],
+        relation: &str,
+    ) {
+        builder.push_str(&format!("@RELATION {}\n\n", quote_arff(relation)));
+
+        for column in &self.columns {
+            builder.push_str(&format!(
+                "@ATTRIBUTE {} {}\n",
+                quote_arff(column.name()),
+                attribute_type(column)
+            ));
+        }
+
+        builder.push_str("\n@DATA\n");
+
+        for row in rows {
+            let values: Vec<String> = self
+                .columns
+                .iter()
+                .map(|column| match self.value(row, column) {
+                    Value::Missing => "?".into(),
+                    Value::Float(f) if !f.is_finite() => "?".into(),
+                    value @ Value::Str(_) | value @ Value::Date(_) => {
+                        quote_arff(&column.format(&value))
+                    }
+                    value => column.format(&value),
+                })
+                .collect();
+
+            builder.push_str(&values.join(","));
+            builder.push_str("\n");
+        }
+    }
+}
+
+/// Returns the ARFF attribute type of a column.
+fn attribute_type(column: &Column) -> String {
+    match column.column_type() {
+        ColumnType::Binary => "{0,1}".into(),
+        ColumnType::Int | ColumnType::Float => "NUMERIC".into(),
+        ColumnType::Str => "STRING".into(),
+        ColumnType::Date => format!("DATE {}", quote_arff(&java_date_format(column))),
+    }
+}
+
+/// Converts the strftime-style date format of a column to the Java
+/// SimpleDateFormat pattern that ARFF expects.
+fn java_date_format(column: &Column) -> String {
+    column
+        .date_format()
+        .replace("%Y", "yyyy")
+        .replace("%m", "MM")
+        .replace("%d", "dd")
+        .replace("%H", "HH")
+        .replace("%M", "mm")
+        .replace("%S", "ss")
+}
+
+/// Quotes a name or value if it contains characters ARFF treats specially.
+fn quote_arff(value: &str) -> String {
+    let special = |c: char| c.is_whitespace() || ",'\"{}%?".contains(c);
+
+    if value.is_empty() || value.chars().any(special) {
+        format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+    } else {
+        value.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn arff_matrix() {
+        let transformer = MatrixTransformerBuilder::new()
+            .with_int_field("age")
+            .with_binary_field("asthma")
+            .with_string_field("ward")
+            .output_as(MatrixOutputType::Arff {
+                relation: "group_1".into(),
+            })
+            .build();
+
+        let rows = vec![
+            MatrixTransformerRow::new()
+                .with_value("age", 24)
+                .with_value("asthma", true)
+                .with_value("ward", "north wing"),
+            MatrixTransformerRow::new().with_value("ward", "icu"),
+        ];
+
+        let output = transformer.generate(rows).unwrap();
+        assert_eq!(
+            output,
+            "@RELATION group_1\n\n\
+             @ATTRIBUTE age NUMERIC\n\
+             @ATTRIBUTE asthma {0,1}\n\
+             @ATTRIBUTE ward STRING\n\n\
+             @DATA\n\
+             24,1,'north wing'\n\
+             ?,0,icu\n"
+        );
+    }
+}
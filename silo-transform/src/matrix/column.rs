@@ -0,0 +1,278 @@
+use super::value::Value;
+
+/// Specifies the type of the values in a column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    /// A binary (0 or 1) column, such as the presence of a trait.
+    Binary,
+    /// An integer column.
+    Int,
+    /// A floating point column.
+    Float,
+    /// A string column.
+    Str,
+    /// A date column.
+    Date,
+}
+
+impl ColumnType {
+    /// Returns the name of the type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnType::Binary => "binary",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Str => "string",
+            ColumnType::Date => "date",
+        }
+    }
+}
+
+/// Specifies the token written in place of a missing value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingValue {
+    /// Writes `NULL`.
+    Null,
+    /// Writes `NA`.
+    Na,
+    /// Writes nothing.
+    Empty,
+}
+
+impl MissingValue {
+    /// Returns the token written for a missing value.
+    pub fn token(&self) -> &'static str {
+        match self {
+            MissingValue::Null => "NULL",
+            MissingValue::Na => "NA",
+            MissingValue::Empty => "",
+        }
+    }
+}
+
+/// Describes a single column of a matrix, its type and how its values are formatted.
+#[derive(Debug, Clone)]
+pub struct Column {
+    name: String,
+    column_type: ColumnType,
+    missing: MissingValue,
+    precision: Option<usize>,
+    date_format: String,
+    trait_id: Option<i32>,
+    code: Option<String>,
+    label: Option<String>,
+    trait_path: Vec<String>,
+    units: Option<String>,
+}
+
+impl Column {
+    /// Creates and returns a new Column with a name and type.
+    pub fn new(name: &str, column_type: ColumnType) -> Self {
+        Self {
+            name: name.into(),
+            column_type,
+            missing: MissingValue::Null,
+            precision: None,
+            date_format: "%Y-%m-%d".into(),
+            trait_id: None,
+            code: None,
+            label: None,
+            trait_path: vec![],
+            units: None,
+        }
+    }
+
+    /// Creates and returns a new binary Column.
+    pub fn binary(name: &str) -> Self {
+        Self::new(name, ColumnType::Binary)
+    }
+
+    /// Creates and returns a new int Column.
+    pub fn int(name: &str) -> Self {
+        Self::new(name, ColumnType::Int)
+    }
+
+    /// Creates and returns a new float Column.
+    pub fn float(name: &str) -> Self {
+        Self::new(name, ColumnType::Float)
+    }
+
+    /// Creates and returns a new string Column.
+    pub fn string(name: &str) -> Self {
+        Self::new(name, ColumnType::Str)
+    }
+
+    /// Creates and returns a new date Column.
+    pub fn date(name: &str) -> Self {
+        Self::new(name, ColumnType::Date)
+    }
+
+    /// Sets the token written for missing values in this column.
+    pub fn with_missing(mut self, missing: MissingValue) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    /// Sets the number of decimal places written for float values.
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Sets the strftime-style format written for date values.
+    pub fn with_date_format(mut self, date_format: &str) -> Self {
+        self.date_format = date_format.into();
+        self
+    }
+
+    /// Sets the ID and name of the trait the column was generated from. The
+    /// trait is coded by its ID unless it is given a code.
+    pub fn with_trait(mut self, trait_id: i32, label: &str) -> Self {
+        self.trait_id = Some(trait_id);
+        self.code = Some(trait_id.to_string());
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Sets the code of the trait the column was generated from, such as its
+    /// code in a clinical terminology.
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Sets the names of the column's trait and its ancestors, starting at the root.
+    pub fn with_trait_path(mut self, trait_path: Vec<String>) -> Self {
+        self.trait_path = trait_path;
+        self
+    }
+
+    /// Sets the units of the column's values, such as `years`.
+    pub fn with_units(mut self, units: &str) -> Self {
+        self.units = Some(units.into());
+        self
+    }
+
+    /// Returns the name of the column.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the type of the column.
+    pub fn column_type(&self) -> ColumnType {
+        self.column_type
+    }
+
+    /// Returns the strftime-style format written for date values.
+    pub fn date_format(&self) -> &str {
+        &self.date_format
+    }
+
+    /// Returns the missing-value token of the column.
+    pub fn missing(&self) -> MissingValue {
+        self.missing
+    }
+
+    /// Returns the ID of the trait the column was generated from, if any.
+    pub fn trait_id(&self) -> Option<i32> {
+        self.trait_id
+    }
+
+    /// Returns the code of the trait the column was generated from, if any.
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    /// Returns the name of the trait the column was generated from, if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Returns the names of the column's trait and its ancestors.
+    pub fn trait_path(&self) -> &[String] {
+        &self.trait_path
+    }
+
+    /// Returns the units of the column's values, if any.
+    pub fn units(&self) -> Option<&str> {
+        self.units.as_deref()
+    }
+
+    /// Returns the value used when a row has no entry for this column.
+    /// Binary columns default to false, since an absent trait is not present.
+    pub fn default_value(&self) -> Value {
+        match self.column_type {
+            ColumnType::Binary => Value::Bool(false),
+            _ => Value::Missing,
+        }
+    }
+
+    /// Formats a value as text for this column.
+    pub fn format(&self, value: &Value) -> String {
+        match value {
+            Value::Missing => self.missing.token().into(),
+            Value::Bool(b) => match (self.column_type, b) {
+                (ColumnType::Str, _) => b.to_string(),
+                (_, true) => "1".into(),
+                (_, false) => "0".into(),
+            },
+            Value::Int(i) => match (self.column_type, self.precision) {
+                (ColumnType::Float, Some(precision)) => {
+                    format!("{:.*}", precision, *i as f64)
+                }
+                _ => i.to_string(),
+            },
+            Value::Float(f) if !f.is_finite() => self.missing.token().into(),
+            Value::Float(f) => match self.precision {
+                Some(precision) => format!("{:.*}", precision, f),
+                None => f.to_string(),
+            },
+            Value::Str(s) => s.clone(),
+            Value::Date(d) => d.format(&self.date_format).to_string(),
+        }
+    }
+
+    /// Converts a value to JSON for this column. Missing values are `null` unless
+    /// the column writes a different token.
+    pub fn to_json(&self, value: &Value) -> serde_json::Value {
+        match value {
+            Value::Missing => match self.missing {
+                MissingValue::Null => serde_json::Value::Null,
+                missing => missing.token().into(),
+            },
+            Value::Bool(b) => (*b).into(),
+            Value::Int(i) => (*i).into(),
+            Value::Float(f) => match self.precision {
+                Some(_) => self
+                    .format(value)
+                    .parse::<f64>()
+                    .map(|f| f.into())
+                    .unwrap_or(serde_json::Value::Null),
+                None => serde_json::Number::from_f64(*f)
+                    .map(serde_json::Value::Number)
+                    .unwrap_or(serde_json::Value::Null),
+            },
+            Value::Str(_) | Value::Date(_) => self.format(value).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn format_values() {
+        let bmi = Column::float("bmi").with_precision(1);
+        assert_eq!(bmi.format(&Value::Float(22.456)), "22.5");
+        assert_eq!(bmi.format(&Value::Missing), "NULL");
+
+        let ward = Column::string("ward").with_missing(MissingValue::Na);
+        assert_eq!(ward.format(&Value::Missing), "NA");
+
+        let admitted = Column::date("admitted").with_date_format("%d/%m/%Y");
+        let date = NaiveDate::from_ymd_opt(2020, 12, 25).unwrap();
+        assert_eq!(admitted.format(&Value::Date(date)), "25/12/2020");
+    }
+}
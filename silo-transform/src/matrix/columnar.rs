@@ -0,0 +1,252 @@
+use super::{Column, ColumnType, MatrixTransformer, MatrixTransformerRow, Value};
+use arrow::array::{
+    ArrayRef, BooleanBuilder, Date32Builder, Float64Builder, Int64Builder, StringBuilder,
+};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use chrono::NaiveDate;
+use parquet::arrow::ArrowWriter;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+impl MatrixTransformer {
+    /// Writes the rows as an Arrow IPC file (Feather v2).
+    pub(super) fn write_arrow_ipc(&self, rows: &[MatrixTransformerRow]) -> Result<Vec<u8>, Error> {
+        let batch = self.record_batch(rows)?;
+
+        let mut writer = FileWriter::try_new(vec![], &batch.schema()).map_err(arrow_error)?;
+        writer.write(&batch).map_err(arrow_error)?;
+
+        writer.into_inner().map_err(arrow_error)
+    }
+
+    /// Writes the rows as a Parquet file.
+    pub(super) fn write_parquet(&self, rows: &[MatrixTransformerRow]) -> Result<Vec<u8>, Error> {
+        let batch = self.record_batch(rows)?;
+
+        let mut writer = ArrowWriter::try_new(vec![], batch.schema(), None).map_err(arrow_error)?;
+        writer.write(&batch).map_err(arrow_error)?;
+
+        writer.into_inner().map_err(arrow_error)
+    }
+
+    /// Converts the rows to a single Arrow record batch with one typed array per
+    /// column. Missing values are nulls.
+    fn record_batch(&self, rows: &[MatrixTransformerRow]) -> Result<RecordBatch, Error> {
+        let fields: Vec<Field> = self
+            .columns
+            .iter()
+            .map(|column| Field::new(column.name(), data_type(column), true))
+            .collect();
+
+        let arrays = self
+            .columns
+            .iter()
+            .map(|column| {
+                let values: Vec<Value> = rows.iter().map(|row| self.value(row, column)).collect();
+                array(column, &values)
+            })
+            .collect::<Result<Vec<ArrayRef>, Error>>()?;
+
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).map_err(arrow_error)
+    }
+}
+
+/// Returns the Arrow type of a column.
+fn data_type(column: &Column) -> DataType {
+    match column.column_type() {
+        ColumnType::Binary => DataType::Boolean,
+        ColumnType::Int => DataType::Int64,
+        ColumnType::Float => DataType::Float64,
+        ColumnType::Str => DataType::Utf8,
+        ColumnType::Date => DataType::Date32,
+    }
+}
+
+/// Builds the Arrow array of a column from its values.
+fn array(column: &Column, values: &[Value]) -> Result<ArrayRef, Error> {
+    let mismatch = |value: &Value| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("column {} cannot hold {:?}", column.name(), value),
+        )
+    };
+
+    let array: ArrayRef = match column.column_type() {
+        ColumnType::Binary => {
+            let mut builder = BooleanBuilder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Value::Missing => builder.append_null(),
+                    Value::Bool(b) => builder.append_value(*b),
+                    Value::Int(i) => builder.append_value(*i != 0),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ColumnType::Int => {
+            let mut builder = Int64Builder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Value::Missing => builder.append_null(),
+                    Value::Bool(b) => builder.append_value(*b as i64),
+                    Value::Int(i) => builder.append_value(*i),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ColumnType::Float => {
+            let mut builder = Float64Builder::with_capacity(values.len());
+            for value in values {
+                match value.as_f64() {
+                    Some(f) => builder.append_value(f),
+                    None if value.is_missing() => builder.append_null(),
+                    None => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ColumnType::Str => {
+            let mut builder = StringBuilder::new();
+            for value in values {
+                match value {
+                    Value::Missing => builder.append_null(),
+                    value => builder.append_value(column.format(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ColumnType::Date => {
+            let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+            let mut builder = Date32Builder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Value::Missing => builder.append_null(),
+                    Value::Date(d) => builder.append_value((*d - epoch).num_days() as i32),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+    };
+
+    Ok(array)
+}
+
+/// Converts an Arrow or Parquet error into an IO error.
+fn arrow_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::new(ErrorKind::Other, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use arrow::array::{Array, BooleanArray, Float64Array, Int64Array, StringArray};
+    use arrow::datatypes::DataType;
+    use arrow::ipc::reader::FileReader;
+    use bytes::Bytes;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::io::Cursor;
+
+    #[test]
+    fn arrow_ipc_matrix() {
+        let transformer = MatrixTransformerBuilder::new()
+            .with_int_field("age")
+            .with_binary_field("asthma")
+            .output_as(MatrixOutputType::ArrowIpc)
+            .build();
+
+        let rows = vec![
+            MatrixTransformerRow::new().with_value("asthma", true),
+            MatrixTransformerRow::new().with_value("age", 24),
+        ];
+
+        let files = transformer.generate_files(rows).unwrap();
+        let mut reader = FileReader::try_new(Cursor::new(files[0].contents.clone()), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+
+        let age = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert!(age.is_null(0));
+        assert_eq!(age.value(1), 24);
+
+        let asthma = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert_eq!(asthma.value(0), true);
+        assert_eq!(asthma.value(1), false);
+    }
+
+    #[test]
+    fn parquet_matrix() {
+        let transformer = MatrixTransformerBuilder::new()
+            .with_int_field("age")
+            .with_float_field("bmi")
+            .with_string_field("sex")
+            .output_as(MatrixOutputType::Parquet)
+            .build();
+
+        let rows = vec![
+            MatrixTransformerRow::new()
+                .with_value("age", 24)
+                .with_value("sex", "female"),
+            MatrixTransformerRow::new().with_value("bmi", 22.5),
+        ];
+
+        let files = transformer.generate_files(rows).unwrap();
+        let mut reader =
+            ParquetRecordBatchReaderBuilder::try_new(Bytes::from(files[0].contents.clone()))
+                .unwrap()
+                .build()
+                .unwrap();
+        let batch = reader.next().unwrap().unwrap();
+
+        let schema = batch.schema();
+        let fields: Vec<(&str, &DataType)> = schema
+            .fields()
+            .iter()
+            .map(|field| (field.name().as_str(), field.data_type()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("age", &DataType::Int64),
+                ("bmi", &DataType::Float64),
+                ("sex", &DataType::Utf8),
+            ]
+        );
+        assert_eq!(batch.num_rows(), 2);
+
+        let age = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(age.value(0), 24);
+        assert!(age.is_null(1));
+
+        let bmi = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert!(bmi.is_null(0));
+        assert_eq!(bmi.value(1), 22.5);
+
+        let sex = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(sex.value(0), "female");
+        assert!(sex.is_null(1));
+    }
+}
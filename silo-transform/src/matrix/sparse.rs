@@ -0,0 +1,279 @@
+use super::{Column, ColumnType, MatrixOutputType, MatrixTransformer, MatrixTransformerRow};
+use std::io::{Error, ErrorKind};
+
+/// The token written for a missing value, which every reader of the sparse
+/// formats parses as NaN.
+const MISSING: &str = "nan";
+
+/// The non-zero and missing cells of a single row as pairs of zero-based column
+/// indices and formatted values.
+type SparseRow = Vec<(usize, String)>;
+
+impl MatrixTransformer {
+    /// Returns the non-zero cells of each row for the given columns. Cells whose
+    /// formatted value is zero are left out, so that a value rounded to zero by
+    /// the precision of its column isn't written as an explicit zero. Missing
+    /// values are written as NaN rather than left out, as a cell that is left
+    /// out reads as zero. Non-numeric values are an error.
+    fn sparse_rows(
+        &self,
+        rows: &[MatrixTransformerRow],
+        columns: &[&Column],
+    ) -> Result<Vec<SparseRow>, Error> {
+        rows.iter()
+            .map(|row| {
+                let mut cells = vec![];
+
+                for (index, column) in columns.iter().enumerate() {
+                    let value = self.value(row, column);
+                    match value.as_f64() {
+                        Some(v) if !v.is_finite() => cells.push((index, MISSING.into())),
+                        Some(_) => {
+                            let formatted = column.format(&value);
+                            if formatted.parse::<f64>() != Ok(0.0) {
+                                cells.push((index, formatted));
+                            }
+                        }
+                        None if value.is_missing() => cells.push((index, MISSING.into())),
+                        None => {
+                            let message = format!("column {} is not numeric", column.name());
+                            return Err(Error::new(ErrorKind::InvalidData, message));
+                        }
+                    }
+                }
+
+                Ok(cells)
+            })
+            .collect()
+    }
+
+    /// Appends the rows in Matrix Market coordinate format, with one-based indices.
+    pub(super) fn append_matrix_market(
+        &self,
+        builder: &mut String,
+        rows: &[MatrixTransformerRow],
+    ) -> Result<(), Error> {
+        let columns: Vec<&Column> = self.columns.iter().collect();
+        let sparse_rows = self.sparse_rows(rows, &columns)?;
+
+        // Only real matrices can hold the NaN of a missing value.
+        let integer = columns.iter().all(|column| match column.column_type() {
+            ColumnType::Binary | ColumnType::Int => true,
+            _ => false,
+        }) && sparse_rows
+            .iter()
+            .flatten()
+            .all(|(_, value)| value != MISSING);
+        let entries: usize = sparse_rows.iter().map(|cells| cells.len()).sum();
+
+        builder.push_str(&format!(
+            "%%MatrixMarket matrix coordinate {} general\n",
+            if integer { "integer" } else { "real" }
+        ));
+        builder.push_str(&format!("{} {} {}\n", rows.len(), columns.len(), entries));
+
+        for (row_index, cells) in sparse_rows.iter().enumerate() {
+            for (column_index, value) in cells {
+                builder.push_str(&format!(
+                    "{} {} {}\n",
+                    row_index + 1,
+                    column_index + 1,
+                    value
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Appends the rows in LIBSVM/SVMlight format, with the label column first and
+    /// one-based indices for every other column.
+    pub(super) fn append_libsvm(
+        &self,
+        builder: &mut String,
+        rows: &[MatrixTransformerRow],
+        label: &str,
+    ) -> Result<(), Error> {
+        let label_column = self
+            .columns
+            .iter()
+            .find(|column| column.name() == label)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("label column {} is not in the matrix", label),
+                )
+            })?;
+
+        let columns = self.feature_columns();
+        let sparse_rows = self.sparse_rows(rows, &columns)?;
+
+        for (row, cells) in rows.iter().zip(sparse_rows.iter()) {
+            let value = self.value(row, label_column);
+            if !matches!(value.as_f64(), Some(v) if v.is_finite()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("row {} has no numeric label", row.id().unwrap_or("")),
+                ));
+            }
+
+            builder.push_str(&label_column.format(&value));
+            for (column_index, value) in cells {
+                builder.push_str(&format!(" {}:{}", column_index + 1, value));
+            }
+            builder.push_str("\n");
+        }
+
+        Ok(())
+    }
+
+    /// Appends the rows as tab-separated (row, column, value) triples with
+    /// zero-based indices, ready for `scipy.sparse.coo_matrix`.
+    pub(super) fn append_coo(
+        &self,
+        builder: &mut String,
+        rows: &[MatrixTransformerRow],
+    ) -> Result<(), Error> {
+        let columns: Vec<&Column> = self.columns.iter().collect();
+        let sparse_rows = self.sparse_rows(rows, &columns)?;
+
+        if self.with_header {
+            builder.push_str("row\tcolumn\tvalue\n");
+        }
+
+        for (row_index, cells) in sparse_rows.iter().enumerate() {
+            for (column_index, value) in cells {
+                builder.push_str(&format!("{}\t{}\t{}\n", row_index, column_index, value));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the columns written as features, which leaves out the LIBSVM label.
+    pub(super) fn feature_columns(&self) -> Vec<&Column> {
+        self.columns
+            .iter()
+            .filter(|column| match &self.output_type {
+                MatrixOutputType::Libsvm { label } => column.name() != label,
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+/// Returns the ID of every row, one per line. Rows without an ID use their
+/// one-based row number.
+pub(super) fn row_ids(rows: &[MatrixTransformerRow]) -> String {
+    rows.iter()
+        .enumerate()
+        .map(|(index, row)| match row.id() {
+            Some(id) => format!("{}\n", id),
+            None => format!("{}\n", index + 1),
+        })
+        .collect()
+}
+
+/// Returns the name of every column, one per line.
+pub(super) fn column_names(columns: &[&Column]) -> String {
+    columns
+        .iter()
+        .map(|column| format!("{}\n", column.name()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn rows() -> Vec<MatrixTransformerRow> {
+        vec![
+            MatrixTransformerRow::new()
+                .with_id(7)
+                .with_value("age", 24)
+                .with_value("asthma", true),
+            MatrixTransformerRow::new()
+                .with_id(9)
+                .with_value("age", 0)
+                .with_value("copd", true),
+        ]
+    }
+
+    fn builder() -> MatrixTransformerBuilder {
+        MatrixTransformerBuilder::new()
+            .with_int_field("age")
+            .with_binary_field("asthma")
+            .with_binary_field("copd")
+    }
+
+    #[test]
+    fn matrix_market_files() {
+        let transformer = builder().output_as(MatrixOutputType::MatrixMarket).build();
+
+        let files = transformer.generate_files(rows()).unwrap();
+        assert_eq!(
+            String::from_utf8(files[0].contents.clone()).unwrap(),
+            "%%MatrixMarket matrix coordinate integer general\n2 3 3\n1 1 24\n1 2 1\n2 3 1\n"
+        );
+        assert_eq!(files[1].kind, MatrixFileKind::RowIds);
+        assert_eq!(
+            String::from_utf8(files[1].contents.clone()).unwrap(),
+            "7\n9\n"
+        );
+        assert_eq!(
+            String::from_utf8(files[2].contents.clone()).unwrap(),
+            "age\nasthma\ncopd\n"
+        );
+    }
+
+    #[test]
+    fn libsvm_matrix() {
+        let transformer = builder()
+            .output_as(MatrixOutputType::Libsvm {
+                label: "asthma".into(),
+            })
+            .build();
+
+        let files = transformer.generate_files(rows()).unwrap();
+        assert_eq!(
+            String::from_utf8(files[0].contents.clone()).unwrap(),
+            "1 1:24\n0 2:1\n"
+        );
+        assert_eq!(
+            String::from_utf8(files[2].contents.clone()).unwrap(),
+            "age\ncopd\n"
+        );
+    }
+
+    #[test]
+    fn missing_and_rounded_cells() {
+        let transformer = builder()
+            .with_column(Column::float("fev1").with_precision(1))
+            .output_as(MatrixOutputType::MatrixMarket)
+            .build();
+        let rows = vec![
+            MatrixTransformerRow::new()
+                .with_id(7)
+                .with_value("age", 24)
+                .with_value("fev1", 0.01),
+            MatrixTransformerRow::new()
+                .with_id(9)
+                .with_value("age", Value::Missing)
+                .with_value("fev1", 2.5),
+        ];
+
+        let output = transformer.generate(rows).unwrap();
+        assert_eq!(
+            output,
+            "%%MatrixMarket matrix coordinate real general\n2 4 3\n1 1 24\n2 1 nan\n2 4 2.5\n"
+        );
+    }
+
+    #[test]
+    fn coo_matrix() {
+        let transformer = builder().output_as(MatrixOutputType::Coo).build();
+
+        let output = transformer.generate(rows()).unwrap();
+        assert_eq!(output, "0\t0\t24\n0\t1\t1\n1\t2\t1\n");
+    }
+}
@@ -0,0 +1,92 @@
+use chrono::NaiveDate;
+
+/// A single typed value in a matrix cell.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// An explicitly missing value, written with the column's missing-value token.
+    Missing,
+    /// A true or false value.
+    Bool(bool),
+    /// An integer value.
+    Int(i64),
+    /// A floating point value.
+    Float(f64),
+    /// A string value.
+    Str(String),
+    /// A calendar date.
+    Date(NaiveDate),
+}
+
+impl Value {
+    /// Returns whether or not the value is missing.
+    pub fn is_missing(&self) -> bool {
+        matches!(self, Value::Missing)
+    }
+
+    /// Returns the value as a float if it is numeric. Bools are 1.0 or 0.0.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(item: bool) -> Self {
+        Value::Bool(item)
+    }
+}
+
+impl From<i16> for Value {
+    fn from(item: i16) -> Self {
+        Value::Int(item.into())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(item: i32) -> Self {
+        Value::Int(item.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(item: i64) -> Self {
+        Value::Int(item)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(item: f64) -> Self {
+        Value::Float(item)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(item: &str) -> Self {
+        Value::Str(item.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(item: String) -> Self {
+        Value::Str(item)
+    }
+}
+
+impl From<NaiveDate> for Value {
+    fn from(item: NaiveDate) -> Self {
+        Value::Date(item)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(item: Option<T>) -> Self {
+        match item {
+            Some(value) => value.into(),
+            None => Value::Missing,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use hmac::{Hmac, Mac, NewMac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// The number of bytes in a token, before it is hex encoded.
+const TOKEN_BYTES: usize = 16;
+
+/// How identifiers are replaced with tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PseudonymMethod {
+    /// Replaces identifiers with a keyed HMAC-SHA256 of them, so the same
+    /// identifier gets the same token in every export made with the same key.
+    Hmac,
+    /// Replaces identifiers with random tokens, so tokens can't be linked
+    /// between exports.
+    Random,
+}
+
+impl PseudonymMethod {
+    /// Returns the name of the method.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PseudonymMethod::Hmac => "hmac",
+            PseudonymMethod::Random => "random",
+        }
+    }
+}
+
+impl FromStr for PseudonymMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hmac" => Ok(PseudonymMethod::Hmac),
+            "random" => Ok(PseudonymMethod::Random),
+            _ => Err(format!("unknown pseudonymisation method {}", s)),
+        }
+    }
+}
+
+/// Replaces identifiers with tokens for an export, remembering the token of
+/// every identifier so the export can be re-identified later.
+pub struct Pseudonymiser {
+    method: PseudonymMethod,
+    key: Vec<u8>,
+    tokens: HashMap<String, String>,
+    used: HashSet<String>,
+}
+
+impl Pseudonymiser {
+    /// Creates and returns a Pseudonymiser that derives tokens from a secret key.
+    pub fn hmac(key: &[u8]) -> Self {
+        Self::new(PseudonymMethod::Hmac, key.to_vec())
+    }
+
+    /// Creates and returns a Pseudonymiser that draws a new random token for
+    /// every identifier.
+    pub fn random() -> Self {
+        Self::new(PseudonymMethod::Random, vec![])
+    }
+
+    fn new(method: PseudonymMethod, key: Vec<u8>) -> Self {
+        Self {
+            method,
+            key,
+            tokens: HashMap::new(),
+            used: HashSet::new(),
+        }
+    }
+
+    /// Returns the method of the Pseudonymiser.
+    pub fn method(&self) -> PseudonymMethod {
+        self.method
+    }
+
+    /// Returns the token of an identifier, the same one every time it is given.
+    pub fn token(&mut self, id: &str) -> String {
+        if let Some(token) = self.tokens.get(id) {
+            return token.clone();
+        }
+
+        let token = match self.method {
+            PseudonymMethod::Hmac => {
+                // HMAC accepts keys of any length.
+                let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+                mac.update(id.as_bytes());
+                hex::encode(&mac.finalize().into_bytes()[..TOKEN_BYTES])
+            }
+            PseudonymMethod::Random => loop {
+                let mut bytes = [0u8; TOKEN_BYTES];
+                rand::thread_rng().fill_bytes(&mut bytes);
+
+                let token = hex::encode(bytes);
+                if !self.used.contains(&token) {
+                    break token;
+                }
+            },
+        };
+
+        self.used.insert(token.clone());
+        self.tokens.insert(id.into(), token.clone());
+        token
+    }
+
+    /// Returns the token of every identifier given so far, by identifier.
+    pub fn mapping(&self) -> &HashMap<String, String> {
+        &self.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_tokens() {
+        let mut a = Pseudonymiser::hmac(b"secret");
+        let mut b = Pseudonymiser::hmac(b"secret");
+        let mut c = Pseudonymiser::hmac(b"other");
+
+        let token = a.token("42");
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+        assert_eq!(a.token("42"), token);
+        assert_eq!(b.token("42"), token);
+        assert_ne!(c.token("42"), token);
+        assert_ne!(a.token("43"), token);
+        assert_eq!(a.mapping().len(), 2);
+    }
+
+    #[test]
+    fn random_tokens() {
+        let mut a = Pseudonymiser::random();
+        let mut b = Pseudonymiser::random();
+
+        let token = a.token("42");
+        assert_eq!(a.token("42"), token);
+        assert_ne!(b.token("42"), token);
+    }
+}
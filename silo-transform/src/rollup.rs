@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+
+/// Specifies the level of the trait tree that binary fields are rolled up to.
+#[derive(Debug, Clone)]
+pub enum RollupLevel {
+    /// Rolls traits up to their ancestor at a given depth. Root traits are at depth 1.
+    Depth(usize),
+    /// Rolls traits up to the depth of the named trait.
+    To(String),
+}
+
+impl RollupLevel {
+    /// Resolves the level to a depth in the given tree, if possible.
+    pub fn depth(&self, tree: &TraitTree) -> Option<usize> {
+        match self {
+            RollupLevel::Depth(0) => None,
+            RollupLevel::Depth(depth) => Some(*depth),
+            RollupLevel::To(name) => tree.depth(name),
+        }
+    }
+}
+
+/// A tree of traits keyed by name, used to find the ancestors of a trait.
+#[derive(Debug, Clone, Default)]
+pub struct TraitTree {
+    parents: HashMap<String, Option<String>>,
+    ids: HashMap<String, i32>,
+}
+
+impl TraitTree {
+    /// Creates and returns an empty TraitTree.
+    pub fn new() -> Self {
+        Self {
+            parents: HashMap::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Adds a trait to the tree with the name of its parent, or None for a root trait.
+    pub fn with_trait(mut self, name: &str, parent: Option<&str>) -> Self {
+        self.insert(name, parent);
+        self
+    }
+
+    /// Adds a trait to the tree with the name of its parent, or None for a root trait.
+    pub fn insert(&mut self, name: &str, parent: Option<&str>) {
+        self.parents
+            .insert(name.into(), parent.map(|parent| parent.into()));
+    }
+
+    /// Sets the ID of a trait in the tree.
+    pub fn set_id(&mut self, name: &str, id: i32) {
+        self.ids.insert(name.into(), id);
+    }
+
+    /// Returns the ID of a trait, if it has been set.
+    pub fn id(&self, name: &str) -> Option<i32> {
+        self.ids.get(name).copied()
+    }
+
+    /// Returns whether or not the tree contains a trait.
+    pub fn contains(&self, name: &str) -> bool {
+        self.parents.contains_key(name)
+    }
+
+    /// Returns the names of a trait's ancestors, starting at the root and ending
+    /// with the trait itself. Returns an empty vector for unknown traits.
+    pub fn path(&self, name: &str) -> Vec<String> {
+        let mut path: Vec<String> = vec![];
+        let mut seen = HashSet::new();
+        let mut current = Some(name.to_string());
+
+        while let Some(name) = current {
+            // Guard against cycles in malformed trees.
+            if !self.contains(&name) || !seen.insert(name.clone()) {
+                break;
+            }
+
+            current = self.parents.get(&name).cloned().flatten();
+            path.push(name);
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Returns the depth of a trait, where root traits are at depth 1.
+    pub fn depth(&self, name: &str) -> Option<usize> {
+        match self.path(name).len() {
+            0 => None,
+            depth => Some(depth),
+        }
+    }
+
+    /// Returns the ancestor of a trait at a given depth. A trait at that depth
+    /// is its own ancestor, and traits above that depth have no ancestor.
+    pub fn ancestor_at(&self, name: &str, depth: usize) -> Option<String> {
+        if depth == 0 {
+            return None;
+        }
+
+        self.path(name).into_iter().nth(depth - 1)
+    }
+
+    /// Returns the names of every trait at a given depth, sorted by name.
+    pub fn traits_at_depth(&self, depth: usize) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .parents
+            .keys()
+            .filter(|name| self.depth(name) == Some(depth))
+            .cloned()
+            .collect();
+
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> TraitTree {
+        TraitTree::new()
+            .with_trait("disease", None)
+            .with_trait("respiratory_disease", Some("disease"))
+            .with_trait("asthma", Some("respiratory_disease"))
+            .with_trait("copd", Some("respiratory_disease"))
+            .with_trait("cardiac_disease", Some("disease"))
+            .with_trait("symptom", None)
+    }
+
+    #[test]
+    fn ancestor_at_depth() {
+        let tree = tree();
+
+        assert_eq!(tree.depth("asthma"), Some(3));
+        assert_eq!(
+            tree.ancestor_at("asthma", 2),
+            Some("respiratory_disease".into())
+        );
+        assert_eq!(tree.ancestor_at("symptom", 2), None);
+        assert_eq!(tree.ancestor_at("unknown", 1), None);
+    }
+
+    #[test]
+    fn resolve_rollup_level() {
+        let tree = tree();
+
+        assert_eq!(
+            RollupLevel::To("respiratory_disease".into()).depth(&tree),
+            Some(2)
+        );
+        assert_eq!(
+            tree.traits_at_depth(2),
+            vec!["cardiac_disease", "respiratory_disease"]
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Subcommands of the silo CLI other than serving the REST API.
+
+use clap::ArgMatches;
+use silo_core::models::{Subject, SubjectTraitLink};
+use silo_db::service::Service as DbServiceTrait;
+
+mod compare;
+mod duplicates;
+mod stats;
+pub use compare::compare;
+pub use duplicates::duplicates;
+pub use stats::stats;
+
+/// Parses the value of an argument, if it was given.
+fn parse_arg<T: std::str::FromStr>(args: &ArgMatches, name: &str) -> Result<Option<T>, String> {
+    match args.value_of(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid value for {}: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+/// Fetches the subjects of a group and their trait links, failing if the group
+/// doesn't exist.
+async fn group_subjects(
+    db_service: &dyn DbServiceTrait,
+    id: i32,
+) -> Result<(Vec<Subject>, Vec<SubjectTraitLink>), String> {
+    let db_error = |e| format!("{}", e);
+    if db_service
+        .find_group_by_id(id)
+        .await
+        .map_err(db_error)?
+        .is_none()
+    {
+        return Err(format!("group {} does not exist", id));
+    }
+
+    let subjects = db_service
+        .find_subjects_by_group_id(id)
+        .await
+        .map_err(db_error)?;
+    let links = db_service
+        .find_subject_trait_links_by_group_id(id)
+        .await
+        .map_err(db_error)?;
+
+    Ok((subjects, links))
+}
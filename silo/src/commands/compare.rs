@@ -0,0 +1,123 @@
+use super::{group_subjects, parse_arg};
+use clap::ArgMatches;
+use silo_core::analysis::{
+    AssociationTest, AttributeTest, ComparisonOptions, GroupComparison, Sample,
+};
+use silo_db::service::Service as DbServiceTrait;
+use std::collections::HashSet;
+
+/// Prints the comparison of a case group and a control group.
+pub async fn compare(db_service: &dyn DbServiceTrait, args: &ArgMatches<'_>) -> Result<(), String> {
+    let case_id = parse_arg::<i32>(args, "CASE_GROUP_ID")?.ok_or("a case group ID is required")?;
+    let control_id =
+        parse_arg::<i32>(args, "CONTROL_GROUP_ID")?.ok_or("a control group ID is required")?;
+
+    let defaults = ComparisonOptions::default();
+    let options = ComparisonOptions {
+        trait_test: parse_arg::<AssociationTest>(args, "trait-test")?
+            .unwrap_or(defaults.trait_test),
+        attribute_test: parse_arg::<AttributeTest>(args, "attribute-test")?
+            .unwrap_or(defaults.attribute_test),
+    };
+
+    let (case_subjects, case_links) = group_subjects(db_service, case_id).await?;
+    let (control_subjects, control_links) = group_subjects(db_service, control_id).await?;
+
+    // Compare every trait held by a subject of either group.
+    let held: HashSet<i32> = case_links
+        .iter()
+        .chain(control_links.iter())
+        .map(|l| l.subject_trait_id)
+        .collect();
+    let traits: Vec<_> = db_service
+        .get_traits()
+        .await
+        .map_err(|e| format!("{}", e))?
+        .into_iter()
+        .filter(|t| held.contains(&t.id))
+        .collect();
+
+    let comparison = GroupComparison::new(
+        Sample {
+            group_id: case_id,
+            subjects: &case_subjects,
+            links: &case_links,
+        },
+        Sample {
+            group_id: control_id,
+            subjects: &control_subjects,
+            links: &control_links,
+        },
+        &traits,
+        &options,
+    );
+
+    print_comparison(&comparison);
+
+    Ok(())
+}
+
+/// Prints the comparison of two groups as plain text, with the most significant
+/// traits first.
+fn print_comparison(comparison: &GroupComparison) {
+    let number = |n: Option<f64>| match n {
+        Some(n) => format!("{:.4}", n),
+        None => "-".into(),
+    };
+
+    println!(
+        "Case group {} ({} subjects) vs control group {} ({} subjects)",
+        comparison.case_group_id,
+        comparison.case_subject_count,
+        comparison.control_group_id,
+        comparison.control_subject_count,
+    );
+
+    println!("\nAttributes");
+    println!(
+        "  {:<16} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "name", "case mean", "ctrl mean", "diff", "p", "p bonf", "p bh"
+    );
+    for a in &comparison.attributes {
+        println!(
+            "  {:<16} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            a.name,
+            number(a.case.mean),
+            number(a.control.mean),
+            number(a.difference),
+            number(a.p_value),
+            number(a.p_bonferroni),
+            number(a.p_benjamini_hochberg),
+        );
+    }
+
+    let mut traits: Vec<_> = comparison.traits.iter().collect();
+    traits.sort_by(|a, b| {
+        let (a, b) = (a.p_value.unwrap_or(1.0), b.p_value.unwrap_or(1.0));
+        a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    println!("\nTraits");
+    println!(
+        "  {:<32} {:>8} {:>8} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "name", "case", "control", "diff", "OR", "p", "p bonf", "p bh", "test"
+    );
+    for t in traits {
+        let test = match t.test {
+            AssociationTest::Fisher => "fisher",
+            _ => "chi_square",
+        };
+        println!(
+            "  {:<32} {:>7.1}% {:>7.1}% {:>8} {:>8.3} {:>10} {:>10} {:>10} {:>10}",
+            t.trait_name,
+            t.case_prevalence.unwrap_or(0.0) * 100.0,
+            t.control_prevalence.unwrap_or(0.0) * 100.0,
+            number(t.difference),
+            t.odds_ratio,
+            number(t.p_value),
+            number(t.p_bonferroni),
+            number(t.p_benjamini_hochberg),
+            test,
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use clap::ArgMatches;
+use silo_core::duplicates::find_duplicates;
+use silo_db::service::Service as DbServiceTrait;
+
+/// Prints the sets of subjects that share an external ID, across every group.
+pub async fn duplicates(
+    db_service: &dyn DbServiceTrait,
+    args: &ArgMatches<'_>,
+) -> Result<(), String> {
+    let external_ids = db_service
+        .get_external_ids()
+        .await
+        .map_err(|e| format!("{}", e))?;
+
+    let duplicates = find_duplicates(&external_ids, args.value_of("namespace"));
+    if duplicates.is_empty() {
+        println!("No duplicate subjects found");
+        return Ok(());
+    }
+
+    println!(
+        "  {:<16} {:<24} {:<24} {:<16}",
+        "namespace", "external id", "subjects", "groups"
+    );
+    for d in &duplicates {
+        let join = |ids: &[i32]| {
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        println!(
+            "  {:<16} {:<24} {:<24} {:<16}",
+            d.namespace,
+            d.value,
+            join(&d.subject_ids),
+            join(&d.group_ids)
+        );
+    }
+
+    Ok(())
+}
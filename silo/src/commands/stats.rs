@@ -0,0 +1,81 @@
+use super::{group_subjects, parse_arg};
+use clap::ArgMatches;
+use silo_core::stats::{Distribution, GroupStats, StatsOptions};
+use silo_db::service::Service as DbServiceTrait;
+
+/// Prints the descriptive statistics of a group.
+pub async fn stats(db_service: &dyn DbServiceTrait, args: &ArgMatches<'_>) -> Result<(), String> {
+    let id = parse_arg::<i32>(args, "GROUP_ID")?.ok_or("a group ID is required")?;
+
+    let defaults = StatsOptions::default();
+    let options = StatsOptions {
+        age_bin_width: parse_arg(args, "age-bin-width")?.unwrap_or(defaults.age_bin_width),
+        length_of_stay_bin_width: parse_arg(args, "length-of-stay-bin-width")?
+            .unwrap_or(defaults.length_of_stay_bin_width),
+    };
+
+    let (subjects, links) = group_subjects(db_service, id).await?;
+    let traits = db_service
+        .get_traits()
+        .await
+        .map_err(|e| format!("{}", e))?;
+    options.validate(&subjects)?;
+
+    print_stats(&GroupStats::new(id, &subjects, &links, &traits, &options));
+
+    Ok(())
+}
+
+/// Prints the statistics of a group as plain text.
+fn print_stats(stats: &GroupStats) {
+    println!("Group {}", stats.group_id);
+    println!("Subjects: {}", stats.subject_count);
+
+    print_distribution("Age (years)", &stats.age);
+    print_distribution("Length of stay (days)", &stats.length_of_stay);
+
+    println!("\nTraits");
+    for t in &stats.traits {
+        println!(
+            "  {:<32} {:>8} {:>7.1}%",
+            t.trait_name,
+            t.count,
+            t.prevalence * 100.0
+        );
+    }
+}
+
+/// Prints a distribution and its histogram as plain text.
+fn print_distribution(title: &str, d: &Distribution) {
+    let number = |n: Option<f64>| match n {
+        Some(n) => format!("{:.2}", n),
+        None => "-".into(),
+    };
+
+    println!("\n{}", title);
+    println!(
+        "  mean {}  median {}  IQR {} ({} to {})  range {} to {}",
+        number(d.mean),
+        number(d.median),
+        number(d.iqr),
+        number(d.q1),
+        number(d.q3),
+        number(d.min),
+        number(d.max),
+    );
+
+    let widest = d.histogram.iter().map(|bin| bin.count).max().unwrap_or(0);
+    for bin in &d.histogram {
+        let bar = match widest {
+            0 => 0,
+            widest => bin.count * 40 / widest,
+        };
+        println!(
+            "  [{:>6}, {:>6})  {:>8}  {}",
+            bin.lower,
+            bin.upper,
+            bin.count,
+            "#".repeat(bar)
+        );
+    }
+}
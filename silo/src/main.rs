@@ -4,38 +4,188 @@
 //! silo initializes the silo_core service and runs it.
 
 use actix::prelude::*;
+use clap::{App, AppSettings, Arg, SubCommand};
 use log::{error, info};
 use tokio::prelude::*;
 
-use silo_db::actor::*;
-use silo_db::config::DatabaseConfig;
+use silo_db::config::{ConnectRetry, DatabaseConfig, DEFAULT_POOL_SIZE};
 use silo_db::connection::Connection as DbConnection;
 use silo_db::service::{Service as DbServiceTrait, ServiceImpl};
+use std::env;
+use std::sync::Arc;
 use std::thread;
 
+use silo_core::logging::Logger;
 use silo_core::models::SubjectTrait;
 use silo_core::service::Service;
 use silo_http::api;
+use silo_transform::pseudonym::PseudonymMethod;
 
-/// Initializes the silo_core Service and runs it.
-#[tokio::main]
-async fn main() -> Result<(), String> {
-    let db_config = DatabaseConfig {
+mod commands;
+
+/// Returns the definition of the CLI's arguments and subcommands.
+fn cli() -> App<'static, 'static> {
+    App::new("silo")
+        .about("Stores groups of subjects and their traits")
+        .setting(AppSettings::VersionlessSubcommands)
+        .subcommand(SubCommand::with_name("serve").about("Runs the REST API (the default)"))
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Prints descriptive statistics of a group")
+                .arg(
+                    Arg::with_name("GROUP_ID")
+                        .help("The ID of the group")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("age-bin-width")
+                        .long("age-bin-width")
+                        .takes_value(true)
+                        .help("The width of each bin of the age histogram, in years"),
+                )
+                .arg(
+                    Arg::with_name("length-of-stay-bin-width")
+                        .long("length-of-stay-bin-width")
+                        .takes_value(true)
+                        .help("The width of each bin of the length of stay histogram, in days"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Compares a case group to a control group")
+                .arg(
+                    Arg::with_name("CASE_GROUP_ID")
+                        .help("The ID of the case group")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("CONTROL_GROUP_ID")
+                        .help("The ID of the control group")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("trait-test")
+                        .long("trait-test")
+                        .takes_value(true)
+                        .possible_values(&["auto", "chi_square", "fisher"])
+                        .help("The test of association of traits"),
+                )
+                .arg(
+                    Arg::with_name("attribute-test")
+                        .long("attribute-test")
+                        .takes_value(true)
+                        .possible_values(&["t_test", "mann_whitney"])
+                        .help("The test of attributes"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("duplicates")
+                .about("Finds subjects that share an external ID across groups")
+                .arg(
+                    Arg::with_name("namespace")
+                        .long("namespace")
+                        .takes_value(true)
+                        .help("Only compares external IDs of this namespace"),
+                ),
+        )
+}
+
+/// Reads the server-wide pseudonymisation policy from the environment:
+/// SILO_PSEUDONYMISE (required or optional, the default), SILO_PSEUDONYM_METHOD
+/// (hmac or random, the default) and SILO_PSEUDONYM_KEY.
+fn pseudonym_policy() -> Result<api::PseudonymPolicy, String> {
+    let defaults = api::PseudonymPolicy::default();
+
+    let required = match env::var("SILO_PSEUDONYMISE").as_deref() {
+        Ok("required") => true,
+        Ok("optional") | Err(_) => false,
+        Ok(value) => return Err(format!("invalid SILO_PSEUDONYMISE: {}", value)),
+    };
+    let default_method = match env::var("SILO_PSEUDONYM_METHOD") {
+        Ok(method) => method.parse()?,
+        Err(_) => defaults.default_method,
+    };
+    let hmac_key = env::var("SILO_PSEUDONYM_KEY").ok().map(String::into_bytes);
+    if required && default_method == PseudonymMethod::Hmac && hmac_key.is_none() {
+        return Err("hmac pseudonyms require SILO_PSEUDONYM_KEY".into());
+    }
+
+    Ok(api::PseudonymPolicy {
+        required,
+        default_method,
+        hmac_key,
+    })
+}
+
+/// Reads the database configuration from the environment: SILO_DB_POOL_SIZE
+/// (50 by default) limits the connections in the pool.
+fn db_config() -> Result<DatabaseConfig, String> {
+    let pool_size = match env::var("SILO_DB_POOL_SIZE") {
+        Ok(size) => match size.parse() {
+            Ok(size) if size > 0 => size,
+            _ => return Err(format!("invalid SILO_DB_POOL_SIZE: {}", size)),
+        },
+        Err(_) => DEFAULT_POOL_SIZE,
+    };
+
+    Ok(DatabaseConfig {
         database_host: String::from("localhost"),
         database_port: String::from("5432"),
         database_username: String::from("postgres"),
         database_password: String::from("postgres"),
         database_name: String::from("silo"),
+        pool_size,
+    })
+}
+
+/// Reads how connecting to the database is retried at startup from the
+/// environment: SILO_DB_CONNECT_ATTEMPTS (10 by default).
+fn connect_retry() -> Result<ConnectRetry, String> {
+    let defaults = ConnectRetry::default();
+
+    let attempts = match env::var("SILO_DB_CONNECT_ATTEMPTS") {
+        Ok(attempts) => match attempts.parse() {
+            Ok(attempts) if attempts > 0 => attempts,
+            _ => return Err(format!("invalid SILO_DB_CONNECT_ATTEMPTS: {}", attempts)),
+        },
+        Err(_) => defaults.attempts,
     };
 
-    let conn = Box::new(DbConnection::connect(&db_config).await.unwrap());
+    Ok(ConnectRetry {
+        attempts,
+        ..defaults
+    })
+}
+
+/// Initializes the silo_core Service and runs it.
+#[tokio::main]
+async fn main() -> Result<(), String> {
+    let matches = cli().get_matches();
+
+    Logger::from_env()?
+        .init()
+        .or_else(|e| Err(format!("failed to start logger: {}", e)))?;
+
+    let db_config = db_config()?;
+
+    let conn = match DbConnection::connect_with_retry(&db_config, &connect_retry()?).await {
+        Ok(conn) => Box::new(conn),
+        Err(e) => return Err(format!("failed to connect to db: {}", e)),
+    };
 
     conn.migrate().await.or_else(|e| {
-        println!("{}", e);
+        error!("{}", e);
         Err("failed to migrate db")
     })?;
 
-    let db_service = ServiceImpl::new(conn);
+    let db_service: Arc<dyn DbServiceTrait> = Arc::new(ServiceImpl::new(conn));
+
+    match matches.subcommand() {
+        ("stats", Some(args)) => return commands::stats(db_service.as_ref(), args).await,
+        ("compare", Some(args)) => return commands::compare(db_service.as_ref(), args).await,
+        ("duplicates", Some(args)) => return commands::duplicates(db_service.as_ref(), args).await,
+        _ => (),
+    }
 
     // Start the Actix system.
     // let service = Service::new();
@@ -44,11 +194,14 @@ async fn main() -> Result<(), String> {
     //     Err(e) => error!("Error starting service: {}", e),
     // };
 
-    let rest_service = api::RestService::new(Box::new(db_service));
-
-    api::build_and_serve_http(rest_service).await;
+    // Administration endpoints are disabled unless SILO_ADMIN_TOKEN is set.
+    let rest_service = api::RestService::new(db_service)
+        .with_pseudonym_policy(pseudonym_policy()?)
+        .with_admin_token(env::var("SILO_ADMIN_TOKEN").ok());
 
-    // let addr = DbActor::new(&db_service).start();
+    api::build_and_serve_http(rest_service)
+        .await
+        .or_else(|e| Err(format!("failed to serve http: {}", e)))?;
 
     Ok(())
 }